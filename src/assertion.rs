@@ -0,0 +1,141 @@
+use serde_yaml::Value;
+
+use crate::error::{AppError, AppResult};
+use crate::yaml_ops::{self, ValueType};
+
+// Two-character operators come first so they win ties with their prefixes.
+const OPERATORS: [&str; 6] = [">=", "<=", "==", "!=", ">", "<"];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Check {
+    Exists,
+    Type(ValueType),
+    Compare(&'static str, Value),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Assertion {
+    key: String,
+    check: Check,
+}
+
+impl Assertion {
+    /// Parses `key exists`, `key type==int` or `key OP value`, where OP is one
+    /// of `==`, `!=`, `>`, `<`, `>=`, `<=` and the value is read as YAML.
+    pub fn parse(expression: &str) -> AppResult<Self> {
+        let expression = expression.trim();
+
+        if let Some(key) = expression.strip_suffix(" exists") {
+            return Ok(Self::new(key, Check::Exists));
+        }
+
+        if let Some((key, value_type)) = expression.split_once(" type==") {
+            return Ok(Self::new(
+                key,
+                Check::Type(ValueType::parse(value_type.trim())?),
+            ));
+        }
+
+        let (index, operator) = OPERATORS
+            .iter()
+            .filter_map(|operator| expression.find(operator).map(|index| (index, *operator)))
+            .min_by_key(|(index, _)| *index)
+            .ok_or_else(|| AppError::cli(format!("Invalid assertion: {expression}")))?;
+        let value = serde_yaml::from_str(expression[index + operator.len()..].trim())
+            .map_err(|error| AppError::parse_yaml(format!("in assertion '{expression}'"), error))?;
+
+        Ok(Self::new(
+            &expression[..index],
+            Check::Compare(operator, value),
+        ))
+    }
+
+    fn new(key: &str, check: Check) -> Self {
+        Self {
+            key: key.trim().to_string(),
+            check,
+        }
+    }
+
+    /// Evaluates the assertion against `document`, describing the outcome.
+    pub fn evaluate(&self, document: &Value) -> AppResult<Result<String, String>> {
+        let Some(actual) = yaml_ops::get_value(document, &self.key)? else {
+            return Ok(Err(format!("'{}' does not exist", self.key)));
+        };
+        let shown = yaml_ops::format_inline_value(&actual);
+
+        let passed = match &self.check {
+            Check::Exists => true,
+            Check::Type(value_type) => value_type.matches(&actual),
+            Check::Compare("==", expected) => &actual == expected,
+            Check::Compare("!=", expected) => &actual != expected,
+            Check::Compare(operator, expected) => {
+                let (Some(actual), Some(expected)) = (actual.as_f64(), expected.as_f64()) else {
+                    return Ok(Err(format!(
+                        "'{}' is {shown}, which cannot be compared with {operator}",
+                        self.key
+                    )));
+                };
+                match *operator {
+                    ">" => actual > expected,
+                    "<" => actual < expected,
+                    ">=" => actual >= expected,
+                    _ => actual <= expected,
+                }
+            }
+        };
+
+        let message = format!("'{}' is {shown}", self.key);
+        Ok(if passed { Ok(message) } else { Err(message) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(yaml: &str, expression: &str) -> bool {
+        let document: Value = serde_yaml::from_str(yaml).unwrap();
+        Assertion::parse(expression)
+            .unwrap()
+            .evaluate(&document)
+            .unwrap()
+            .is_ok()
+    }
+
+    #[test]
+    fn test_equality_assertions_compare_typed_values() {
+        let yaml = "name: web\nreplicas: 3\ndebug: false\n";
+        assert!(check(yaml, "name==web"));
+        assert!(check(yaml, "replicas==3"));
+        assert!(!check(yaml, "replicas=='3'"));
+        assert!(check(yaml, "debug!=true"));
+        assert!(!check(yaml, "name!=web"));
+    }
+
+    #[test]
+    fn test_numeric_assertions() {
+        let yaml = "replicas: 3\nratio: 0.5\nname: web\n";
+        assert!(check(yaml, "replicas>=3"));
+        assert!(!check(yaml, "replicas>3"));
+        assert!(check(yaml, "replicas<4"));
+        assert!(check(yaml, "ratio<=0.5"));
+        assert!(!check(yaml, "name>1"));
+    }
+
+    #[test]
+    fn test_existence_and_type_assertions() {
+        let yaml = "database:\n  port: 5432\n";
+        assert!(check(yaml, "database.port exists"));
+        assert!(!check(yaml, "database.host exists"));
+        assert!(!check(yaml, "database.host==db"));
+        assert!(check(yaml, "database.port type==int"));
+        assert!(!check(yaml, "database type==string"));
+    }
+
+    #[test]
+    fn test_parse_rejects_expressions_without_operator() {
+        assert!(Assertion::parse("replicas").is_err());
+        assert!(Assertion::parse("replicas type==integer").is_err());
+    }
+}
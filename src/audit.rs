@@ -0,0 +1,47 @@
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+/// One `audit: {json}` line describing who changed which keys of `file`, and when.
+pub fn record(user: &str, timestamp: u64, command: &str, file: &str, keys: &[String]) -> String {
+    let record = json!({
+        "user": user,
+        "timestamp": timestamp,
+        "command": command,
+        "file": file,
+        "keys": keys,
+    });
+    format!("audit: {record}")
+}
+
+/// Writes the audit record for a mutation of `file` to stderr.
+pub fn emit(command: &str, file: &str, keys: &[String]) {
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    eprintln!("{}", record(&user, timestamp, command, file, keys));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_lists_changed_keys() {
+        let keys = vec!["db.host".to_string(), "ports[1]".to_string()];
+        let line = record("ops", 1700000000, "set", "app.yaml", &keys);
+
+        let json: serde_json::Value =
+            serde_json::from_str(line.strip_prefix("audit: ").unwrap()).unwrap();
+        assert_eq!(json["user"], "ops");
+        assert_eq!(json["timestamp"], 1700000000);
+        assert_eq!(json["command"], "set");
+        assert_eq!(json["file"], "app.yaml");
+        assert_eq!(json["keys"], json!(["db.host", "ports[1]"]));
+    }
+}
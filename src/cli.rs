@@ -1,33 +1,189 @@
+use std::env;
+
 use clap::{Parser, Subcommand};
 
+use crate::assertion::Assertion;
 use crate::error::{AppError, AppResult};
+use crate::yaml_ops::{
+    ChangeKind, InputFormat, KeyStyle, MatchOptions, MatchTarget, MergeStrategy, TransferOptions,
+    Transform, ValueType,
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GrepOptions {
+    pub full: bool,
+    pub format_template: Option<String>,
+    pub stdin_format: InputFormat,
+    pub distinct_values: bool,
+    pub matching: MatchOptions,
+    pub json: bool,
+    pub with_position: bool,
+    pub depth_histogram: bool,
+    pub max_results: Option<usize>,
+    pub files_from_stdin: bool,
+    pub csv: bool,
+    pub include_hidden: bool,
+    pub recursive: bool,
+    pub max_file_size: Option<u64>,
+    /// Number of files searched at once; defaults to the available cores.
+    pub jobs: Option<usize>,
+    pub per_file_yaml: bool,
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    pub count: bool,
+    pub show_match: bool,
+    pub include: Vec<String>,
+    pub follow_symlinks: bool,
+    pub ancestors: bool,
+    pub tag: Option<String>,
+    /// Grep the files deep-merged into one document, later files winning.
+    pub merged: bool,
+    /// With `merged`, report each match under the file its value came from.
+    pub with_source: bool,
+    /// Search again whenever the searched files change.
+    pub watch: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UnsetOptions {
+    pub print_removed: bool,
+    pub null_out: bool,
+    /// Treat each key as a pattern matched against every key path, as in
+    /// `grep`.
+    pub regex: bool,
+    /// Also remove the mappings the removal leaves empty.
+    pub prune: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GetOptions {
+    pub pointer: bool,
+    pub transforms: Vec<Transform>,
+    /// How to parse the input when the file is `-` (stdin).
+    pub stdin_format: InputFormat,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SetOptions {
+    pub merge_json: Option<String>,
+    pub merge_strategy: MergeStrategy,
+    pub report: bool,
+    pub comment_prefix: Option<String>,
+    pub normalize_keys: Option<KeyStyle>,
+    pub append_doc: bool,
+    pub from_dotenv: Option<String>,
+    pub dotenv_prefix: Option<String>,
+    pub when: Option<Assertion>,
+    pub string: bool,
+    pub force_array: bool,
+}
+
+const DEFAULT_BACKUP_SUFFIX: &str = ".bak";
+const FILE_ENV_VAR: &str = "YM_FILE";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub exec: Option<String>,
+    pub indent: Option<usize>,
+    pub audit: bool,
+    /// Print how many lines the edit inserted and deleted.
+    pub stat: bool,
+    pub backup: Option<String>,
+    /// Where `--backup` copies go instead of next to each file.
+    pub backup_dir: Option<String>,
+    pub exit_code_on_change: Option<u8>,
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     Grep {
         pattern: String,
-        full: bool,
         files: Vec<String>,
+        options: GrepOptions,
+    },
+    Get {
+        file: String,
+        key: String,
+        options: GetOptions,
     },
     Set {
         file: String,
         updates: Vec<(String, String)>,
+        options: SetOptions,
+        write: WriteOptions,
     },
     Unset {
         file: String,
         keys: Vec<String>,
+        options: UnsetOptions,
+        write: WriteOptions,
+    },
+    Keys {
+        file: String,
+        leaves_only: bool,
+        glob: Option<String>,
+    },
+    Find {
+        file: String,
+        term: String,
+    },
+    Diff {
+        left: String,
+        right: String,
+        only: Option<ChangeKind>,
+        patch: bool,
+    },
+    Assert {
+        file: String,
+        expressions: Vec<String>,
+    },
+    Template {
+        file: String,
+        template: String,
+        keep_missing: bool,
+    },
+    Scrub {
+        file: String,
+        value_pattern: String,
+        dry_run: bool,
+        write: WriteOptions,
     },
     Cp {
         source_file: String,
-        source_key: String,
+        source_keys: Vec<String>,
         dest_file: Option<String>,
-        dest_key: Option<String>,
+        dest_keys: Option<Vec<String>>,
+        options: TransferOptions,
+        write: WriteOptions,
     },
     Mv {
         source_file: String,
-        source_key: String,
+        source_keys: Vec<String>,
         dest_file: Option<String>,
-        dest_key: Option<String>,
+        dest_keys: Option<Vec<String>>,
+        options: TransferOptions,
+        write: WriteOptions,
+    },
+    RenameKeys {
+        file: String,
+        pattern: String,
+        replacement: String,
+        write: WriteOptions,
+    },
+    StripComments {
+        file: String,
+        write: WriteOptions,
+    },
+    Run {
+        script: String,
+        file: String,
+        write: WriteOptions,
+    },
+    ToJson {
+        paths: Vec<String>,
+        output_dir: Option<String>,
+        recursive: bool,
+        ascii: bool,
     },
 }
 
@@ -36,6 +192,33 @@ pub enum Command {
 #[command(about = "A YAML search and patch tool", long_about = None)]
 #[command(version)]
 pub struct Cli {
+    #[arg(long, global = true)]
+    pub indent: Option<usize>,
+
+    #[arg(long, global = true)]
+    pub profile: bool,
+
+    #[arg(long, global = true)]
+    pub audit: bool,
+
+    #[arg(long, global = true)]
+    pub stat: bool,
+
+    #[arg(
+        long,
+        global = true,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_BACKUP_SUFFIX
+    )]
+    pub backup: Option<String>,
+
+    #[arg(long, global = true)]
+    pub backup_dir: Option<String>,
+
+    #[arg(long, global = true)]
+    pub preserve_numbers_as_strings: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -46,115 +229,801 @@ pub enum Commands {
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
+    Get {
+        #[arg(long)]
+        pointer: bool,
+
+        #[arg(long)]
+        stdin_format: Option<String>,
+
+        file: String,
+
+        #[arg(allow_hyphen_values = true)]
+        key: Option<String>,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        transforms: Vec<String>,
+    },
     Set {
+        #[arg(long)]
+        exec: Option<String>,
+
+        #[arg(long)]
+        merge_json: Option<String>,
+
+        #[arg(long)]
+        strategy: Option<String>,
+
+        #[arg(long)]
+        report: bool,
+
+        #[arg(long)]
+        comment_prefix: Option<String>,
+
+        #[arg(long)]
+        normalize_keys: Option<String>,
+
+        #[arg(long)]
+        append_doc: bool,
+
+        #[arg(long)]
+        from_dotenv: Option<String>,
+
+        #[arg(long)]
+        dotenv_prefix: Option<String>,
+
+        #[arg(long)]
+        when: Option<String>,
+
+        #[arg(short, long)]
+        string: bool,
+
+        #[arg(long)]
+        force_array: bool,
+
+        #[arg(long)]
+        exit_code_on_change: Option<String>,
+
         file: String,
 
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         updates: Vec<String>,
     },
     Unset {
+        #[arg(long)]
+        exec: Option<String>,
+
+        #[arg(long)]
+        print_removed: bool,
+
+        #[arg(long)]
+        null_out: bool,
+
+        #[arg(long)]
+        regex: bool,
+
+        #[arg(long)]
+        prune: bool,
+
+        #[arg(long)]
+        exit_code_on_change: Option<String>,
+
         file: String,
 
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         keys: Vec<String>,
     },
+    Keys {
+        #[arg(long)]
+        leaves_only: bool,
+
+        #[arg(long)]
+        glob: Option<String>,
+
+        file: String,
+    },
+    Find {
+        file: String,
+        term: String,
+    },
+    Diff {
+        #[arg(long)]
+        only: Option<String>,
+
+        #[arg(long)]
+        patch: bool,
+
+        left: String,
+        right: String,
+    },
+    Assert {
+        file: String,
+
+        #[arg(required = true)]
+        expressions: Vec<String>,
+    },
+    Template {
+        #[arg(long)]
+        keep_missing: bool,
+
+        file: String,
+        template: String,
+    },
+    Scrub {
+        #[arg(long)]
+        value_pattern: String,
+
+        #[arg(long)]
+        dry_run: bool,
+
+        #[arg(long)]
+        exec: Option<String>,
+
+        file: String,
+    },
     Cp {
+        #[arg(long)]
+        with_anchor: bool,
+
+        #[arg(long)]
+        same_type_only: bool,
+
+        #[arg(long)]
+        merge: bool,
+
+        #[arg(long)]
+        force: bool,
+
+        #[arg(long)]
+        exec: Option<String>,
+
         source: String,
 
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         destination: Vec<String>,
     },
     Mv {
+        #[arg(long)]
+        same_type_only: bool,
+
+        #[arg(long)]
+        exec: Option<String>,
+
         source: String,
 
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         destination: Vec<String>,
     },
+    #[command(name = "rename-keys")]
+    RenameKeys {
+        #[arg(long)]
+        exec: Option<String>,
+
+        file: String,
+
+        pattern: String,
+
+        #[arg(allow_hyphen_values = true)]
+        replacement: String,
+    },
+    StripComments {
+        #[arg(long)]
+        exec: Option<String>,
+
+        file: String,
+    },
+    #[command(alias = "apply")]
+    Run {
+        #[arg(long)]
+        exec: Option<String>,
+
+        script: String,
+
+        file: String,
+    },
+    #[command(name = "tojson")]
+    ToJson {
+        #[arg(long)]
+        output_dir: Option<String>,
+
+        #[arg(short = 'R', long)]
+        recursive: bool,
+
+        #[arg(long)]
+        ascii: bool,
+
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
 }
 
-pub fn parse_cli() -> AppResult<Command> {
-    command_from_cli(Cli::parse())
+/// Global flags that configure the whole run rather than one command.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GlobalOptions {
+    pub profile: bool,
+    pub preserve_numbers_as_strings: bool,
+}
+
+/// Parses the command line into the command to run and its global flags.
+pub fn parse_cli() -> AppResult<(Command, GlobalOptions)> {
+    let cli = Cli::parse();
+    let global = GlobalOptions {
+        profile: cli.profile,
+        preserve_numbers_as_strings: cli.preserve_numbers_as_strings,
+    };
+    Ok((command_from_cli(cli)?, global))
 }
 
 fn command_from_cli(cli: Cli) -> AppResult<Command> {
-    command_from_parsed(cli.command)
+    let default_file = env::var(FILE_ENV_VAR).ok();
+    command_from_parsed(
+        cli.command,
+        cli.indent,
+        cli.audit,
+        cli.stat,
+        cli.backup,
+        cli.backup_dir,
+        default_file,
+    )
+}
+
+/// With `$YM_FILE` set, the file unless `names_file` says the first
+/// positional argument is one; it is then taken as the argument following
+/// the omitted file. The caller decides from the arguments alone, never
+/// from what exists on disk.
+fn omitted_file(names_file: bool, default_file: &Option<String>) -> Option<String> {
+    default_file.clone().filter(|_| !names_file)
 }
 
-fn command_from_parsed(command: Commands) -> AppResult<Command> {
+fn command_from_parsed(
+    command: Commands,
+    indent: Option<usize>,
+    audit: bool,
+    stat: bool,
+    backup: Option<String>,
+    backup_dir: Option<String>,
+    default_file: Option<String>,
+) -> AppResult<Command> {
     match command {
         Commands::Grep { args } => {
-            let (pattern, full, files) = parse_grep_args(args)?;
+            let (pattern, mut files, options) = parse_grep_args(args)?;
+            if files.is_empty() {
+                files.extend(default_file);
+            }
             Ok(Command::Grep {
                 pattern,
-                full,
                 files,
+                options,
             })
         }
-        Commands::Set { file, updates } => Ok(Command::Set {
+        Commands::Get {
+            pointer,
+            stdin_format,
             file,
-            updates: parse_updates(updates)?,
-        }),
-        Commands::Unset { file, keys } => {
+            key,
+            transforms,
+        } => {
+            // A lone argument is the key; otherwise the first one must be
+            // named like a YAML file, or be `-` for stdin, to be the file.
+            let names_file = key.is_some() && (file == "-" || looks_like_yaml_file_path(&file));
+            let (file, key, mut transforms) = match omitted_file(names_file, &default_file) {
+                Some(default) => (
+                    default,
+                    Some(file),
+                    key.into_iter().chain(transforms).collect(),
+                ),
+                None => (file, key, transforms),
+            };
+            let key = key.ok_or_else(|| AppError::cli("get requires a file and a key"))?;
+            let pointer = take_flag(&mut transforms, "--pointer") || pointer;
+            let stdin_format = take_option(&mut transforms, "--stdin-format")?.or(stdin_format);
+            Ok(Command::Get {
+                file,
+                key,
+                options: GetOptions {
+                    pointer,
+                    stdin_format: stdin_format
+                        .as_deref()
+                        .map(parse_input_format)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    transforms: transforms
+                        .iter()
+                        .map(|flag| Transform::parse(flag))
+                        .collect::<AppResult<_>>()?,
+                },
+            })
+        }
+        Commands::Set {
+            exec,
+            merge_json,
+            strategy,
+            report,
+            comment_prefix,
+            normalize_keys,
+            append_doc,
+            from_dotenv,
+            dotenv_prefix,
+            when,
+            string,
+            force_array,
+            exit_code_on_change,
+            mut file,
+            mut updates,
+        } => {
+            if let Some(default) = omitted_file(!file.contains('='), &default_file) {
+                updates.insert(0, std::mem::replace(&mut file, default));
+            }
+            let mut write =
+                parse_write_options(&mut updates, exec, indent, audit, stat, backup, backup_dir)?;
+            write.exit_code_on_change = take_exit_code(&mut updates, exit_code_on_change)?;
+            let strategy = take_option(&mut updates, "--strategy")?.or(strategy);
+            let options = SetOptions {
+                merge_json: take_option(&mut updates, "--merge-json")?.or(merge_json),
+                merge_strategy: strategy
+                    .as_deref()
+                    .map(MergeStrategy::parse)
+                    .transpose()?
+                    .unwrap_or_default(),
+                report: take_flag(&mut updates, "--report") || report,
+                comment_prefix: take_option(&mut updates, "--comment-prefix")?.or(comment_prefix),
+                normalize_keys: take_option(&mut updates, "--normalize-keys")?
+                    .or(normalize_keys)
+                    .map(|style| KeyStyle::parse(&style))
+                    .transpose()?,
+                append_doc: take_flag(&mut updates, "--append-doc") || append_doc,
+                from_dotenv: take_option(&mut updates, "--from-dotenv")?.or(from_dotenv),
+                dotenv_prefix: take_option(&mut updates, "--dotenv-prefix")?.or(dotenv_prefix),
+                when: take_option(&mut updates, "--when")?
+                    .or(when)
+                    .map(|condition| Assertion::parse(&condition))
+                    .transpose()?,
+                string: take_flag(&mut updates, "--string")
+                    | take_flag(&mut updates, "-s")
+                    | string,
+                force_array: take_flag(&mut updates, "--force-array") || force_array,
+            };
+            if strategy.is_some() && options.merge_json.is_none() {
+                return Err(AppError::cli("--strategy requires --merge-json"));
+            }
+            if options.dotenv_prefix.is_some() && options.from_dotenv.is_none() {
+                return Err(AppError::cli("--dotenv-prefix requires --from-dotenv"));
+            }
+            if updates.is_empty() && options.merge_json.is_none() && options.from_dotenv.is_none() {
+                return Err(AppError::cli("set requires at least one key=value pair"));
+            }
+
+            Ok(Command::Set {
+                file,
+                updates: parse_updates(updates)?,
+                options,
+                write,
+            })
+        }
+        Commands::Unset {
+            exec,
+            print_removed,
+            null_out,
+            regex,
+            prune,
+            exit_code_on_change,
+            mut file,
+            mut keys,
+        } => {
+            let names_file = looks_like_yaml_file_path(&file);
+            if let Some(default) = omitted_file(names_file, &default_file) {
+                keys.insert(0, std::mem::replace(&mut file, default));
+            }
+            let mut write =
+                parse_write_options(&mut keys, exec, indent, audit, stat, backup, backup_dir)?;
+            write.exit_code_on_change = take_exit_code(&mut keys, exit_code_on_change)?;
+            let options = UnsetOptions {
+                print_removed: take_flag(&mut keys, "--print-removed") || print_removed,
+                null_out: take_flag(&mut keys, "--null-out") || null_out,
+                regex: take_flag(&mut keys, "--regex") || regex,
+                prune: take_flag(&mut keys, "--prune") || prune,
+            };
             if keys.is_empty() {
                 return Err(AppError::cli("unset requires at least one key"));
             }
+            // A nulled-out key stays, so there is never a parent to prune.
+            if options.null_out && options.prune {
+                return Err(AppError::cli("--null-out cannot be combined with --prune"));
+            }
 
-            Ok(Command::Unset { file, keys })
+            Ok(Command::Unset {
+                file,
+                keys,
+                options,
+                write,
+            })
         }
+        Commands::Keys {
+            leaves_only,
+            glob,
+            file,
+        } => Ok(Command::Keys {
+            file,
+            leaves_only,
+            glob,
+        }),
+        Commands::Find { file, term } => Ok(Command::Find { file, term }),
+        Commands::Diff {
+            only,
+            patch,
+            left,
+            right,
+        } => Ok(Command::Diff {
+            left,
+            right,
+            only: only.as_deref().map(ChangeKind::parse).transpose()?,
+            patch,
+        }),
+        Commands::Assert { file, expressions } => Ok(Command::Assert { file, expressions }),
+        Commands::Template {
+            keep_missing,
+            file,
+            template,
+        } => Ok(Command::Template {
+            file,
+            template,
+            keep_missing,
+        }),
+        Commands::Scrub {
+            value_pattern,
+            dry_run,
+            exec,
+            file,
+        } => Ok(Command::Scrub {
+            file,
+            value_pattern,
+            dry_run,
+            write: parse_write_options(
+                &mut Vec::new(),
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?,
+        }),
         Commands::Cp {
+            with_anchor,
+            same_type_only,
+            merge,
+            force,
+            exec,
             source,
-            destination,
+            mut destination,
         } => {
+            let options = TransferOptions {
+                with_anchor: take_flag(&mut destination, "--with-anchor") || with_anchor,
+                same_type_only: take_flag(&mut destination, "--same-type-only") || same_type_only,
+                merge: take_flag(&mut destination, "--merge") || merge,
+                force: take_flag(&mut destination, "--force") || force,
+            };
+            if options.merge && options.with_anchor {
+                return Err(AppError::cli(
+                    "--merge and --with-anchor cannot be used together",
+                ));
+            }
+            let write = parse_write_options(
+                &mut destination,
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?;
             let transfer = parse_transfer_command(source, destination, "cp")?;
             Ok(Command::Cp {
                 source_file: transfer.source_file,
-                source_key: transfer.source_key,
+                source_keys: transfer.source_keys,
                 dest_file: transfer.dest_file,
-                dest_key: transfer.dest_key,
+                dest_keys: transfer.dest_keys,
+                options,
+                write,
             })
         }
         Commands::Mv {
+            same_type_only,
+            exec,
             source,
-            destination,
+            mut destination,
         } => {
+            let options = TransferOptions {
+                same_type_only: take_flag(&mut destination, "--same-type-only") || same_type_only,
+                ..TransferOptions::default()
+            };
+            let write = parse_write_options(
+                &mut destination,
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?;
             let transfer = parse_transfer_command(source, destination, "mv")?;
             Ok(Command::Mv {
                 source_file: transfer.source_file,
-                source_key: transfer.source_key,
+                source_keys: transfer.source_keys,
                 dest_file: transfer.dest_file,
-                dest_key: transfer.dest_key,
+                dest_keys: transfer.dest_keys,
+                options,
+                write,
             })
         }
+        Commands::RenameKeys {
+            exec,
+            file,
+            pattern,
+            replacement,
+        } => Ok(Command::RenameKeys {
+            file,
+            pattern,
+            replacement,
+            write: parse_write_options(
+                &mut Vec::new(),
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?,
+        }),
+        Commands::StripComments { exec, file } => Ok(Command::StripComments {
+            file,
+            write: parse_write_options(
+                &mut Vec::new(),
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?,
+        }),
+        Commands::Run { exec, script, file } => Ok(Command::Run {
+            script,
+            file,
+            write: parse_write_options(
+                &mut Vec::new(),
+                exec,
+                indent,
+                audit,
+                stat,
+                backup,
+                backup_dir,
+            )?,
+        }),
+        Commands::ToJson {
+            output_dir,
+            recursive,
+            ascii,
+            paths,
+        } => Ok(Command::ToJson {
+            paths,
+            output_dir,
+            recursive,
+            ascii,
+        }),
+    }
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != before
+}
+
+fn take_option(args: &mut Vec<String>, option: &str) -> AppResult<Option<String>> {
+    let Some(index) = args.iter().position(|arg| arg == option) else {
+        return Ok(None);
+    };
+
+    args.remove(index);
+    if index == args.len() {
+        return Err(AppError::cli(format!("{option} requires a value")));
     }
+    Ok(Some(args.remove(index)))
+}
+
+fn parse_write_options(
+    args: &mut Vec<String>,
+    exec: Option<String>,
+    indent: Option<usize>,
+    audit: bool,
+    stat: bool,
+    backup: Option<String>,
+    backup_dir: Option<String>,
+) -> AppResult<WriteOptions> {
+    let trailing_indent = take_option(args, "--indent")?
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| AppError::cli(format!("Invalid value for --indent: {value}")))
+        })
+        .transpose()?;
+
+    // `--backup-dir` alone asks for backups with the default suffix.
+    let backup_dir = take_option(args, "--backup-dir")?.or(backup_dir);
+    let backup = take_backup(args).or(backup).or_else(|| {
+        backup_dir
+            .as_ref()
+            .map(|_| DEFAULT_BACKUP_SUFFIX.to_string())
+    });
+
+    Ok(WriteOptions {
+        exec: take_option(args, "--exec")?.or(exec),
+        indent: trailing_indent.or(indent),
+        audit: take_flag(args, "--audit") || audit,
+        stat: take_flag(args, "--stat") || stat,
+        backup,
+        backup_dir,
+        exit_code_on_change: None,
+    })
 }
 
-fn parse_grep_args(args: Vec<String>) -> AppResult<(String, bool, Vec<String>)> {
+/// Takes `--exit-code-on-change CODE` from trailing arguments; 0 would be
+/// indistinguishable from "unchanged".
+fn take_exit_code(args: &mut Vec<String>, code: Option<String>) -> AppResult<Option<u8>> {
+    take_option(args, "--exit-code-on-change")?
+        .or(code)
+        .map(|value| match value.parse() {
+            Ok(code) if code != 0 => Ok(code),
+            _ => Err(AppError::cli(format!(
+                "Invalid value for --exit-code-on-change: {value}"
+            ))),
+        })
+        .transpose()
+}
+
+/// Takes `--backup` or `--backup=SUFFIX` from trailing arguments.
+fn take_backup(args: &mut Vec<String>) -> Option<String> {
+    let index = args
+        .iter()
+        .position(|arg| arg == "--backup" || arg.starts_with("--backup="))?;
+    let arg = args.remove(index);
+    Some(
+        arg.strip_prefix("--backup=")
+            .unwrap_or(DEFAULT_BACKUP_SUFFIX)
+            .to_string(),
+    )
+}
+
+fn parse_grep_args(args: Vec<String>) -> AppResult<(String, Vec<String>, GrepOptions)> {
     if args.is_empty() {
         return Err(AppError::cli("grep requires at least a pattern"));
     }
 
     let mut pattern = None;
-    let mut full = false;
+    let mut options = GrepOptions::default();
     let mut files = Vec::new();
+    let (mut match_keys, mut match_values) = (false, false);
+    let mut args = args.into_iter();
 
-    for arg in args {
+    while let Some(arg) = args.next() {
         match arg.as_str() {
-            "--full" => full = true,
+            "--full" => options.full = true,
+            "--distinct-values" => options.distinct_values = true,
+            "--json" => options.json = true,
+            "--csv" => options.csv = true,
+            "--per-file-yaml" => options.per_file_yaml = true,
+            "-l" | "--files-with-matches" => options.files_with_matches = true,
+            "-L" | "--files-without-match" => options.files_without_match = true,
+            "-c" | "--count" => options.count = true,
+            "--hidden" | "--include-hidden" => options.include_hidden = true,
+            "-R" | "--recursive" => options.recursive = true,
+            "--follow-symlinks" => options.follow_symlinks = true,
+            "-k" | "--key" => match_keys = true,
+            "-v" | "--value" => match_values = true,
+            "--any-separator" => options.matching.any_separator = true,
+            "-i" | "--ignore-case" => options.matching.ignore_case = true,
+            "-w" | "--word-regexp" => options.matching.word_regexp = true,
+            "-x" | "--exact" => options.matching.exact = true,
+            "--top-level" => options.matching.top_level = true,
+            "--fold-keys" => options.matching.fold_keys = true,
+            "--with-position" => options.with_position = true,
+            "--show-match" => options.show_match = true,
+            "--ancestors" => options.ancestors = true,
+            "--merged" => options.merged = true,
+            "--with-source" => options.with_source = true,
+            "--watch" => options.watch = true,
+            "--depth-histogram" => options.depth_histogram = true,
+            "--files-from-stdin" | "--null-data" => options.files_from_stdin = true,
+            "--include" => options.include.push(next_option_value(&mut args, &arg)?),
+            _ if arg.starts_with("--include=") => {
+                options.include.push(arg["--include=".len()..].to_string());
+            }
+            "--tag" => options.tag = Some(next_option_value(&mut args, &arg)?),
+            "--format-template" => {
+                options.format_template = Some(next_option_value(&mut args, &arg)?);
+            }
+            "--type" => {
+                options.matching.value_type =
+                    Some(ValueType::parse(&next_option_value(&mut args, &arg)?)?);
+            }
+            "--stdin-format" => {
+                options.stdin_format = parse_input_format(&next_option_value(&mut args, &arg)?)?;
+            }
+            "--regex-size-limit" => {
+                let value = next_option_value(&mut args, &arg)?;
+                options.matching.regex_size_limit = Some(value.parse().map_err(|_| {
+                    AppError::cli(format!("Invalid value for --regex-size-limit: {value}"))
+                })?);
+            }
+            "--max-results" => {
+                let value = next_option_value(&mut args, &arg)?;
+                options.max_results = Some(value.parse().map_err(|_| {
+                    AppError::cli(format!("Invalid value for --max-results: {value}"))
+                })?);
+            }
+            "-j" | "--jobs" => {
+                let value = next_option_value(&mut args, &arg)?;
+                options.jobs =
+                    Some(value.parse().ok().filter(|&jobs| jobs > 0).ok_or_else(|| {
+                        AppError::cli(format!("Invalid value for --jobs: {value}"))
+                    })?);
+            }
+            "--max-file-size" => {
+                let value = next_option_value(&mut args, &arg)?;
+                options.max_file_size = Some(value.parse().map_err(|_| {
+                    AppError::cli(format!("Invalid value for --max-file-size: {value}"))
+                })?);
+            }
             _ if pattern.is_none() => pattern = Some(arg),
             _ => files.push(arg),
         }
     }
 
+    options.matching.target = match (match_keys, match_values) {
+        (true, true) => MatchTarget::Either,
+        (false, true) => MatchTarget::Values,
+        _ => MatchTarget::Keys,
+    };
+
+    if options.json && options.csv {
+        return Err(AppError::cli("--json and --csv cannot be used together"));
+    }
+
+    if options.files_with_matches && options.files_without_match {
+        return Err(AppError::cli("-l and -L cannot be used together"));
+    }
+
+    if options.with_position && !options.json {
+        return Err(AppError::cli("--with-position requires --json"));
+    }
+
+    if options.with_source && !options.merged {
+        return Err(AppError::cli("--with-source requires --merged"));
+    }
+
     pattern
-        .map(|pattern| (pattern, full, files))
+        .map(|pattern| (pattern, files, options))
         .ok_or_else(|| AppError::cli("grep requires a pattern"))
 }
 
-fn parse_updates(updates: Vec<String>) -> AppResult<Vec<(String, String)>> {
-    if updates.is_empty() {
-        return Err(AppError::cli("set requires at least one key=value pair"));
+fn next_option_value(args: &mut impl Iterator<Item = String>, option: &str) -> AppResult<String> {
+    args.next()
+        .ok_or_else(|| AppError::cli(format!("{option} requires a value")))
+}
+
+fn parse_input_format(input: &str) -> AppResult<InputFormat> {
+    match input {
+        "yaml" => Ok(InputFormat::Yaml),
+        "json" => Ok(InputFormat::Json),
+        "toml" => Ok(InputFormat::Toml),
+        _ => Err(AppError::cli(format!(
+            "Unsupported input format: {input} (expected yaml, json or toml)"
+        ))),
     }
+}
 
+fn parse_updates(updates: Vec<String>) -> AppResult<Vec<(String, String)>> {
     updates
         .into_iter()
         .map(|update| {
@@ -168,9 +1037,9 @@ fn parse_updates(updates: Vec<String>) -> AppResult<Vec<(String, String)>> {
 
 struct TransferCommand {
     source_file: String,
-    source_key: String,
+    source_keys: Vec<String>,
     dest_file: Option<String>,
-    dest_key: Option<String>,
+    dest_keys: Option<Vec<String>>,
 }
 
 fn parse_transfer_command(
@@ -179,6 +1048,7 @@ fn parse_transfer_command(
     name: &str,
 ) -> AppResult<TransferCommand> {
     let (source_file, source_key) = parse_file_key_pair(&source)?;
+    let source_keys = split_keys(&source_key)?;
 
     let (dest_file, dest_key) = match destination.as_slice() {
         [] => (None, None),
@@ -196,14 +1066,52 @@ fn parse_transfer_command(
         ));
     }
 
+    let dest_keys = dest_key.as_deref().map(split_keys).transpose()?;
+    if let Some(dest_keys) = &dest_keys {
+        if dest_keys.len() != source_keys.len() {
+            return Err(AppError::cli(format!(
+                "{name} was given {} source keys but {} destination keys",
+                source_keys.len(),
+                dest_keys.len()
+            )));
+        }
+    }
+
     Ok(TransferCommand {
         source_file,
-        source_key,
+        source_keys,
         dest_file,
-        dest_key,
+        dest_keys,
     })
 }
 
+/// Splits a comma-separated list of keys, as in `file.yaml:a,b.c`. An
+/// escaped comma (`a\,b`) stays in its key, escape included, for the key
+/// path to read as a literal comma.
+fn split_keys(keys: &str) -> AppResult<Vec<String>> {
+    let mut split = vec![String::new()];
+    let mut chars = keys.chars();
+    while let Some(ch) = chars.next() {
+        let key = split.last_mut().unwrap();
+        match ch {
+            '\\' => {
+                key.push(ch);
+                key.extend(chars.next());
+            }
+            ',' => split.push(String::new()),
+            ch => key.push(ch),
+        }
+    }
+
+    split
+        .into_iter()
+        .map(|key| match key.as_str() {
+            "" => Err(AppError::cli(format!("Empty key in key list: {keys}"))),
+            _ => Ok(key),
+        })
+        .collect()
+}
+
 fn parse_file_key_pair(input: &str) -> AppResult<(String, String)> {
     let Some((file, key)) = input.split_once(':') else {
         return Err(AppError::cli(format!(
@@ -252,10 +1160,139 @@ fn looks_like_yaml_file_path(input: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::yaml_ops::{ConflictStrategy, SequenceStrategy};
 
+    /// Parses `args` as if `$YM_FILE` were unset, whatever the environment.
     fn test_with_args(args: Vec<&str>) -> AppResult<Command> {
+        parse_with_default_file(args, None)
+    }
+
+    fn test_with_default_file(args: Vec<&str>, default_file: &str) -> AppResult<Command> {
+        parse_with_default_file(args, Some(default_file.to_string()))
+    }
+
+    fn parse_with_default_file(
+        args: Vec<&str>,
+        default_file: Option<String>,
+    ) -> AppResult<Command> {
         let cli = Cli::try_parse_from(args).map_err(|error| AppError::cli(error.to_string()))?;
-        command_from_cli(cli)
+        command_from_parsed(
+            cli.command,
+            cli.indent,
+            cli.audit,
+            cli.stat,
+            cli.backup,
+            cli.backup_dir,
+            default_file,
+        )
+    }
+
+    #[test]
+    fn test_parse_defaults_file_from_env_var_when_omitted() {
+        let get = test_with_default_file(vec!["ym", "get", "db.host", "--upper"], "env.yaml");
+        assert_eq!(
+            get.unwrap(),
+            Command::Get {
+                file: "env.yaml".to_string(),
+                key: "db.host".to_string(),
+                options: GetOptions {
+                    transforms: vec![Transform::Upper],
+                    ..GetOptions::default()
+                },
+            }
+        );
+
+        let set = test_with_default_file(vec!["ym", "set", "a=1", "b=2"], "env.yaml");
+        assert_eq!(
+            set.unwrap(),
+            Command::Set {
+                file: "env.yaml".to_string(),
+                updates: vec![
+                    ("a".to_string(), "1".to_string()),
+                    ("b".to_string(), "2".to_string()),
+                ],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+
+        let unset = test_with_default_file(vec!["ym", "unset", "a"], "env.yaml");
+        assert_eq!(
+            unset.unwrap(),
+            Command::Unset {
+                file: "env.yaml".to_string(),
+                keys: vec!["a".to_string()],
+                options: UnsetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+
+        let grep = test_with_default_file(vec!["ym", "grep", "host"], "env.yaml");
+        assert_eq!(
+            grep.unwrap(),
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec!["env.yaml".to_string()],
+                options: GrepOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_explicit_file_wins_over_env_var() {
+        let get = test_with_default_file(vec!["ym", "get", "app.yaml", "package"], "env.yaml");
+        assert_eq!(
+            get.unwrap(),
+            Command::Get {
+                file: "app.yaml".to_string(),
+                key: "package".to_string(),
+                options: GetOptions::default(),
+            }
+        );
+
+        let grep = test_with_default_file(vec!["ym", "grep", "host", "a.yaml"], "env.yaml");
+        assert!(matches!(grep.unwrap(), Command::Grep { files, .. } if files == ["a.yaml"]));
+    }
+
+    #[test]
+    fn test_parse_env_var_default_follows_argument_shape_not_disk() {
+        // Neither file exists; only their names and the `=` matter.
+        let unset = test_with_default_file(vec!["ym", "unset", "missing.yml", "a"], "env.yaml");
+        assert!(matches!(
+            unset.unwrap(),
+            Command::Unset { file, keys, .. } if file == "missing.yml" && keys == ["a"]
+        ));
+        let set = test_with_default_file(vec!["ym", "set", "new.yaml", "a=1"], "env.yaml");
+        assert!(matches!(set.unwrap(), Command::Set { file, .. } if file == "new.yaml"));
+
+        // Cargo.toml exists, but is not named like a YAML file.
+        let unset = test_with_default_file(vec!["ym", "unset", "Cargo.toml", "a"], "env.yaml");
+        assert!(matches!(
+            unset.unwrap(),
+            Command::Unset { file, keys, .. } if file == "env.yaml" && keys == ["Cargo.toml", "a"]
+        ));
+        let get = test_with_default_file(vec!["ym", "get", "config.yaml"], "env.yaml");
+        assert!(matches!(
+            get.unwrap(),
+            Command::Get { file, key, .. } if file == "env.yaml" && key == "config.yaml"
+        ));
+
+        // Without the env var the first argument is always the file.
+        let unset = test_with_args(vec!["ym", "unset", "a", "b"]);
+        assert!(matches!(
+            unset.unwrap(),
+            Command::Unset { file, keys, .. } if file == "a" && keys == ["b"]
+        ));
+        assert!(test_with_args(vec!["ym", "set", "a=1"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_get_without_file_or_env_var_fails() {
+        let result = test_with_args(vec!["ym", "get", "db.host"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("get requires a file and a key"));
     }
 
     #[test]
@@ -266,8 +1303,8 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: false,
                 files: vec!["file.yaml".to_string()],
+                options: GrepOptions::default(),
             }
         );
     }
@@ -288,12 +1325,12 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: false,
                 files: vec![
                     "file1.yaml".to_string(),
                     "file2.yaml".to_string(),
                     "file3.yaml".to_string(),
                 ],
+                options: GrepOptions::default(),
             }
         );
     }
@@ -311,8 +1348,8 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: false,
                 files: Vec::new(),
+                options: GrepOptions::default(),
             }
         );
     }
@@ -325,8 +1362,11 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: true,
                 files: vec!["file.yaml".to_string()],
+                options: GrepOptions {
+                    full: true,
+                    ..GrepOptions::default()
+                },
             }
         );
     }
@@ -339,8 +1379,11 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: true,
                 files: vec!["file.yaml".to_string()],
+                options: GrepOptions {
+                    full: true,
+                    ..GrepOptions::default()
+                },
             }
         );
     }
@@ -353,8 +1396,11 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: true,
                 files: vec!["file.yaml".to_string()],
+                options: GrepOptions {
+                    full: true,
+                    ..GrepOptions::default()
+                },
             }
         );
     }
@@ -367,34 +1413,1224 @@ mod tests {
             cmd,
             Command::Grep {
                 pattern: "pattern".to_string(),
-                full: true,
                 files: Vec::new(),
+                options: GrepOptions {
+                    full: true,
+                    ..GrepOptions::default()
+                },
             }
         );
     }
 
     #[test]
-    fn test_parse_set_single_key_value() {
-        let cmd = test_with_args(vec!["ym", "set", "file.yaml", "key=value"]).unwrap();
-
-        assert_eq!(
-            cmd,
-            Command::Set {
+    fn test_parse_grep_with_format_template() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "grep",
+            "pattern",
+            "--format-template",
+            "{key} {value}",
+            "file.yaml",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "pattern".to_string(),
+                files: vec!["file.yaml".to_string()],
+                options: GrepOptions {
+                    format_template: Some("{key} {value}".to_string()),
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_format_template_requires_value() {
+        let result = test_with_args(vec!["ym", "grep", "pattern", "--format-template"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--format-template requires a value"));
+    }
+
+    #[test]
+    fn test_parse_grep_with_distinct_values() {
+        let cmd =
+            test_with_args(vec!["ym", "grep", "--distinct-values", "host", "f.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec!["f.yaml".to_string()],
+                options: GrepOptions {
+                    distinct_values: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_type_filter() {
+        let cmd = test_with_args(vec!["ym", "grep", "timeout", "--type", "int", "f.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "timeout".to_string(),
+                files: vec!["f.yaml".to_string()],
+                options: GrepOptions {
+                    matching: MatchOptions {
+                        value_type: Some(ValueType::Int),
+                        ..MatchOptions::default()
+                    },
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_json_with_position() {
+        let cmd = test_with_args(vec!["ym", "grep", "--json", "--with-position", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    json: true,
+                    with_position: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+        assert!(test_with_args(vec!["ym", "grep", "--with-position", "host"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_grep_with_any_separator() {
+        let cmd = test_with_args(vec!["ym", "grep", "--any-separator", "database/host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "database/host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    matching: MatchOptions {
+                        any_separator: true,
+                        ..MatchOptions::default()
+                    },
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_depth_histogram() {
+        let cmd = test_with_args(vec!["ym", "grep", "--depth-histogram", ".", "f.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: ".".to_string(),
+                files: vec!["f.yaml".to_string()],
+                options: GrepOptions {
+                    depth_histogram: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_files_from_stdin() {
+        for flag in ["--files-from-stdin", "--null-data"] {
+            let cmd = test_with_args(vec!["ym", "grep", flag, "password"]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: "password".to_string(),
+                    files: Vec::new(),
+                    options: GrepOptions {
+                        files_from_stdin: true,
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_include_hidden() {
+        let cmd = test_with_args(vec!["ym", "grep", "--include-hidden", "host", "."]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec![".".to_string()],
+                options: GrepOptions {
+                    include_hidden: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+
+        let cmd = test_with_args(vec!["ym", "grep", "--hidden", "host"]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.include_hidden));
+    }
+
+    #[test]
+    fn test_parse_grep_match_targets() {
+        for (flags, target) in [
+            (vec![], MatchTarget::Keys),
+            (vec!["-k"], MatchTarget::Keys),
+            (vec!["--value"], MatchTarget::Values),
+            (vec!["-v", "--key"], MatchTarget::Either),
+        ] {
+            let mut args = vec!["ym", "grep"];
+            args.extend(flags);
+            args.push("localhost");
+            let cmd = test_with_args(args).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: "localhost".to_string(),
+                    files: Vec::new(),
+                    options: GrepOptions {
+                        matching: MatchOptions {
+                            target,
+                            ..MatchOptions::default()
+                        },
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_ignore_case() {
+        for flag in ["-i", "--ignore-case"] {
+            let cmd = test_with_args(vec!["ym", "grep", flag, r"DATABASE\.host"]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: r"DATABASE\.host".to_string(),
+                    files: Vec::new(),
+                    options: GrepOptions {
+                        matching: MatchOptions {
+                            ignore_case: true,
+                            ..MatchOptions::default()
+                        },
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_word_regexp() {
+        for flag in ["-w", "--word-regexp"] {
+            let cmd = test_with_args(vec!["ym", "grep", flag, "port"]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: "port".to_string(),
+                    files: Vec::new(),
+                    options: GrepOptions {
+                        matching: MatchOptions {
+                            word_regexp: true,
+                            ..MatchOptions::default()
+                        },
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_top_level() {
+        let cmd = test_with_args(vec!["ym", "grep", "--top-level", "."]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: ".".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    matching: MatchOptions {
+                        top_level: true,
+                        ..MatchOptions::default()
+                    },
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_exact() {
+        for flag in ["-x", "--exact"] {
+            let cmd = test_with_args(vec!["ym", "grep", flag, "host"]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: "host".to_string(),
+                    files: Vec::new(),
+                    options: GrepOptions {
+                        matching: MatchOptions {
+                            exact: true,
+                            ..MatchOptions::default()
+                        },
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_fold_keys() {
+        let cmd = test_with_args(vec!["ym", "grep", "--fold-keys", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    matching: MatchOptions {
+                        fold_keys: true,
+                        ..MatchOptions::default()
+                    },
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_recursive() {
+        for flag in ["-R", "--recursive"] {
+            let cmd = test_with_args(vec!["ym", "grep", flag, "host", "."]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Grep {
+                    pattern: "host".to_string(),
+                    files: vec![".".to_string()],
+                    options: GrepOptions {
+                        recursive: true,
+                        ..GrepOptions::default()
+                    },
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_csv() {
+        let cmd = test_with_args(vec!["ym", "grep", "--csv", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    csv: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "grep", "--csv", "--json", "host"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_grep_with_regex_size_limit() {
+        let cmd = test_with_args(vec!["ym", "grep", "--regex-size-limit", "4096", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    matching: MatchOptions {
+                        regex_size_limit: Some(4096),
+                        ..MatchOptions::default()
+                    },
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_max_results() {
+        let cmd = test_with_args(vec!["ym", "grep", "--max-results", "5", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    max_results: Some(5),
+                    ..GrepOptions::default()
+                },
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "grep", "--max-results", "many", "host"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid value for --max-results: many"));
+    }
+
+    #[test]
+    fn test_parse_grep_with_file_name_listing() {
+        let cmd = test_with_args(vec!["ym", "grep", "-l", "host", "dir"]).unwrap();
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec!["dir".to_string()],
+                options: GrepOptions {
+                    files_with_matches: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+
+        let cmd = test_with_args(vec!["ym", "grep", "--files-without-match", "host"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Grep { options, .. } if options.files_without_match
+        ));
+
+        let result = test_with_args(vec!["ym", "grep", "-l", "-L", "host"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("-l and -L cannot be used together"));
+    }
+
+    #[test]
+    fn test_parse_grep_with_include_globs() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "grep",
+            "--include=*.values",
+            "--include",
+            "*.yaml.tpl",
+            "host",
+            "-R",
+            "charts",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec!["charts".to_string()],
+                options: GrepOptions {
+                    include: vec!["*.values".to_string(), "*.yaml.tpl".to_string()],
+                    recursive: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_follow_symlinks() {
+        let cmd = test_with_args(vec!["ym", "grep", "--follow-symlinks", "-R", "host"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Grep { options, .. } if options.follow_symlinks && options.recursive
+        ));
+    }
+
+    #[test]
+    fn test_parse_grep_with_tag() {
+        let cmd = test_with_args(vec!["ym", "grep", "--tag", "!secret", "."]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Grep { options, .. } if options.tag.as_deref() == Some("!secret")
+        ));
+        assert!(test_with_args(vec!["ym", "grep", ".", "--tag"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_grep_merged_with_source() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "grep",
+            "--merged",
+            "--with-source",
+            "host",
+            "base.yaml",
+            "prod.yaml",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Grep { files, options, .. }
+                if options.merged && options.with_source && files.len() == 2
+        ));
+
+        let result = test_with_args(vec!["ym", "grep", "--with-source", "host", "a.yaml"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--with-source requires --merged"));
+    }
+
+    #[test]
+    fn test_parse_grep_with_jobs() {
+        let cmd = test_with_args(vec!["ym", "grep", "-R", "--jobs", "4", "host", "."]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.jobs == Some(4)));
+
+        for jobs in ["0", "many"] {
+            let result = test_with_args(vec!["ym", "grep", "-j", jobs, "host"]);
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains(&format!("Invalid value for --jobs: {jobs}")));
+        }
+    }
+
+    #[test]
+    fn test_parse_grep_with_watch() {
+        let cmd = test_with_args(vec!["ym", "grep", "--watch", "host", "config.yaml"]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.watch));
+    }
+
+    #[test]
+    fn test_parse_grep_with_ancestors() {
+        let cmd = test_with_args(vec!["ym", "grep", "--ancestors", "host"]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.ancestors));
+    }
+
+    #[test]
+    fn test_parse_grep_with_show_match() {
+        let cmd = test_with_args(vec!["ym", "grep", "--show-match", "(host|port)"]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.show_match));
+    }
+
+    #[test]
+    fn test_parse_grep_with_count() {
+        let cmd = test_with_args(vec!["ym", "grep", "--count", "host", "dir"]).unwrap();
+        assert!(matches!(cmd, Command::Grep { options, .. } if options.count));
+
+        let cmd = test_with_args(vec!["ym", "grep", "-c", "-L", "host"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Grep { options, .. } if options.count && options.files_without_match
+        ));
+    }
+
+    #[test]
+    fn test_parse_grep_with_per_file_yaml() {
+        let cmd =
+            test_with_args(vec!["ym", "grep", "--per-file-yaml", "host", "-R", "dir"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec!["dir".to_string()],
+                options: GrepOptions {
+                    per_file_yaml: true,
+                    recursive: true,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_with_max_file_size() {
+        let cmd = test_with_args(vec!["ym", "grep", "--max-file-size", "1024", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    max_file_size: Some(1024),
+                    ..GrepOptions::default()
+                },
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "grep", "--max-file-size", "1k", "host"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid value for --max-file-size: 1k"));
+    }
+
+    #[test]
+    fn test_parse_grep_with_stdin_format() {
+        let cmd = test_with_args(vec!["ym", "grep", "--stdin-format", "json", "host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: Vec::new(),
+                options: GrepOptions {
+                    stdin_format: InputFormat::Json,
+                    ..GrepOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_grep_rejects_unknown_stdin_format() {
+        let result = test_with_args(vec!["ym", "grep", "--stdin-format", "xml", "host"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported input format: xml"));
+    }
+
+    #[test]
+    fn test_parse_get_from_stdin_with_format() {
+        let expected = Command::Get {
+            file: "-".to_string(),
+            key: "database.host".to_string(),
+            options: GetOptions {
+                stdin_format: InputFormat::Toml,
+                ..GetOptions::default()
+            },
+        };
+
+        let leading = test_with_args(vec![
+            "ym",
+            "get",
+            "--stdin-format",
+            "toml",
+            "-",
+            "database.host",
+        ]);
+        let trailing = test_with_args(vec![
+            "ym",
+            "get",
+            "-",
+            "database.host",
+            "--stdin-format",
+            "toml",
+        ]);
+        let env = test_with_default_file(
+            vec!["ym", "get", "-", "database.host", "--stdin-format", "toml"],
+            "env.yaml",
+        );
+        assert_eq!(leading.unwrap(), expected);
+        assert_eq!(trailing.unwrap(), expected);
+        assert_eq!(env.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_get() {
+        let cmd = test_with_args(vec!["ym", "get", "file.yaml", "database.host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Get {
+                file: "file.yaml".to_string(),
+                key: "database.host".to_string(),
+                options: GetOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_get_with_transforms_in_order() {
+        let cmd =
+            test_with_args(vec!["ym", "get", "file.yaml", "name", "--trim", "--upper"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Get {
+                file: "file.yaml".to_string(),
+                key: "name".to_string(),
+                options: GetOptions {
+                    transforms: vec![Transform::Trim, Transform::Upper],
+                    ..GetOptions::default()
+                },
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "get", "file.yaml", "name", "--reverse"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown transform: --reverse"));
+    }
+
+    #[test]
+    fn test_parse_get_with_pointer() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "get",
+            "--pointer",
+            "file.yaml",
+            "/servers/0/host",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Get {
+                file: "file.yaml".to_string(),
+                key: "/servers/0/host".to_string(),
+                options: GetOptions {
+                    pointer: true,
+                    ..GetOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_assert() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "assert",
+            "file.yaml",
+            "replicas>=3",
+            "name exists",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Assert {
+                file: "file.yaml".to_string(),
+                expressions: vec!["replicas>=3".to_string(), "name exists".to_string()],
+            }
+        );
+        assert!(test_with_args(vec!["ym", "assert", "file.yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let cmd = test_with_args(vec!["ym", "keys", "config.yaml", "--leaves-only"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Keys {
+                file: "config.yaml".to_string(),
+                leaves_only: true,
+                glob: None,
+            }
+        );
+
+        let cmd =
+            test_with_args(vec!["ym", "keys", "config.yaml", "--glob", "database.*"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Keys {
+                file: "config.yaml".to_string(),
+                leaves_only: false,
+                glob: Some("database.*".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_find() {
+        let cmd = test_with_args(vec!["ym", "find", "config.yaml", "db host"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Find {
+                file: "config.yaml".to_string(),
+                term: "db host".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_diff() {
+        let cmd = test_with_args(vec!["ym", "diff", "old.yaml", "new.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Diff {
+                left: "old.yaml".to_string(),
+                right: "new.yaml".to_string(),
+                only: None,
+                patch: false,
+            }
+        );
+        assert!(test_with_args(vec!["ym", "diff", "old.yaml"]).is_err());
+
+        let patch = test_with_args(vec!["ym", "diff", "--patch", "a.yaml", "b.yaml"]);
+        assert!(matches!(patch.unwrap(), Command::Diff { patch: true, .. }));
+
+        let only = test_with_args(vec!["ym", "diff", "a.yaml", "b.yaml", "--only", "removed"]);
+        assert!(matches!(
+            only.unwrap(),
+            Command::Diff {
+                only: Some(ChangeKind::Removed),
+                ..
+            }
+        ));
+        let error = test_with_args(vec!["ym", "diff", "--only", "moved", "a.yaml", "b.yaml"]);
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown change kind: moved"));
+    }
+
+    #[test]
+    fn test_parse_strip_comments() {
+        let cmd = test_with_args(vec!["ym", "strip-comments", "config.yaml", "--backup"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::StripComments {
+                file: "config.yaml".to_string(),
+                write: WriteOptions {
+                    backup: Some(".bak".to_string()),
+                    ..WriteOptions::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "template",
+            "--keep-missing",
+            "config.yaml",
+            "app.tmpl",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Template {
+                file: "config.yaml".to_string(),
+                template: "app.tmpl".to_string(),
+                keep_missing: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_scrub() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "scrub",
+            "file.yaml",
+            "--value-pattern",
+            "hunter2",
+            "--dry-run",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Scrub {
+                file: "file.yaml".to_string(),
+                value_pattern: "hunter2".to_string(),
+                dry_run: true,
+                write: WriteOptions::default(),
+            }
+        );
+        assert!(test_with_args(vec!["ym", "scrub", "file.yaml"]).is_err());
+
+        let backup = test_with_args(vec![
+            "ym",
+            "--backup",
+            "scrub",
+            "--value-pattern",
+            "secret",
+            "s.yaml",
+        ]);
+        assert!(matches!(
+            backup.unwrap(),
+            Command::Scrub { write, .. } if write.backup.as_deref() == Some(".bak")
+        ));
+
+        let audit = test_with_args(vec![
+            "ym",
+            "scrub",
+            "--value-pattern",
+            "secret",
+            "s.yaml",
+            "--audit",
+        ]);
+        assert!(matches!(
+            audit.unwrap(),
+            Command::Scrub { write, .. } if write.audit
+        ));
+
+        let stat = test_with_args(vec![
+            "ym",
+            "--stat",
+            "scrub",
+            "--value-pattern",
+            "secret",
+            "s.yaml",
+        ]);
+        assert!(matches!(
+            stat.unwrap(),
+            Command::Scrub { write, .. } if write.stat
+        ));
+    }
+
+    #[test]
+    fn test_parse_rename_keys() {
+        let cmd = test_with_args(vec!["ym", "rename-keys", "file.yaml", "^old_", "new_"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::RenameKeys {
+                file: "file.yaml".to_string(),
+                pattern: "^old_".to_string(),
+                replacement: "new_".to_string(),
+                write: WriteOptions::default(),
+            }
+        );
+        assert!(test_with_args(vec!["ym", "rename-keys", "file.yaml", "^old_"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_run() {
+        let cmd = test_with_args(vec!["ym", "run", "edits.ym", "file.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Run {
+                script: "edits.ym".to_string(),
+                file: "file.yaml".to_string(),
+                write: WriteOptions::default(),
+            }
+        );
+        let apply = test_with_args(vec!["ym", "apply", "edits.ym", "file.yaml"]).unwrap();
+        assert_eq!(apply, cmd);
+    }
+
+    #[test]
+    fn test_parse_set_single_key_value() {
+        let cmd = test_with_args(vec!["ym", "set", "file.yaml", "key=value"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: vec![("key".to_string(), "value".to_string())],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_multiple_key_values() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "key1=value1",
+            "key2=value2",
+            "key3=value3",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: vec![
+                    ("key1".to_string(), "value1".to_string()),
+                    ("key2".to_string(), "value2".to_string()),
+                    ("key3".to_string(), "value3".to_string()),
+                ],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_nested_key_path() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "database.host=localhost",
+            "database.port=5432",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: vec![
+                    ("database.host".to_string(), "localhost".to_string()),
+                    ("database.port".to_string(), "5432".to_string()),
+                ],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_value_with_equals() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "url=http://example.com?param=value",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: vec![(
+                    "url".to_string(),
+                    "http://example.com?param=value".to_string(),
+                )],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_no_file_error() {
+        assert!(test_with_args(vec!["ym", "set"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_no_key_value_error() {
+        assert!(test_with_args(vec!["ym", "set", "file.yaml"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_invalid_key_value_format() {
+        let result = test_with_args(vec!["ym", "set", "file.yaml", "invalid_no_equals"]);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid key=value pair"));
+    }
+
+    #[test]
+    fn test_parse_set_with_merge_json_only() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "--merge-json",
+            r#"{"database":{"host":"x"}}"#,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: Vec::new(),
+                options: SetOptions {
+                    merge_json: Some(r#"{"database":{"host":"x"}}"#.to_string()),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_from_dotenv() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "--from-dotenv",
+            ".env",
+            "--dotenv-prefix",
+            "APP_",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: Vec::new(),
+                options: SetOptions {
+                    from_dotenv: Some(".env".to_string()),
+                    dotenv_prefix: Some("APP_".to_string()),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "set", "file.yaml", "--dotenv-prefix", "APP_"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--dotenv-prefix requires --from-dotenv"));
+    }
+
+    #[test]
+    fn test_parse_set_string_flag() {
+        for flag in ["-s", "--string"] {
+            let cmd = test_with_args(vec!["ym", "set", "file.yaml", "port=8080", flag]).unwrap();
+
+            assert_eq!(
+                cmd,
+                Command::Set {
+                    file: "file.yaml".to_string(),
+                    updates: vec![("port".to_string(), "8080".to_string())],
+                    options: SetOptions {
+                        string: true,
+                        ..SetOptions::default()
+                    },
+                    write: WriteOptions::default(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_set_force_array_flag() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "servers[0]=web",
+            "--force-array",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            cmd,
+            Command::Set { options, .. } if options.force_array
+        ));
+    }
+
+    #[test]
+    fn test_parse_set_when_condition() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "logging.level=debug",
+            "--when",
+            "env==staging",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "file.yaml".to_string(),
+                updates: vec![("logging.level".to_string(), "debug".to_string())],
+                options: SetOptions {
+                    when: Some(Assertion::parse("env==staging").unwrap()),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+
+        let result = test_with_args(vec!["ym", "set", "file.yaml", "a=1", "--when", "env"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_set_with_merge_strategy() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "--merge-json",
+            r#"{"a":1}"#,
+            "--strategy",
+            "base-wins,union",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Set {
                 file: "file.yaml".to_string(),
-                updates: vec![("key".to_string(), "value".to_string())],
+                updates: Vec::new(),
+                options: SetOptions {
+                    merge_json: Some(r#"{"a":1}"#.to_string()),
+                    merge_strategy: MergeStrategy {
+                        conflicts: ConflictStrategy::BaseWins,
+                        sequences: SequenceStrategy::Union,
+                    },
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
+
+        let result = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "a=1",
+            "--strategy",
+            "append",
+        ]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--strategy requires --merge-json"));
     }
 
     #[test]
-    fn test_parse_set_multiple_key_values() {
+    fn test_parse_set_with_comment_prefix() {
         let cmd = test_with_args(vec![
             "ym",
             "set",
             "file.yaml",
-            "key1=value1",
-            "key2=value2",
-            "key3=value3",
+            "x=1",
+            "--comment-prefix",
+            "//",
         ])
         .unwrap();
 
@@ -402,23 +2638,25 @@ mod tests {
             cmd,
             Command::Set {
                 file: "file.yaml".to_string(),
-                updates: vec![
-                    ("key1".to_string(), "value1".to_string()),
-                    ("key2".to_string(), "value2".to_string()),
-                    ("key3".to_string(), "value3".to_string()),
-                ],
+                updates: vec![("x".to_string(), "1".to_string())],
+                options: SetOptions {
+                    comment_prefix: Some("//".to_string()),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
     }
 
     #[test]
-    fn test_parse_set_nested_key_path() {
+    fn test_parse_set_with_normalize_keys() {
         let cmd = test_with_args(vec![
             "ym",
             "set",
+            "--normalize-keys",
+            "kebab",
             "file.yaml",
-            "database.host=localhost",
-            "database.port=5432",
+            "x=1",
         ])
         .unwrap();
 
@@ -426,54 +2664,422 @@ mod tests {
             cmd,
             Command::Set {
                 file: "file.yaml".to_string(),
-                updates: vec![
-                    ("database.host".to_string(), "localhost".to_string()),
-                    ("database.port".to_string(), "5432".to_string()),
-                ],
+                updates: vec![("x".to_string(), "1".to_string())],
+                options: SetOptions {
+                    normalize_keys: Some(KeyStyle::Kebab),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
+
+        let result = test_with_args(vec!["ym", "set", "f.yaml", "x=1", "--normalize-keys", "x"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown key style: x"));
     }
 
     #[test]
-    fn test_parse_set_value_with_equals() {
+    fn test_parse_set_with_append_doc() {
         let cmd = test_with_args(vec![
             "ym",
             "set",
-            "file.yaml",
-            "url=http://example.com?param=value",
+            "--append-doc",
+            "k8s.yaml",
+            "service.name=web",
         ])
         .unwrap();
 
+        assert_eq!(
+            cmd,
+            Command::Set {
+                file: "k8s.yaml".to_string(),
+                updates: vec![("service.name".to_string(), "web".to_string())],
+                options: SetOptions {
+                    append_doc: true,
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_set_with_report() {
+        let cmd = test_with_args(vec!["ym", "set", "file.yaml", "x=1", "--report"]).unwrap();
+
         assert_eq!(
             cmd,
             Command::Set {
                 file: "file.yaml".to_string(),
-                updates: vec![(
-                    "url".to_string(),
-                    "http://example.com?param=value".to_string(),
-                )],
+                updates: vec![("x".to_string(), "1".to_string())],
+                options: SetOptions {
+                    report: true,
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
     }
 
     #[test]
-    fn test_parse_set_no_file_error() {
-        assert!(test_with_args(vec!["ym", "set"]).is_err());
+    fn test_parse_set_with_indent_before_or_after_updates() {
+        let expected = Command::Set {
+            file: "file.yaml".to_string(),
+            updates: vec![("x".to_string(), "1".to_string())],
+            options: SetOptions::default(),
+            write: WriteOptions {
+                indent: Some(4),
+                ..WriteOptions::default()
+            },
+        };
+
+        let global = test_with_args(vec!["ym", "--indent", "4", "set", "file.yaml", "x=1"]);
+        let trailing = test_with_args(vec!["ym", "set", "file.yaml", "x=1", "--indent", "4"]);
+        assert_eq!(global.unwrap(), expected);
+        assert_eq!(trailing.unwrap(), expected);
+
+        let result = test_with_args(vec!["ym", "set", "file.yaml", "x=1", "--indent", "wide"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid value for --indent: wide"));
     }
 
     #[test]
-    fn test_parse_set_no_key_value_error() {
-        assert!(test_with_args(vec!["ym", "set", "file.yaml"]).is_err());
+    fn test_parse_audit_before_or_after_arguments() {
+        let expected = Command::Unset {
+            file: "file.yaml".to_string(),
+            keys: vec!["x".to_string()],
+            options: UnsetOptions::default(),
+            write: WriteOptions {
+                audit: true,
+                ..WriteOptions::default()
+            },
+        };
+
+        let global = test_with_args(vec!["ym", "--audit", "unset", "file.yaml", "x"]);
+        let trailing = test_with_args(vec!["ym", "unset", "file.yaml", "x", "--audit"]);
+        assert_eq!(global.unwrap(), expected);
+        assert_eq!(trailing.unwrap(), expected);
     }
 
     #[test]
-    fn test_parse_set_invalid_key_value_format() {
-        let result = test_with_args(vec!["ym", "set", "file.yaml", "invalid_no_equals"]);
-        assert!(result.is_err());
+    fn test_parse_stat_before_or_after_arguments() {
+        let expected = Command::RenameKeys {
+            file: "file.yaml".to_string(),
+            pattern: "^old_".to_string(),
+            replacement: "new_".to_string(),
+            write: WriteOptions {
+                stat: true,
+                ..WriteOptions::default()
+            },
+        };
+
+        let global = test_with_args(vec![
+            "ym",
+            "--stat",
+            "rename-keys",
+            "file.yaml",
+            "^old_",
+            "new_",
+        ]);
+        assert_eq!(global.unwrap(), expected);
+        let trailing = test_with_args(vec!["ym", "set", "file.yaml", "x=1", "--stat"]);
+        assert!(matches!(trailing.unwrap(), Command::Set { write, .. } if write.stat));
+    }
+
+    #[test]
+    fn test_parse_unset_with_null_out() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "unset",
+            "file.yaml",
+            "db.password",
+            "--null-out",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Unset {
+                file: "file.yaml".to_string(),
+                keys: vec!["db.password".to_string()],
+                options: UnsetOptions {
+                    null_out: true,
+                    ..UnsetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+
+        let error = test_with_args(vec![
+            "ym",
+            "unset",
+            "f.yaml",
+            "a.b",
+            "--null-out",
+            "--prune",
+        ]);
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "CLI error: --null-out cannot be combined with --prune"
+        );
+    }
+
+    #[test]
+    fn test_parse_unset_with_regex() {
+        let result = test_with_args(vec!["ym", "unset", "config.yaml", "secret.*", "--regex"]);
+
+        assert_eq!(
+            result.unwrap(),
+            Command::Unset {
+                file: "config.yaml".to_string(),
+                keys: vec!["secret.*".to_string()],
+                options: UnsetOptions {
+                    regex: true,
+                    ..UnsetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unset_with_prune() {
+        let result = test_with_args(vec!["ym", "unset", "config.yaml", "db.password", "--prune"]);
+
+        assert_eq!(
+            result.unwrap(),
+            Command::Unset {
+                file: "config.yaml".to_string(),
+                keys: vec!["db.password".to_string()],
+                options: UnsetOptions {
+                    prune: true,
+                    ..UnsetOptions::default()
+                },
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unset_with_print_removed() {
+        let expected = Command::Unset {
+            file: "file.yaml".to_string(),
+            keys: vec!["db.password".to_string()],
+            options: UnsetOptions {
+                print_removed: true,
+                ..UnsetOptions::default()
+            },
+            write: WriteOptions::default(),
+        };
+
+        let leading = test_with_args(vec![
+            "ym",
+            "unset",
+            "--print-removed",
+            "file.yaml",
+            "db.password",
+        ]);
+        let trailing = test_with_args(vec![
+            "ym",
+            "unset",
+            "file.yaml",
+            "db.password",
+            "--print-removed",
+        ]);
+        assert_eq!(leading.unwrap(), expected);
+        assert_eq!(trailing.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_backup_with_optional_suffix() {
+        let unset = |backup: &str| Command::Unset {
+            file: "file.yaml".to_string(),
+            keys: vec!["x".to_string()],
+            options: UnsetOptions::default(),
+            write: WriteOptions {
+                backup: Some(backup.to_string()),
+                ..WriteOptions::default()
+            },
+        };
+
+        let global = test_with_args(vec!["ym", "--backup", "unset", "file.yaml", "x"]);
+        let trailing = test_with_args(vec!["ym", "unset", "file.yaml", "x", "--backup"]);
+        let suffix = test_with_args(vec!["ym", "unset", "file.yaml", "x", "--backup=.orig"]);
+        let global_suffix = test_with_args(vec!["ym", "--backup=.orig", "unset", "file.yaml", "x"]);
+        assert_eq!(global.unwrap(), unset(".bak"));
+        assert_eq!(trailing.unwrap(), unset(".bak"));
+        assert_eq!(suffix.unwrap(), unset(".orig"));
+        assert_eq!(global_suffix.unwrap(), unset(".orig"));
+    }
+
+    #[test]
+    fn test_parse_backup_dir_implies_backup() {
+        let unset = |backup: &str| Command::Unset {
+            file: "file.yaml".to_string(),
+            keys: vec!["x".to_string()],
+            options: UnsetOptions::default(),
+            write: WriteOptions {
+                backup: Some(backup.to_string()),
+                backup_dir: Some("backups".to_string()),
+                ..WriteOptions::default()
+            },
+        };
+
+        let global = test_with_args(vec![
+            "ym",
+            "--backup-dir",
+            "backups",
+            "unset",
+            "file.yaml",
+            "x",
+        ]);
+        let trailing = test_with_args(vec![
+            "ym",
+            "unset",
+            "file.yaml",
+            "x",
+            "--backup-dir",
+            "backups",
+            "--backup=.orig",
+        ]);
+        assert_eq!(global.unwrap(), unset(".bak"));
+        assert_eq!(trailing.unwrap(), unset(".orig"));
+    }
+
+    #[test]
+    fn test_parse_exit_code_on_change() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "x=1",
+            "--exit-code-on-change",
+            "10",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Set { write, .. } if write.exit_code_on_change == Some(10)
+        ));
+
+        let cmd = test_with_args(vec![
+            "ym",
+            "unset",
+            "--exit-code-on-change",
+            "3",
+            "file.yaml",
+            "x",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Unset { write, .. } if write.exit_code_on_change == Some(3)
+        ));
+
+        for code in ["0", "256", "ten"] {
+            let result = test_with_args(vec![
+                "ym",
+                "set",
+                "file.yaml",
+                "x=1",
+                "--exit-code-on-change",
+                code,
+            ]);
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains("Invalid value for --exit-code-on-change"));
+        }
+    }
+
+    #[test]
+    fn test_parse_set_with_exec_hook() {
+        let expected = Command::Set {
+            file: "file.yaml".to_string(),
+            updates: vec![("x".to_string(), "1".to_string())],
+            options: SetOptions::default(),
+            write: WriteOptions {
+                exec: Some("yamllint {}".to_string()),
+                ..WriteOptions::default()
+            },
+        };
+
+        let trailing = test_with_args(vec![
+            "ym",
+            "set",
+            "file.yaml",
+            "x=1",
+            "--exec",
+            "yamllint {}",
+        ])
+        .unwrap();
+        let leading = test_with_args(vec![
+            "ym",
+            "set",
+            "--exec",
+            "yamllint {}",
+            "file.yaml",
+            "x=1",
+        ])
+        .unwrap();
+        assert_eq!(trailing, expected);
+        assert_eq!(leading, expected);
+    }
+
+    #[test]
+    fn test_parse_exec_requires_value() {
+        let result = test_with_args(vec!["ym", "unset", "file.yaml", "key", "--exec"]);
         assert!(result
             .unwrap_err()
             .to_string()
-            .contains("Invalid key=value pair"));
+            .contains("--exec requires a value"));
+    }
+
+    #[test]
+    fn test_parse_tojson_with_output_dir() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "tojson",
+            "--output-dir",
+            "out/",
+            "-R",
+            "configs/",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::ToJson {
+                paths: vec!["configs/".to_string()],
+                output_dir: Some("out/".to_string()),
+                recursive: true,
+                ascii: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tojson_with_ascii() {
+        let cmd = test_with_args(vec!["ym", "tojson", "--ascii", "app.yaml"]).unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::ToJson {
+                paths: vec!["app.yaml".to_string()],
+                output_dir: None,
+                recursive: false,
+                ascii: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tojson_requires_path() {
+        assert!(test_with_args(vec!["ym", "tojson"]).is_err());
     }
 
     #[test]
@@ -485,6 +3091,8 @@ mod tests {
             Command::Unset {
                 file: "file.yaml".to_string(),
                 keys: vec!["key".to_string()],
+                options: UnsetOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -498,6 +3106,8 @@ mod tests {
             Command::Unset {
                 file: "file.yaml".to_string(),
                 keys: vec!["key1".to_string(), "key2".to_string(), "key3".to_string()],
+                options: UnsetOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -521,6 +3131,8 @@ mod tests {
                     "database.password".to_string(),
                     "database.username".to_string(),
                 ],
+                options: UnsetOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -553,9 +3165,11 @@ mod tests {
             cmd,
             Command::Cp {
                 source_file: "file.yaml".to_string(),
-                source_key: "source.key".to_string(),
+                source_keys: vec!["source.key".to_string()],
                 dest_file: None,
-                dest_key: Some("dest.key".to_string()),
+                dest_keys: Some(vec!["dest.key".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -568,9 +3182,11 @@ mod tests {
             cmd,
             Command::Cp {
                 source_file: "source.yaml".to_string(),
-                source_key: "mykey".to_string(),
+                source_keys: vec!["mykey".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: Some("mykey".to_string()),
+                dest_keys: Some(vec!["mykey".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -589,9 +3205,11 @@ mod tests {
             cmd,
             Command::Cp {
                 source_file: "source.yaml".to_string(),
-                source_key: "source.key".to_string(),
+                source_keys: vec!["source.key".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: Some("dest.key".to_string()),
+                dest_keys: Some(vec!["dest.key".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -604,9 +3222,11 @@ mod tests {
             cmd,
             Command::Cp {
                 source_file: "source.yaml".to_string(),
-                source_key: "mykey".to_string(),
+                source_keys: vec!["mykey".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: None,
+                dest_keys: None,
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -625,11 +3245,133 @@ mod tests {
             cmd,
             Command::Cp {
                 source_file: "tests/data/config-prod.yaml".to_string(),
-                source_key: "environment".to_string(),
+                source_keys: vec!["environment".to_string()],
                 dest_file: Some("tests/data/config-dev.yaml".to_string()),
-                dest_key: None,
+                dest_keys: None,
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_cp_with_merge() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "cp",
+            "src.yaml:section",
+            "dst.yaml:existing",
+            "--merge",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Cp {
+                source_file: "src.yaml".to_string(),
+                source_keys: vec!["section".to_string()],
+                dest_file: Some("dst.yaml".to_string()),
+                dest_keys: Some(vec!["existing".to_string()]),
+                options: TransferOptions {
+                    merge: true,
+                    ..TransferOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
+
+        let result = test_with_args(vec![
+            "ym",
+            "cp",
+            "--merge",
+            "--with-anchor",
+            "src.yaml:section",
+            "other",
+        ]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("--merge and --with-anchor cannot be used together"));
+    }
+
+    #[test]
+    fn test_parse_cp_with_anchor_flag() {
+        let expected = Command::Cp {
+            source_file: "file.yaml".to_string(),
+            source_keys: vec!["defaults".to_string()],
+            dest_file: None,
+            dest_keys: Some(vec!["service.defaults".to_string()]),
+            options: TransferOptions {
+                with_anchor: true,
+                ..TransferOptions::default()
+            },
+            write: WriteOptions::default(),
+        };
+
+        let before = test_with_args(vec![
+            "ym",
+            "cp",
+            "--with-anchor",
+            "file.yaml:defaults",
+            "service.defaults",
+        ])
+        .unwrap();
+        let after = test_with_args(vec![
+            "ym",
+            "cp",
+            "file.yaml:defaults",
+            "service.defaults",
+            "--with-anchor",
+        ])
+        .unwrap();
+        assert_eq!(before, expected);
+        assert_eq!(after, expected);
+    }
+
+    #[test]
+    fn test_parse_cp_force() {
+        for args in [
+            vec!["ym", "cp", "--force", "a.yaml:x", "b.yaml"],
+            vec!["ym", "cp", "a.yaml:x", "b.yaml", "--force"],
+        ] {
+            let cmd = test_with_args(args).unwrap();
+            assert!(matches!(cmd, Command::Cp { options, .. } if options.force));
+        }
+    }
+
+    #[test]
+    fn test_parse_cp_key_lists() {
+        let cmd = test_with_args(vec!["ym", "cp", "a.yaml:db.host,db.port", "b.yaml"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Cp { source_keys, dest_keys: None, .. }
+                if source_keys == ["db.host", "db.port"]
+        ));
+
+        let cmd = test_with_args(vec!["ym", "mv", "a.yaml:x,y", "b.yaml:new.x,new.y"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Mv { dest_keys: Some(dest_keys), .. } if dest_keys == ["new.x", "new.y"]
+        ));
+
+        let result = test_with_args(vec!["ym", "cp", "a.yaml:x,y", "b.yaml:z"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("cp was given 2 source keys but 1 destination keys"));
+
+        let result = test_with_args(vec!["ym", "cp", "a.yaml:x,,y", "b.yaml"]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Empty key in key list: x,,y"));
+
+        let cmd = test_with_args(vec!["ym", "cp", r"a.yaml:a\,b,c", r"b.yaml:x,y\,z"]).unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Cp { source_keys, dest_keys: Some(dest_keys), .. }
+                if source_keys == [r"a\,b", "c"] && dest_keys == ["x", r"y\,z"]
+        ));
     }
 
     #[test]
@@ -686,9 +3428,38 @@ mod tests {
             cmd,
             Command::Mv {
                 source_file: "file.yaml".to_string(),
-                source_key: "source.key".to_string(),
+                source_keys: vec!["source.key".to_string()],
+                dest_file: None,
+                dest_keys: Some(vec!["dest.key".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_mv_with_same_type_only() {
+        let cmd = test_with_args(vec![
+            "ym",
+            "mv",
+            "file.yaml:source",
+            "target",
+            "--same-type-only",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            cmd,
+            Command::Mv {
+                source_file: "file.yaml".to_string(),
+                source_keys: vec!["source".to_string()],
                 dest_file: None,
-                dest_key: Some("dest.key".to_string()),
+                dest_keys: Some(vec!["target".to_string()]),
+                options: TransferOptions {
+                    same_type_only: true,
+                    ..TransferOptions::default()
+                },
+                write: WriteOptions::default(),
             }
         );
     }
@@ -701,9 +3472,11 @@ mod tests {
             cmd,
             Command::Mv {
                 source_file: "source.yaml".to_string(),
-                source_key: "mykey".to_string(),
+                source_keys: vec!["mykey".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: Some("mykey".to_string()),
+                dest_keys: Some(vec!["mykey".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -722,9 +3495,11 @@ mod tests {
             cmd,
             Command::Mv {
                 source_file: "source.yaml".to_string(),
-                source_key: "source.key".to_string(),
+                source_keys: vec!["source.key".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: Some("dest.key".to_string()),
+                dest_keys: Some(vec!["dest.key".to_string()]),
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
@@ -737,9 +3512,11 @@ mod tests {
             cmd,
             Command::Mv {
                 source_file: "source.yaml".to_string(),
-                source_key: "mykey".to_string(),
+                source_keys: vec!["mykey".to_string()],
                 dest_file: Some("dest.yaml".to_string()),
-                dest_key: None,
+                dest_keys: None,
+                options: TransferOptions::default(),
+                write: WriteOptions::default(),
             }
         );
     }
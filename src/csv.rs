@@ -0,0 +1,35 @@
+/// Renders one CSV record, quoting fields per RFC 4180.
+pub fn record(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .map(|field| quote_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn quote_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_leaves_plain_fields_unquoted() {
+        assert_eq!(record(&["app.yaml", "name", "web"]), "app.yaml,name,web");
+    }
+
+    #[test]
+    fn test_record_quotes_commas_quotes_and_newlines() {
+        assert_eq!(
+            record(&["app.yaml", "motd", "hello, \"world\""]),
+            "app.yaml,motd,\"hello, \"\"world\"\"\""
+        );
+        assert_eq!(record(&["", "text", "a\nb"]), ",text,\"a\nb\"");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::{AppError, AppResult};
+use crate::yaml_ops::string_literal;
+
+/// Parses `KEY=VALUE` lines into `set` updates. Blank lines, `#` comments
+/// and `export ` prefixes are skipped; quoted values, and every value with
+/// `strings` (`set --string`), are rendered as YAML strings so they are
+/// never re-read as numbers, booleans or mappings.
+pub fn parse(contents: &str, strings: bool) -> AppResult<Vec<(String, String)>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(index, line)| {
+            let line = line.trim();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                AppError::message(format!("Invalid dotenv line {}: {line}", index + 1))
+            })?;
+            Ok((key.trim().to_string(), parse_value(value.trim(), strings)))
+        })
+        .collect()
+}
+
+fn parse_value(value: &str, strings: bool) -> String {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let text = unescape(quoted.split_once('"').map_or(quoted, |(text, _)| text));
+        return string_literal(&text);
+    }
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return string_literal(quoted.split_once('\'').map_or(quoted, |(text, _)| text));
+    }
+
+    let value = match value.split_once(" #") {
+        Some((value, _)) => value.trim_end(),
+        None => value,
+    };
+    match strings {
+        true => string_literal(value),
+        false => value.to_string(),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('t')) => unescaped.push('\t'),
+            ('\\', Some(escaped @ ('\\' | '"'))) => unescaped.push(escaped),
+            _ => {
+                unescaped.push(ch);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_handles_quotes_comments_and_export() {
+        let contents = concat!(
+            "# database settings\n",
+            "\n",
+            "export DB_HOST=db.internal # primary\n",
+            "DB_PORT=5432\n",
+            "GREETING=\"hello # not a comment\\nworld\"\n",
+            "ENABLED='true'\n",
+            "URL='http://x:8080/a'\n",
+        );
+
+        assert_eq!(
+            parse(contents, false).unwrap(),
+            vec![
+                ("DB_HOST".to_string(), "db.internal".to_string()),
+                ("DB_PORT".to_string(), "5432".to_string()),
+                (
+                    "GREETING".to_string(),
+                    r#""hello # not a comment\nworld""#.to_string()
+                ),
+                ("ENABLED".to_string(), r#""true""#.to_string()),
+                ("URL".to_string(), r#""http://x:8080/a""#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_with_strings_quotes_unquoted_values() {
+        let contents = "PORT=3 # http\nNAME='web'\n";
+
+        assert_eq!(
+            parse(contents, true).unwrap(),
+            vec![
+                ("PORT".to_string(), r#""3""#.to_string()),
+                ("NAME".to_string(), r#""web""#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_lines_without_equals() {
+        let error = parse("A=1\nBROKEN\n", false).unwrap_err();
+        assert!(error.to_string().contains("Invalid dotenv line 2: BROKEN"));
+    }
+}
@@ -42,6 +42,9 @@ pub enum AppError {
     #[error("Failed to read from stdin: {0}")]
     ReadStdin(#[source] io::Error),
 
+    #[error("Failed to write to stdout: {0}")]
+    WriteStdout(#[source] io::Error),
+
     #[error("Failed to parse YAML {context}: {source}")]
     ParseYaml {
         context: String,
@@ -49,11 +52,51 @@ pub enum AppError {
         source: serde_yaml::Error,
     },
 
+    #[error("Failed to parse JSON {context}: {source}")]
+    ParseJson {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Failed to parse TOML {context}: {source}")]
+    ParseToml {
+        context: String,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Failed to convert YAML {context} to JSON: {source}")]
+    ToJson {
+        context: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Failed to create directory '{path}': {source}")]
+    CreateDir {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
     #[error("Invalid regex pattern: {0}")]
     Regex(#[from] regex::Error),
 
+    #[error("Failed to run hook '{command}': {source}")]
+    RunHook {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+
     #[error("Failed to apply YAML patch: {0}")]
     Patch(String),
+
+    /// Not a failure: the edit changed the file and `--exit-code-on-change`
+    /// asked to report that through this exit code.
+    #[error("File changed (exit code {0})")]
+    Changed(u8),
 }
 
 impl AppError {
@@ -79,6 +122,34 @@ impl AppError {
         }
     }
 
+    pub fn parse_json(context: impl Into<String>, source: serde_json::Error) -> Self {
+        Self::ParseJson {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn parse_toml(context: impl Into<String>, source: toml::de::Error) -> Self {
+        Self::ParseToml {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn to_json(context: impl Into<String>, source: serde_json::Error) -> Self {
+        Self::ToJson {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn create_dir(path: impl Into<String>, source: io::Error) -> Self {
+        Self::CreateDir {
+            path: path.into(),
+            source,
+        }
+    }
+
     pub fn read_file(path: impl Into<String>, source: io::Error) -> Self {
         Self::ReadFile {
             path: path.into(),
@@ -100,6 +171,13 @@ impl AppError {
         }
     }
 
+    pub fn run_hook(command: impl Into<String>, source: io::Error) -> Self {
+        Self::RunHook {
+            command: command.into(),
+            source,
+        }
+    }
+
     pub fn patch(source: impl Into<String>) -> Self {
         Self::Patch(source.into())
     }
@@ -0,0 +1,187 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::error::{AppError, AppResult};
+
+/// The `.gitignore` rules in effect for a directory: those of its own
+/// `.gitignore` after those of the directories above it, so that later
+/// rules win as in git.
+#[derive(Clone, Debug, Default)]
+pub struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    base: PathBuf,
+    pattern: Regex,
+    anchored: bool,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Gitignore {
+    /// Extends these rules with the `.gitignore` of `dir`, if it has one.
+    pub fn descend(&self, dir: &Path) -> AppResult<Self> {
+        let file = dir.join(".gitignore");
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(self.clone()),
+            Err(error) => return Err(AppError::read_file(file.display().to_string(), error)),
+        };
+
+        let mut rules = self.rules.clone();
+        rules.extend(contents.lines().filter_map(|line| Rule::parse(dir, line)));
+        Ok(Self { rules })
+    }
+
+    /// Whether `path` is ignored; the last matching rule decides.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let pattern = glob_regex(line).ok()?;
+
+        Some(Self {
+            base: base.to_path_buf(),
+            pattern,
+            anchored,
+            negated,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+
+        if self.anchored {
+            let relative: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            self.pattern.is_match(&relative.join("/"))
+        } else {
+            path.file_name()
+                .is_some_and(|name| self.pattern.is_match(&name.to_string_lossy()))
+        }
+    }
+}
+
+/// Compiles a gitignore-style glob (`*`, `?`, `**`, `[...]`) matching a
+/// whole name or relative path.
+pub fn glob_regex(glob: &str) -> AppResult<Regex> {
+    Ok(Regex::new(&format!("^{}$", glob_to_regex(glob)))?)
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == '\\' {
+                        regex.push_str("\\\\");
+                        continue;
+                    }
+                    regex.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(base: &str, lines: &str) -> Gitignore {
+        Gitignore {
+            rules: lines
+                .lines()
+                .filter_map(|line| Rule::parse(Path::new(base), line))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_unanchored_patterns_match_names_at_any_depth() {
+        let ignore = rules("/repo", "# build output\ntarget/\n*.generated.yaml\n");
+
+        assert!(ignore.is_ignored(Path::new("/repo/target"), true));
+        assert!(ignore.is_ignored(Path::new("/repo/sub/target"), true));
+        assert!(!ignore.is_ignored(Path::new("/repo/target"), false));
+        assert!(ignore.is_ignored(Path::new("/repo/a/b.generated.yaml"), false));
+        assert!(!ignore.is_ignored(Path::new("/repo/app.yaml"), false));
+    }
+
+    #[test]
+    fn test_anchored_patterns_and_negation() {
+        let ignore = rules(
+            "/repo",
+            "/local.yaml\nconfig/**/secret*.yaml\n!config/keep/secret.yaml\n",
+        );
+
+        assert!(ignore.is_ignored(Path::new("/repo/local.yaml"), false));
+        assert!(!ignore.is_ignored(Path::new("/repo/sub/local.yaml"), false));
+        assert!(ignore.is_ignored(Path::new("/repo/config/secret.yaml"), false));
+        assert!(ignore.is_ignored(Path::new("/repo/config/a/b/secret-db.yaml"), false));
+        assert!(!ignore.is_ignored(Path::new("/repo/config/keep/secret.yaml"), false));
+        assert!(!ignore.is_ignored(Path::new("/other/local.yaml"), false));
+    }
+}
@@ -0,0 +1,129 @@
+use serde_json::{json, Map};
+use serde_yaml::Value;
+
+use crate::error::{AppError, AppResult};
+
+pub struct JsonMatch<'a> {
+    pub file: Option<&'a str>,
+    pub key: &'a str,
+    pub value: &'a Value,
+    pub position: Option<(usize, usize)>,
+}
+
+pub fn to_json(value: &Value, context: &str) -> AppResult<String> {
+    serde_json::to_string_pretty(value).map_err(|error| AppError::to_json(context, error))
+}
+
+/// Renders grep matches as a JSON array of `{file, key, value}` objects,
+/// with `line` and `column` (1-based) when a position is known.
+pub fn grep_results_to_json(matches: &[JsonMatch]) -> AppResult<String> {
+    let results = matches
+        .iter()
+        .map(|result| {
+            let mut object = Map::new();
+            if let Some(file) = result.file {
+                object.insert("file".to_string(), json!(file));
+            }
+            object.insert("key".to_string(), json!(result.key));
+            object.insert(
+                "value".to_string(),
+                serde_json::to_value(result.value)
+                    .map_err(|error| AppError::to_json(format!("at '{}'", result.key), error))?,
+            );
+            if let Some((line, column)) = result.position {
+                object.insert("line".to_string(), json!(line));
+                object.insert("column".to_string(), json!(column));
+            }
+            Ok(serde_json::Value::Object(object))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    serde_json::to_string_pretty(&results).map_err(|error| AppError::to_json("matches", error))
+}
+
+/// Escapes every non-ASCII character as `\uXXXX` (using surrogate pairs
+/// outside the BMP). Such characters only occur inside JSON strings, so the
+/// result stays valid JSON.
+pub fn escape_non_ascii(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let mut units = [0u16; 2];
+
+    for ch in json.chars() {
+        if ch.is_ascii() {
+            escaped.push(ch);
+        } else {
+            for unit in ch.encode_utf16(&mut units) {
+                escaped.push_str(&format!("\\u{unit:04x}"));
+            }
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_converts_nested_values() {
+        let value: Value =
+            serde_yaml::from_str("name: app\nports:\n  - 80\nenabled: true\n").unwrap();
+        assert_eq!(
+            to_json(&value, "in 'app.yaml'").unwrap(),
+            "{\n  \"name\": \"app\",\n  \"ports\": [\n    80\n  ],\n  \"enabled\": true\n}"
+        );
+    }
+
+    #[test]
+    fn test_grep_results_to_json_includes_positions() {
+        let value = Value::String("db".to_string());
+        let matches = [
+            JsonMatch {
+                file: Some("app.yaml"),
+                key: "database.host",
+                value: &value,
+                position: Some((3, 9)),
+            },
+            JsonMatch {
+                file: None,
+                key: "host",
+                value: &value,
+                position: None,
+            },
+        ];
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&grep_results_to_json(&matches).unwrap()).unwrap();
+        assert_eq!(
+            parsed,
+            json!([
+                {"file": "app.yaml", "key": "database.host", "value": "db", "line": 3, "column": 9},
+                {"key": "host", "value": "db"},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_escape_non_ascii() {
+        let value: Value = serde_yaml::from_str("city: Zürich\nemoji: \"🚀\"\n").unwrap();
+        let json = escape_non_ascii(&to_json(&value, "in 'app.yaml'").unwrap());
+        assert_eq!(
+            json,
+            "{\n  \"city\": \"Z\\u00fcrich\",\n  \"emoji\": \"\\ud83d\\ude80\"\n}"
+        );
+
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["city"], "Zürich");
+        assert_eq!(parsed["emoji"], "🚀");
+    }
+
+    #[test]
+    fn test_to_json_rejects_non_string_keys() {
+        let value: Value = serde_yaml::from_str("{[1, 2]: pair}").unwrap();
+        let error = to_json(&value, "in 'app.yaml'").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Failed to convert YAML in 'app.yaml' to JSON"));
+    }
+}
@@ -1,17 +1,40 @@
+use std::collections::HashSet;
 use std::env;
+use std::fmt::Display;
 use std::fs;
-use std::io::{self, Read};
-use std::path::Path;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
+use regex::Regex;
+
+mod assertion;
+mod audit;
 mod cli;
+mod csv;
+mod dotenv;
 mod error;
+mod gitignore;
+mod json;
 mod path;
+mod profile;
+mod script;
+mod stat;
+mod template;
+mod watch;
 mod yaml_ops;
 
-use cli::{parse_cli, Command};
+use cli::{parse_cli, Command, GetOptions, GlobalOptions, GrepOptions, SetOptions, WriteOptions};
 use error::{AppError, AppResult};
-use yaml_ops::GrepOutputMode;
+use gitignore::Gitignore;
+use path::YamlPath;
+use yaml_ops::{ChangeKind, EditOptions, GrepOutputMode, InputFormat, MatchOptions};
+
+const TRUNCATED_NOTICE: &str = "... (truncated)";
+const STREAM_THRESHOLD: usize = 4 * 1024 * 1024;
 
 fn get_terminal_width() -> usize {
     if let Some(size) = termsize::get() {
@@ -36,16 +59,24 @@ fn is_no_matches_error(error: &AppError) -> bool {
 }
 
 fn main() {
-    let command = match parse_cli() {
-        Ok(command) => command,
+    let (command, global) = match parse_cli() {
+        Ok(parsed) => parsed,
         Err(error) => {
             eprintln!("Error: {error}");
             process::exit(1);
         }
     };
 
-    match execute_command(command) {
+    if global.profile {
+        profile::enable();
+    }
+    let result = execute_command(command, &global);
+    // Failing to print the timings must not change the exit status.
+    let _ = write_profile(io::stderr().lock());
+
+    match result {
         Ok(()) => {}
+        Err(AppError::Changed(code)) => process::exit(code.into()),
         Err(error) if is_no_matches_error(&error) => {
             process::exit(2);
         }
@@ -56,324 +87,3188 @@ fn main() {
     }
 }
 
-fn execute_command(command: Command) -> AppResult<()> {
+/// Writes the `--profile` phase timings, one line each.
+fn write_profile(mut out: impl Write) -> io::Result<()> {
+    for line in profile::report() {
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+fn execute_command(command: Command, global: &GlobalOptions) -> AppResult<()> {
+    let preserve_numbers = global.preserve_numbers_as_strings;
     match command {
         Command::Grep {
             pattern,
-            full,
             files,
-        } => run_grep(&pattern, full, &files),
-        Command::Set { file, updates } => {
-            apply_file_update(&file, |contents| yaml_ops::set_values(contents, &updates))
+            options,
+        } => run_grep(&pattern, &files, &options, preserve_numbers),
+        Command::Get { file, key, options } => run_get(&file, &key, &options, preserve_numbers),
+        Command::Set {
+            file,
+            updates,
+            options,
+            write,
+        } => {
+            let updates = if options.string {
+                updates
+                    .into_iter()
+                    .map(|(key, value)| (key, yaml_ops::string_literal(&value)))
+                    .collect()
+            } else {
+                updates
+            };
+            let updates = [dotenv_updates(&options)?, updates].concat();
+            let mut report = Vec::new();
+            let edit_options = EditOptions {
+                force_array: options.force_array,
+                ..edit_options(&write, preserve_numbers)
+            };
+            let changed = mutate_files(&write, "set", &[&file], || {
+                apply_file_update(&file, |contents| {
+                    let mut edit = |contents: &str| {
+                        apply_set(contents, &updates, &options, &edit_options, &mut report)
+                    };
+                    match &options.comment_prefix {
+                        Some(prefix) => yaml_ops::with_comment_prefix(contents, prefix, edit),
+                        None => edit(contents),
+                    }
+                })
+            })?;
+            for (key, change) in report {
+                println!("{}: {key}", change.label());
+            }
+            run_after_write_hook(&write, &[&file])?;
+            exit_code_on_change(&write, changed)
         }
-        Command::Unset { file, keys } => {
-            apply_file_update(&file, |contents| yaml_ops::unset_values(contents, &keys))
+        Command::Unset {
+            file,
+            keys,
+            options,
+            write,
+        } => {
+            let mut removed = Vec::new();
+            let changed = mutate_files(&write, "unset", &[&file], || {
+                apply_file_update(&file, |contents| {
+                    let keys = if options.regex {
+                        keys_matching_patterns(&file, contents, &keys, preserve_numbers)?
+                    } else {
+                        keys.clone()
+                    };
+                    if options.print_removed {
+                        removed = removed_values(&file, contents, &keys, preserve_numbers)?;
+                    }
+                    if options.null_out {
+                        return yaml_ops::null_out_values(contents, &keys);
+                    }
+                    let unset = if options.prune {
+                        yaml_ops::prune_values
+                    } else {
+                        yaml_ops::unset_values
+                    };
+                    unset(contents, &keys)
+                })
+            })?;
+            for value in removed {
+                println!("{value}");
+            }
+            run_after_write_hook(&write, &[&file])?;
+            exit_code_on_change(&write, changed)
         }
+        Command::Keys {
+            file,
+            leaves_only,
+            glob,
+        } => run_keys(&file, leaves_only, glob.as_deref()),
+        Command::Find { file, term } => run_find(&file, &term),
+        Command::Diff {
+            left,
+            right,
+            only,
+            patch,
+        } => run_diff(&left, &right, only, patch, preserve_numbers),
+        Command::Assert { file, expressions } => run_assert(&file, &expressions, preserve_numbers),
+        Command::Template {
+            file,
+            template,
+            keep_missing,
+        } => run_template(&file, &template, keep_missing, preserve_numbers),
+        Command::Scrub {
+            file,
+            value_pattern,
+            dry_run,
+            write,
+        } => run_scrub(&file, &value_pattern, dry_run, &write, preserve_numbers),
         Command::Cp {
             source_file,
-            source_key,
+            source_keys,
             dest_file,
-            dest_key,
+            dest_keys,
+            options,
+            write,
         } => {
             let final_dest_file = dest_file.unwrap_or_else(|| source_file.clone());
-            let final_dest_key = dest_key.unwrap_or_else(|| source_key.clone());
-            yaml_ops::copy_value(&source_file, &source_key, &final_dest_file, &final_dest_key)
+            let keys = transfer_keys(source_keys, dest_keys);
+            mutate_files(&write, "cp", &[&final_dest_file], || {
+                profile::time("copy", || {
+                    yaml_ops::copy_values(
+                        &source_file,
+                        &final_dest_file,
+                        &keys,
+                        &options,
+                        &edit_options(&write, preserve_numbers),
+                    )
+                })
+            })?;
+            run_after_write_hook(&write, &[&final_dest_file])
         }
         Command::Mv {
             source_file,
-            source_key,
+            source_keys,
             dest_file,
-            dest_key,
+            dest_keys,
+            options,
+            write,
         } => {
             let final_dest_file = dest_file.unwrap_or_else(|| source_file.clone());
-            let final_dest_key = dest_key.unwrap_or_else(|| source_key.clone());
-            yaml_ops::move_value(&source_file, &source_key, &final_dest_file, &final_dest_key)
+            let keys = transfer_keys(source_keys, dest_keys);
+            let written: &[&str] = if final_dest_file == source_file {
+                &[&final_dest_file]
+            } else {
+                &[&final_dest_file, &source_file]
+            };
+            mutate_files(&write, "mv", written, || {
+                profile::time("move", || {
+                    yaml_ops::move_values(
+                        &source_file,
+                        &final_dest_file,
+                        &keys,
+                        &options,
+                        &edit_options(&write, preserve_numbers),
+                    )
+                })
+            })?;
+            run_after_write_hook(&write, written)
+        }
+        Command::RenameKeys {
+            file,
+            pattern,
+            replacement,
+            write,
+        } => {
+            let regex = Regex::new(&pattern)?;
+            mutate_files(&write, "rename-keys", &[&file], || {
+                apply_file_update(&file, |contents| {
+                    yaml_ops::rename_keys(contents, &regex, &replacement)
+                })
+            })?;
+            run_after_write_hook(&write, &[&file])
+        }
+        Command::StripComments { file, write } => {
+            mutate_files(&write, "strip-comments", &[&file], || {
+                apply_file_update(&file, yaml_ops::strip_comments)
+            })?;
+            run_after_write_hook(&write, &[&file])
         }
+        Command::Run {
+            script,
+            file,
+            write,
+        } => {
+            let contents =
+                fs::read_to_string(&script).map_err(|error| AppError::read_file(&script, error))?;
+            let script = script::Script::parse(&contents)?;
+            mutate_files(&write, "run", &[&file], || {
+                apply_file_update(&file, |contents| {
+                    script.apply(contents, &edit_options(&write, preserve_numbers))
+                })
+            })?;
+            run_after_write_hook(&write, &[&file])
+        }
+        Command::ToJson {
+            paths,
+            output_dir,
+            recursive,
+            ascii,
+        } => run_to_json(
+            &paths,
+            output_dir.as_deref(),
+            recursive,
+            ascii,
+            preserve_numbers,
+        ),
     }
 }
 
-fn apply_file_update<F>(file: &str, update: F) -> AppResult<()>
-where
-    F: FnOnce(&str) -> AppResult<String>,
-{
-    let contents = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
-    let updated = update(&contents)?;
-    fs::write(file, updated).map_err(|error| AppError::write_file(file, error))?;
+fn run_to_json(
+    paths: &[String],
+    output_dir: Option<&str>,
+    recursive: bool,
+    ascii: bool,
+    preserve_numbers: bool,
+) -> AppResult<()> {
+    for path in paths {
+        let root = Path::new(path);
+        let files = if root.is_dir() {
+            if !recursive {
+                return Err(AppError::message(format!(
+                    "'{path}' is a directory (use -R to convert it recursively)"
+                )));
+            }
+            yaml_files_in(
+                root,
+                &Walk {
+                    include_hidden: true,
+                    recursive: true,
+                    respect_gitignore: false,
+                    follow_symlinks: true,
+                    include: Vec::new(),
+                },
+            )?
+        } else {
+            vec![root.to_path_buf()]
+        };
+
+        for file in files {
+            let json = convert_file_to_json(&file, preserve_numbers)?;
+            let json = if ascii {
+                json::escape_non_ascii(&json)
+            } else {
+                json
+            };
+            match output_dir {
+                Some(output_dir) => {
+                    let relative = file.strip_prefix(root).unwrap_or(&file);
+                    let relative = if relative.as_os_str().is_empty() {
+                        file.file_name().map(Path::new).unwrap_or(&file)
+                    } else {
+                        relative
+                    };
+                    write_json_output(&Path::new(output_dir).join(relative), &json)?;
+                }
+                None => println!("{json}"),
+            }
+        }
+    }
+
     Ok(())
 }
 
-fn run_grep(pattern: &str, full: bool, files: &[String]) -> AppResult<()> {
-    let output_mode = if full {
-        GrepOutputMode::Full
-    } else {
-        GrepOutputMode::Inline
-    };
+fn convert_file_to_json(path: &Path, preserve_numbers: bool) -> AppResult<String> {
+    let display = path.display().to_string();
+    let contents =
+        fs::read_to_string(path).map_err(|error| AppError::read_file(display.as_str(), error))?;
+    let context = format!("in '{display}'");
+    let value = yaml_ops::parse_input(&contents, InputFormat::Yaml, &context, preserve_numbers)?;
+    json::to_json(&value, &format!("in '{display}'"))
+}
 
-    if files.is_empty() {
-        if atty::is(atty::Stream::Stdin) {
-            let files = vec![".".to_string()];
-            return run_grep_files(pattern, &files, output_mode);
-        }
-        return grep_stdin(pattern, output_mode);
+fn write_json_output(target: &Path, json: &str) -> AppResult<()> {
+    let target = target.with_extension("json");
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|error| AppError::create_dir(parent.display().to_string(), error))?;
     }
-
-    run_grep_files(pattern, files, output_mode)
+    fs::write(&target, format!("{json}\n"))
+        .map_err(|error| AppError::write_file(target.display().to_string(), error))
 }
 
-fn run_grep_files(pattern: &str, files: &[String], output_mode: GrepOutputMode) -> AppResult<()> {
-    let show_filename = should_show_filename(files, output_mode);
-    let mut found_any = false;
+/// Pairs each source key with its destination key, which defaults to the
+/// source key itself.
+fn transfer_keys(
+    source_keys: Vec<String>,
+    dest_keys: Option<Vec<String>>,
+) -> Vec<(String, String)> {
+    match dest_keys {
+        Some(dest_keys) => source_keys.into_iter().zip(dest_keys).collect(),
+        None => source_keys
+            .into_iter()
+            .map(|key| (key.clone(), key))
+            .collect(),
+    }
+}
 
-    for file in files {
-        match grep_path(Path::new(file), pattern, show_filename, output_mode) {
-            Ok(()) => found_any = true,
-            Err(error) if is_no_matches_error(&error) => {}
-            Err(error) => return Err(error),
+/// Runs `mutate` over `files`, first saving each one to its `--backup` copy
+/// and afterwards emitting `--audit` records for the keys it changed and
+/// `--stat` line counts.
+/// Returns whether the contents of any of the files changed.
+fn mutate_files(
+    write: &WriteOptions,
+    command: &str,
+    files: &[&str],
+    mutate: impl FnOnce() -> AppResult<()>,
+) -> AppResult<bool> {
+    if let Some(suffix) = &write.backup {
+        for file in files {
+            backup_file(
+                file,
+                &backup_path(file, suffix, write.backup_dir.as_deref()),
+            )?;
         }
     }
 
-    if found_any {
-        Ok(())
-    } else {
-        Err(no_matches_error())
+    let originals: Vec<String> = files
+        .iter()
+        .map(|file| fs::read_to_string(file).unwrap_or_default())
+        .collect();
+    mutate()?;
+
+    let mut changed = false;
+    for (&file, original) in files.iter().zip(originals) {
+        let updated = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+        changed |= updated != original;
+        if write.audit {
+            audit::emit(command, file, &yaml_ops::changed_keys(&original, &updated)?);
+        }
+        if write.stat {
+            println!("{}", stat::summary(file, &original, &updated));
+        }
     }
+
+    Ok(changed)
 }
 
-fn should_show_filename(files: &[String], output_mode: GrepOutputMode) -> bool {
-    if matches!(output_mode, GrepOutputMode::Full) {
-        return true;
+/// The layout `--indent` asks edits to give the YAML they create, and
+/// whether `--preserve-numbers-as-strings` keeps number text.
+fn edit_options(write: &WriteOptions, preserve_numbers: bool) -> EditOptions {
+    EditOptions {
+        indent: write.indent,
+        preserve_numbers,
+        ..EditOptions::default()
     }
+}
 
-    if files.len() != 1 {
-        return true;
+/// Reports an edit that changed the file through the
+/// `--exit-code-on-change` exit code.
+fn exit_code_on_change(write: &WriteOptions, changed: bool) -> AppResult<()> {
+    match write.exit_code_on_change {
+        Some(code) if changed => Err(AppError::Changed(code)),
+        _ => Ok(()),
     }
+}
 
-    Path::new(&files[0]).is_dir()
+/// Where `--backup` copies `file`: `file` + `suffix`, or under
+/// `--backup-dir` with the same relative path, leaving out any root and
+/// `..` parts so the copy stays inside the directory.
+fn backup_path(file: &str, suffix: &str, dir: Option<&str>) -> String {
+    let Some(dir) = dir else {
+        return format!("{file}{suffix}");
+    };
+
+    let relative: PathBuf = Path::new(file)
+        .components()
+        .filter(|part| matches!(part, std::path::Component::Normal(_)))
+        .collect();
+    format!("{}{suffix}", Path::new(dir).join(relative).display())
 }
 
-fn grep_stdin(pattern: &str, output_mode: GrepOutputMode) -> AppResult<()> {
-    let mut buffer = String::new();
-    io::stdin()
-        .read_to_string(&mut buffer)
-        .map_err(AppError::ReadStdin)?;
+/// Copies `file` to `backup`, refusing to overwrite an earlier backup.
+/// Files that do not exist yet have nothing to back up.
+fn backup_file(file: &str, backup: &str) -> AppResult<()> {
+    let contents = match fs::read(file) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(AppError::read_file(file, error)),
+    };
 
-    if buffer.trim().is_empty() {
-        return Err(AppError::message("No input provided"));
+    if let Some(parent) = Path::new(backup).parent() {
+        fs::create_dir_all(parent).map_err(|error| AppError::write_file(backup, error))?;
     }
-
-    let value =
-        serde_yaml::from_str(&buffer).map_err(|error| AppError::parse_yaml("from stdin", error))?;
-    print_grep_results(None, pattern, &value, output_mode)
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(backup)
+        .and_then(|mut out| out.write_all(&contents))
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::AlreadyExists => {
+                AppError::message(format!("Backup '{backup}' already exists"))
+            }
+            _ => AppError::write_file(backup, error),
+        })
 }
 
-fn grep_path(
-    path: &Path,
-    pattern: &str,
-    show_filename: bool,
-    output_mode: GrepOutputMode,
-) -> AppResult<()> {
-    if path.is_file() {
-        return grep_file(path, pattern, show_filename, output_mode);
+fn run_after_write_hook(write: &WriteOptions, files: &[&str]) -> AppResult<()> {
+    let Some(hook) = &write.exec else {
+        return Ok(());
+    };
+
+    for file in files {
+        let status = after_write_hook(hook, file)
+            .status()
+            .map_err(|error| AppError::run_hook(hook, error))?;
+        if !status.success() {
+            return Err(AppError::message(format!(
+                "Hook '{hook}' failed for '{file}' ({status})"
+            )));
+        }
     }
 
-    if path.is_dir() {
-        return search_dir(path, pattern, show_filename, output_mode);
+    Ok(())
+}
+
+/// Builds `sh -c HOOK ym FILE`, where `{}` in the hook (or an appended
+/// argument when there is no `{}`) refers to the file as `$1`.
+fn after_write_hook(hook: &str, file: &str) -> process::Command {
+    let script = if hook.contains("{}") {
+        hook.replace("{}", "\"$1\"")
+    } else {
+        format!("{hook} \"$1\"")
+    };
+
+    let mut command = process::Command::new("sh");
+    command.arg("-c").arg(script).arg("ym").arg(file);
+    command
+}
+
+fn run_get(file: &str, key: &str, options: &GetOptions, preserve_numbers: bool) -> AppResult<()> {
+    let found = get_found(file, key, options, preserve_numbers, io::stdin())?;
+    if options.transforms.is_empty() {
+        println!("{}", yaml_ops::format_value(&found)?);
+    } else {
+        println!(
+            "{}",
+            yaml_ops::transform_value(&found, &options.transforms)?
+        );
     }
+    Ok(())
+}
 
-    Err(AppError::message(format!(
-        "'{}' is not a file or directory",
-        path.display()
-    )))
+/// The value `get` prints, read from `file`, or from `stdin` in the
+/// `--stdin-format` format when `file` is `-`.
+fn get_found(
+    file: &str,
+    key: &str,
+    options: &GetOptions,
+    preserve_numbers: bool,
+    mut stdin: impl Read,
+) -> AppResult<serde_yaml::Value> {
+    let value = if file == "-" {
+        let mut contents = String::new();
+        stdin
+            .read_to_string(&mut contents)
+            .map_err(AppError::ReadStdin)?;
+        yaml_ops::parse_input(
+            &contents,
+            options.stdin_format,
+            "from stdin",
+            preserve_numbers,
+        )?
+    } else {
+        let contents =
+            fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+        let context = format!("in '{file}'");
+        yaml_ops::parse_input(&contents, InputFormat::Yaml, &context, preserve_numbers)?
+    };
+
+    let found = if options.pointer {
+        yaml_ops::get_pointer(&value, key)?
+    } else {
+        yaml_ops::get_value(&value, key)?
+    };
+    found.ok_or_else(|| AppError::message(format!("Key '{key}' not found in '{file}'")))
 }
 
-fn grep_file(
-    path: &Path,
-    pattern: &str,
-    show_filename: bool,
-    output_mode: GrepOutputMode,
-) -> AppResult<()> {
-    let display = path.to_string_lossy();
-    let contents =
-        fs::read_to_string(path).map_err(|error| AppError::read_file(display.as_ref(), error))?;
-    let value = serde_yaml::from_str(&contents)
-        .map_err(|error| AppError::parse_yaml(format!("in '{display}'"), error))?;
-
-    print_grep_results(
-        show_filename.then_some(display.as_ref()),
-        pattern,
-        &value,
-        output_mode,
-    )
+/// Every key path matching one of `patterns`, last match first so removing
+/// a sequence item does not shift the indices of the matches still to go.
+/// As in `grep`, a matched key hides the keys below it, so removing the
+/// matches never touches a key twice.
+fn keys_matching_patterns(
+    file: &str,
+    contents: &str,
+    patterns: &[String],
+    preserve_numbers: bool,
+) -> AppResult<Vec<String>> {
+    let context = format!("in '{file}'");
+    let document = yaml_ops::parse_input(contents, InputFormat::Yaml, &context, preserve_numbers)?;
+    let options = MatchOptions::default();
+    let pattern = patterns
+        .iter()
+        .map(|pattern| format!("(?:{pattern})"))
+        .collect::<Vec<_>>()
+        .join("|");
+    let regex = yaml_ops::compile_pattern(&pattern, &options)?;
+    Ok(yaml_ops::grep(&document, &regex, &options)
+        .into_iter()
+        .map(|(key, _)| key)
+        .rev()
+        .collect())
 }
 
-fn print_grep_results(
-    filename: Option<&str>,
-    pattern: &str,
-    value: &serde_yaml::Value,
-    output_mode: GrepOutputMode,
-) -> AppResult<()> {
-    let results = yaml_ops::grep(value, pattern)?;
-    if results.is_empty() {
-        return Err(no_matches_error());
+/// Formats the current value of each of `keys` that exists, in order, so
+/// `unset --print-removed` can show what it deleted.
+fn removed_values(
+    file: &str,
+    contents: &str,
+    keys: &[String],
+    preserve_numbers: bool,
+) -> AppResult<Vec<String>> {
+    let context = format!("in '{file}'");
+    let document = yaml_ops::parse_input(contents, InputFormat::Yaml, &context, preserve_numbers)?;
+    let mut removed = Vec::new();
+    for key in keys {
+        if let Some(value) = yaml_ops::get_value(&document, key)? {
+            removed.push(yaml_ops::format_value(&value)?);
+        }
     }
+    Ok(removed)
+}
 
-    let width = get_terminal_width();
+fn run_assert(file: &str, expressions: &[String], preserve_numbers: bool) -> AppResult<()> {
+    let assertions = expressions
+        .iter()
+        .map(|expression| assertion::Assertion::parse(expression))
+        .collect::<AppResult<Vec<_>>>()?;
+    let contents = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+    let context = format!("in '{file}'");
+    let document = yaml_ops::parse_input(&contents, InputFormat::Yaml, &context, preserve_numbers)?;
 
-    for (key, value) in results {
-        print_grep_result(filename, &key, &value, width, output_mode);
+    let mut failures = 0;
+    for (expression, assertion) in expressions.iter().zip(&assertions) {
+        match assertion.evaluate(&document)? {
+            Ok(detail) => println!("ok: {expression} ({detail})"),
+            Err(detail) => {
+                println!("FAILED: {expression} ({detail})");
+                failures += 1;
+            }
+        }
     }
 
+    if failures > 0 {
+        return Err(AppError::message(format!(
+            "{failures} of {} assertions failed in '{file}'",
+            assertions.len()
+        )));
+    }
     Ok(())
 }
 
-fn print_grep_result(
-    filename: Option<&str>,
-    key: &str,
-    value: &serde_yaml::Value,
-    width: usize,
-    output_mode: GrepOutputMode,
-) {
-    let formatted = yaml_ops::format_result(key, value, width, output_mode);
+fn run_keys(file: &str, leaves_only: bool, glob: Option<&str>) -> AppResult<()> {
+    let contents = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+    let document =
+        yaml_ops::parse_input(&contents, InputFormat::Yaml, &format!("in '{file}'"), false)?;
 
-    match (filename, output_mode) {
-        (Some(filename), GrepOutputMode::Inline) => println!("{filename}:{formatted}"),
-        (Some(filename), GrepOutputMode::Full) => println!("--- {filename} ---\n{formatted}"),
-        (None, _) => println!("{formatted}"),
+    for key in matching_keys(&document, leaves_only, glob)? {
+        println!("{key}");
     }
+    Ok(())
 }
 
-fn search_dir(
-    dir: &Path,
-    pattern: &str,
-    show_filename: bool,
-    output_mode: GrepOutputMode,
+/// The key paths `keys` lists, keeping only those the whole of `glob`
+/// matches when given; `*` crosses dots, so `database.*` is a subtree.
+fn matching_keys(
+    document: &serde_yaml::Value,
+    leaves_only: bool,
+    glob: Option<&str>,
+) -> AppResult<Vec<String>> {
+    let glob = glob.map(gitignore::glob_regex).transpose()?;
+    Ok(yaml_ops::list_keys(document, leaves_only)
+        .into_iter()
+        .filter(|key| glob.as_ref().is_none_or(|glob| glob.is_match(key)))
+        .collect())
+}
+
+fn run_diff(
+    left: &str,
+    right: &str,
+    only: Option<ChangeKind>,
+    patch: bool,
+    preserve_numbers: bool,
 ) -> AppResult<()> {
-    let entries =
-        fs::read_dir(dir).map_err(|error| AppError::read_dir(dir.display().to_string(), error))?;
+    let read =
+        |file: &str| fs::read_to_string(file).map_err(|error| AppError::read_file(file, error));
 
-    let mut found_any = false;
+    for line in diff_lines(&read(left)?, &read(right)?, only, patch, preserve_numbers)? {
+        println!("{line}");
+    }
+    Ok(())
+}
 
-    for entry in entries {
-        let entry = entry.map_err(AppError::ReadDirEntry)?;
-        let path = entry.path();
+/// The `diff` output lines, restricted to one kind of change with `--only`,
+/// or with `patch` the `run` script turning `left` into `right`.
+fn diff_lines(
+    left: &str,
+    right: &str,
+    only: Option<ChangeKind>,
+    patch: bool,
+    preserve_numbers: bool,
+) -> AppResult<Vec<String>> {
+    let changes: Vec<_> = yaml_ops::value_changes(left, right, preserve_numbers)?
+        .into_iter()
+        .filter(|change| only.is_none_or(|kind| change.kind() == kind))
+        .collect();
+    Ok(match patch {
+        true => patch_lines(&changes),
+        false => changes.iter().map(format_change).collect(),
+    })
+}
 
-        if path.is_dir() {
-            match search_dir(&path, pattern, show_filename, output_mode) {
-                Ok(()) => found_any = true,
-                Err(error) if is_no_matches_error(&error) => {}
-                Err(error) => return Err(error),
-            }
-        } else if path.is_file() && should_process_file(&path) {
-            match grep_file(&path, pattern, show_filename, output_mode) {
-                Ok(()) => found_any = true,
-                Err(error) if is_no_matches_error(&error) => {}
-                Err(error) => return Err(error),
-            }
-        }
+/// A `set key=value` line per added or changed key, the value written as
+/// JSON so that it reads back as the same YAML value, then an `unset key`
+/// line per removed key. Removals come last and in reverse, so removing a
+/// sequence item does not shift the items still to be removed.
+fn patch_lines(changes: &[yaml_ops::ValueChange]) -> Vec<String> {
+    let sets = changes.iter().filter_map(|change| {
+        let value = change.after.as_ref()?;
+        let value =
+            serde_json::to_string(value).unwrap_or_else(|_| yaml_ops::format_inline_value(value));
+        Some(format!("set {}={value}", change.key))
+    });
+    let unsets = changes
+        .iter()
+        .rev()
+        .filter(|change| change.after.is_none())
+        .map(|change| format!("unset {}", change.key));
+    sets.chain(unsets).collect()
+}
+
+fn format_change(change: &yaml_ops::ValueChange) -> String {
+    let key = &change.key;
+    match (&change.before, &change.after) {
+        (Some(before), Some(after)) => format!(
+            "~ {key}: {} -> {}",
+            yaml_ops::format_inline_value(before),
+            yaml_ops::format_inline_value(after)
+        ),
+        (Some(before), None) => format!("- {key}: {}", yaml_ops::format_inline_value(before)),
+        (None, Some(after)) => format!("+ {key}: {}", yaml_ops::format_inline_value(after)),
+        (None, None) => unreachable!("a change has a value on at least one side"),
     }
+}
 
-    if found_any {
-        Ok(())
-    } else {
-        Err(no_matches_error())
+fn run_find(file: &str, term: &str) -> AppResult<()> {
+    let contents = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+    let document =
+        yaml_ops::parse_input(&contents, InputFormat::Yaml, &format!("in '{file}'"), false)?;
+
+    let keys = yaml_ops::find_keys(&document, term);
+    if keys.is_empty() {
+        return Err(no_matches_error());
     }
+    for key in keys {
+        println!("{key}");
+    }
+    Ok(())
 }
 
-fn should_process_file(path: &Path) -> bool {
-    matches!(
-        path.extension().and_then(|ext| ext.to_str()),
-        Some("yaml" | "yml")
-    )
+fn run_template(
+    file: &str,
+    template: &str,
+    keep_missing: bool,
+    preserve_numbers: bool,
+) -> AppResult<()> {
+    let contents = fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+    let context = format!("in '{file}'");
+    let document = yaml_ops::parse_input(&contents, InputFormat::Yaml, &context, preserve_numbers)?;
+    let template =
+        fs::read_to_string(template).map_err(|error| AppError::read_file(template, error))?;
+
+    print!("{}", template::render(&template, &document, keep_missing)?);
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_yaml::Value;
-    use std::time::{SystemTime, UNIX_EPOCH};
+fn run_scrub(
+    file: &str,
+    value_pattern: &str,
+    dry_run: bool,
+    write: &WriteOptions,
+    preserve_numbers: bool,
+) -> AppResult<()> {
+    let regex = Regex::new(value_pattern)?;
+    let matching_keys = |contents: &str| -> AppResult<Vec<String>> {
+        let context = format!("in '{file}'");
+        let value = yaml_ops::parse_input(contents, InputFormat::Yaml, &context, preserve_numbers)?;
+        Ok(yaml_ops::keys_with_matching_values(&value, &regex))
+    };
 
-    fn temp_dir(name: &str) -> std::path::PathBuf {
-        let unique = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let dir = std::env::temp_dir().join(format!("ym_{}_{}_{}", name, process::id(), unique));
-        fs::create_dir_all(&dir).unwrap();
-        dir
+    if dry_run {
+        let contents =
+            fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+        for key in matching_keys(&contents)? {
+            println!("{key}");
+        }
+        return Ok(());
     }
 
-    fn read_yaml(path: &Path) -> Value {
-        serde_yaml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    mutate_files(write, "scrub", &[file], || {
+        apply_file_update(file, |contents| {
+            yaml_ops::unset_values(contents, &matching_keys(contents)?)
+        })
+    })?;
+    run_after_write_hook(write, &[file])
+}
+
+fn apply_set(
+    contents: &str,
+    updates: &[(String, String)],
+    options: &SetOptions,
+    edit: &EditOptions,
+    report: &mut Vec<(String, yaml_ops::KeyChange)>,
+) -> AppResult<String> {
+    if let Some(condition) = &options.when {
+        let document = yaml_ops::parse_input(
+            contents,
+            InputFormat::Yaml,
+            "for --when",
+            edit.preserve_numbers,
+        )?;
+        if condition.evaluate(&document)?.is_err() {
+            return Ok(contents.to_string());
+        }
+    }
+    if !options.append_doc {
+        return edit_document(contents, updates, options, edit, report);
     }
 
-    #[test]
-    fn test_set_and_unset_commands_update_yaml_semantics() {
-        let dir = temp_dir("set_unset");
-        let file = dir.join("config.yaml");
-        fs::write(
-            &file,
-            "# config\nname  : \"Alice\"   # keep quote/comment\nstatus: enabled\ncount: 1\n",
-        )
-        .unwrap();
+    let document =
+        yaml_ops::new_document(|seed| edit_document(seed, updates, options, edit, report))?;
+    Ok(yaml_ops::append_document(contents, &document))
+}
 
-        execute_command(Command::Set {
-            file: file.display().to_string(),
-            updates: vec![
-                ("name".to_string(), "Bob".to_string()),
-                ("count".to_string(), "2".to_string()),
-            ],
-        })
-        .unwrap();
+/// Reads `--from-dotenv` into updates applied before the command line ones,
+/// keeping only keys with `--dotenv-prefix` (which is stripped) when given.
+fn dotenv_updates(options: &SetOptions) -> AppResult<Vec<(String, String)>> {
+    let Some(path) = &options.from_dotenv else {
+        return Ok(Vec::new());
+    };
 
-        let updated = read_yaml(&file);
-        assert_eq!(updated["name"].as_str(), Some("Bob"));
-        assert_eq!(updated["status"].as_str(), Some("enabled"));
-        assert_eq!(updated["count"].as_i64(), Some(2));
+    let contents = fs::read_to_string(path).map_err(|error| AppError::read_file(path, error))?;
+    let updates = dotenv::parse(&contents, options.string)?;
+    Ok(match &options.dotenv_prefix {
+        Some(prefix) => updates
+            .into_iter()
+            .filter_map(|(key, value)| {
+                Some((key.strip_prefix(prefix.as_str())?.to_string(), value))
+            })
+            .collect(),
+        None => updates,
+    })
+}
 
-        execute_command(Command::Unset {
-            file: file.display().to_string(),
-            keys: vec!["count".to_string()],
-        })
-        .unwrap();
+fn edit_document(
+    contents: &str,
+    updates: &[(String, String)],
+    options: &SetOptions,
+    edit: &EditOptions,
+    report: &mut Vec<(String, yaml_ops::KeyChange)>,
+) -> AppResult<String> {
+    let merged = match &options.merge_json {
+        Some(merge_json) => {
+            let overlay = serde_json::from_str(merge_json)
+                .map_err(|error| AppError::parse_json("from --merge-json", error))?;
+            yaml_ops::deep_merge(contents, &overlay, &options.merge_strategy, edit)?
+        }
+        None => contents.to_string(),
+    };
 
-        let updated = read_yaml(&file);
-        assert!(updated.get("count").is_none());
+    let updates = match options.normalize_keys {
+        Some(style) => updates
+            .iter()
+            .map(|(key, value)| {
+                Ok((
+                    yaml_ops::normalize_new_keys(&merged, key, style)?,
+                    value.clone(),
+                ))
+            })
+            .collect::<AppResult<Vec<_>>>()?,
+        None => updates.to_vec(),
+    };
 
-        fs::remove_dir_all(dir).unwrap();
+    let updated = yaml_ops::set_values(&merged, &updates, edit)?;
+    if options.report {
+        let keys: Vec<_> = updates.iter().map(|(key, _)| key.clone()).collect();
+        *report = yaml_ops::classify_changes(contents, &updated, &keys, edit.preserve_numbers)?;
     }
+    Ok(updated)
+}
 
-    #[test]
-    fn test_cp_command_copies_value_between_files() {
-        let dir = temp_dir("cp_semantics");
-        let source = dir.join("source.yaml");
-        let dest = dir.join("dest.yaml");
+fn apply_file_update<F>(file: &str, update: F) -> AppResult<()>
+where
+    F: FnOnce(&str) -> AppResult<String>,
+{
+    let contents = profile::time("read", || fs::read_to_string(file))
+        .map_err(|error| AppError::read_file(file, error))?;
+    let updated = profile::time("edit", || update(&contents))?;
+    profile::time("write", || fs::write(file, updated))
+        .map_err(|error| AppError::write_file(file, error))?;
+    Ok(())
+}
 
-        fs::write(&source, "source:\n  value: copied\n  enabled: true\n").unwrap();
-        fs::write(&dest, "existing: item\n").unwrap();
+struct GrepContext<'a> {
+    regex: Regex,
+    options: &'a GrepOptions,
+    output_mode: GrepOutputMode,
+    show_filename: bool,
+    preserve_numbers: bool,
+}
 
-        execute_command(Command::Cp {
-            source_file: source.display().to_string(),
-            source_key: "source".to_string(),
-            dest_file: Some(dest.display().to_string()),
-            dest_key: Some("copied.settings".to_string()),
-        })
-        .unwrap();
+#[derive(Debug)]
+struct FileMatches {
+    path: Option<String>,
+    results: Vec<(String, serde_yaml::Value)>,
+    positions: Vec<Option<(usize, usize)>>,
+    depths: Vec<usize>,
+}
 
-        let dest_yaml = read_yaml(&dest);
-        assert_eq!(
-            dest_yaml["copied"]["settings"]["value"].as_str(),
-            Some("copied")
-        );
-        assert_eq!(
-            dest_yaml["copied"]["settings"]["enabled"].as_bool(),
-            Some(true)
-        );
+fn run_grep(
+    pattern: &str,
+    files: &[String],
+    options: &GrepOptions,
+    preserve_numbers: bool,
+) -> AppResult<()> {
+    let output_mode = if options.full {
+        GrepOutputMode::Full
+    } else {
+        GrepOutputMode::Inline
+    };
+    let regex = yaml_ops::compile_pattern(pattern, &options.matching)?;
 
-        fs::remove_dir_all(dir).unwrap();
+    let stdin_files;
+    let files = if options.files_from_stdin {
+        stdin_files = [files, &paths_from_reader(io::stdin())?].concat();
+        if stdin_files.is_empty() {
+            return Err(AppError::message("No file paths provided on stdin"));
+        }
+        &stdin_files[..]
+    } else {
+        files
+    };
+
+    if files.is_empty() && !atty::is(atty::Stream::Stdin) {
+        let context = GrepContext {
+            regex,
+            options,
+            output_mode,
+            show_filename: false,
+            preserve_numbers,
+        };
+        if options.watch {
+            return Err(AppError::message("--watch needs files to watch, not stdin"));
+        }
+        let matches = grep_reader(&context, io::stdin())?;
+        return profile::time("output", || {
+            write_report(&context, &[matches], io::stdout().lock())
+        });
     }
 
-    #[test]
-    fn test_mv_command_moves_value_between_files() {
+    let default_files = [".".to_string()];
+    let files = if files.is_empty() {
+        &default_files[..]
+    } else {
+        files
+    };
+    let context = GrepContext {
+        regex,
+        options,
+        output_mode,
+        show_filename: if options.merged {
+            options.with_source
+        } else {
+            should_show_filename(files, output_mode)
+        },
+        preserve_numbers,
+    };
+
+    if options.watch {
+        let walk = grep_walk(options)?;
+        let changes = watch::file_changes(|| watched_files(files, &walk))?;
+        return watch::run(changes, || {
+            // A half-written file must not end the watch.
+            match search_and_report(&context, files, io::stdout(), io::stderr()) {
+                Err(error) if !is_no_matches_error(&error) => eprintln!("Error: {error}"),
+                _ => {}
+            }
+        });
+    }
+    search_and_report(&context, files, io::stdout(), io::stderr())
+}
+
+/// Greps `files`, writing the report to `out` and the `--max-results`
+/// notice to `err`.
+fn search_and_report(
+    context: &GrepContext,
+    files: &[String],
+    out: impl Write,
+    mut err: impl Write,
+) -> AppResult<()> {
+    let mut matches = if context.options.merged {
+        grep_merged(context, files)?
+    } else {
+        collect_matches(context, files)?
+    };
+    let truncated = context
+        .options
+        .max_results
+        .is_some_and(|max| truncate_matches(&mut matches, max));
+    profile::time("output", || write_report(context, &matches, out))?;
+    if truncated {
+        // Like eprintln!, without panicking when stderr is closed.
+        let _ = writeln!(err, "{TRUNCATED_NOTICE}");
+    }
+    Ok(())
+}
+
+/// True once more than `--max-results` matches were collected, so the
+/// search can stop early while still knowing output was cut short.
+fn limit_reached(context: &GrepContext, matches: &[FileMatches]) -> bool {
+    context
+        .options
+        .max_results
+        .is_some_and(|max| matches.iter().map(|file| file.results.len()).sum::<usize>() > max)
+}
+
+fn truncate_matches(matches: &mut [FileMatches], max: usize) -> bool {
+    let mut remaining = max;
+    let mut truncated = false;
+
+    for file in matches {
+        if file.results.len() > remaining {
+            file.results.truncate(remaining);
+            file.positions.truncate(remaining);
+            truncated = true;
+        }
+        remaining -= file.results.len();
+    }
+
+    truncated
+}
+
+/// Splits NUL-separated paths, as produced by `find -print0`.
+fn paths_from_reader(mut reader: impl Read) -> AppResult<Vec<String>> {
+    let mut buffer = Vec::new();
+    reader
+        .read_to_end(&mut buffer)
+        .map_err(AppError::ReadStdin)?;
+
+    Ok(buffer
+        .split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .collect())
+}
+
+fn should_show_filename(files: &[String], output_mode: GrepOutputMode) -> bool {
+    if matches!(output_mode, GrepOutputMode::Full) {
+        return true;
+    }
+
+    if files.len() != 1 {
+        return true;
+    }
+
+    Path::new(&files[0]).is_dir()
+}
+
+fn grep_reader(context: &GrepContext, mut reader: impl Read) -> AppResult<FileMatches> {
+    let mut buffer = String::new();
+    profile::time("read", || reader.read_to_string(&mut buffer)).map_err(AppError::ReadStdin)?;
+
+    if buffer.trim().is_empty() {
+        return Err(AppError::message("No input provided"));
+    }
+
+    let documents = profile::time("parse", || {
+        yaml_ops::parse_input_documents(
+            &buffer,
+            context.options.stdin_format,
+            "from stdin",
+            context.preserve_numbers,
+        )
+    })?;
+    profile::time("match", || file_matches(context, None, &buffer, &documents))
+}
+
+/// Greps every file reachable from `files`, visiting each canonical path
+/// once even when roots overlap.
+fn collect_matches(context: &GrepContext, files: &[String]) -> AppResult<Vec<FileMatches>> {
+    let mut matches = Vec::new();
+    let mut visited = HashSet::new();
+
+    for file in files {
+        if limit_reached(context, &matches) {
+            break;
+        }
+        grep_path(context, Path::new(file), &mut matches, &mut visited)?;
+    }
+
+    Ok(matches)
+}
+
+/// Greps `files` deep-merged in order into one document. With
+/// `--with-source`, each match is reported under the file that wrote its key
+/// into the merged document, so consecutive matches from one file are grouped.
+fn grep_merged(context: &GrepContext, files: &[String]) -> AppResult<Vec<FileMatches>> {
+    let sources = files
+        .iter()
+        .map(|file| {
+            let contents =
+                fs::read_to_string(file).map_err(|error| AppError::read_file(file, error))?;
+            let document = yaml_ops::parse_input(
+                &contents,
+                InputFormat::Yaml,
+                &format!("in '{file}'"),
+                context.preserve_numbers,
+            )?;
+            Ok((file.as_str(), contents, document))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    let Some(((_, base, _), overlays)) = sources.split_first() else {
+        return Ok(Vec::new());
+    };
+    // Each path written by an overlay, with the index of its source file.
+    let mut written = Vec::new();
+    let mut merged = base.clone();
+    for (index, (_, _, overlay)) in overlays.iter().enumerate() {
+        let (updated, paths) = yaml_ops::deep_merge_with_paths(
+            &merged,
+            overlay,
+            &yaml_ops::MergeStrategy::default(),
+            &EditOptions::default(),
+        )?;
+        merged = updated;
+        written.extend(paths.into_iter().map(|path| (path, index + 1)));
+    }
+    let document = yaml_ops::parse_input(
+        &merged,
+        InputFormat::Yaml,
+        "in merged document",
+        context.preserve_numbers,
+    )?;
+    let matches = file_matches(context, None, &merged, &[document])?;
+    if !context.options.with_source || context.options.depth_histogram {
+        return Ok(vec![matches]);
+    }
+
+    let mut grouped: Vec<FileMatches> = Vec::new();
+    for (key, value) in matches.results {
+        let (file, contents, _) = &sources[merged_source(&written, &key)];
+
+        let group = match grouped.last_mut() {
+            Some(group) if group.path.as_deref() == Some(*file) => group,
+            _ => {
+                grouped.push(FileMatches {
+                    path: Some(file.to_string()),
+                    results: Vec::new(),
+                    positions: Vec::new(),
+                    depths: Vec::new(),
+                });
+                grouped.last_mut().unwrap()
+            }
+        };
+        if context.options.with_position {
+            group
+                .positions
+                .push(yaml_ops::value_position(contents, &key)?);
+        }
+        group.results.push((key, value));
+    }
+    Ok(grouped)
+}
+
+/// The index of the file that last wrote `key` or one of its ancestors, per
+/// the `written` paths of a merge; keys no overlay wrote come from the base.
+fn merged_source(written: &[(YamlPath, usize)], key: &str) -> usize {
+    let Ok(key) = YamlPath::parse(key) else {
+        return 0;
+    };
+    written
+        .iter()
+        .rev()
+        .find(|(path, _)| key.as_segments().starts_with(path.as_segments()))
+        .map_or(0, |(_, index)| *index)
+}
+
+fn first_visit(visited: &mut HashSet<PathBuf>, path: &Path) -> bool {
+    visited.insert(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+fn grep_path(
+    context: &GrepContext,
+    path: &Path,
+    matches: &mut Vec<FileMatches>,
+    visited: &mut HashSet<PathBuf>,
+) -> AppResult<()> {
+    if path.is_file() {
+        if first_visit(visited, path) {
+            matches.push(grep_file(context, path)?);
+        }
+        return Ok(());
+    }
+
+    if path.is_dir() {
+        return search_dir(context, path, matches, visited);
+    }
+
+    Err(AppError::message(format!(
+        "'{}' is not a file or directory",
+        path.display()
+    )))
+}
+
+fn grep_file(context: &GrepContext, path: &Path) -> AppResult<FileMatches> {
+    let display = path.to_string_lossy();
+    let contents = profile::time("read", || fs::read_to_string(path))
+        .map_err(|error| AppError::read_file(display.as_ref(), error))?;
+    if should_stream(context, &contents) {
+        let per_document = profile::time("match", || {
+            yaml_ops::grep_stream(
+                &contents,
+                &context.regex,
+                &context.options.matching,
+                &format!("in '{display}'"),
+            )
+        })?;
+        return document_matches(context, Some(display.into_owned()), &contents, per_document);
+    }
+    let documents = profile::time("parse", || {
+        yaml_ops::parse_input_documents(
+            &contents,
+            InputFormat::Yaml,
+            &format!("in '{display}'"),
+            context.preserve_numbers,
+        )
+    })?;
+
+    profile::time("match", || {
+        file_matches(context, Some(display.into_owned()), &contents, &documents)
+    })
+}
+
+/// Large files are matched while parsing rather than through a full
+/// document tree; `--tag`, `--depth-histogram` and preserving number text
+/// still need the tree.
+fn should_stream(context: &GrepContext, contents: &str) -> bool {
+    contents.len() >= STREAM_THRESHOLD
+        && context.options.tag.is_none()
+        && !context.options.depth_histogram
+        && !context.preserve_numbers
+}
+
+/// Matches every document of a file. Keys from multi-document files are
+/// prefixed with the document index, as in `[1]name`, and have no position.
+fn file_matches(
+    context: &GrepContext,
+    path: Option<String>,
+    contents: &str,
+    documents: &[serde_yaml::Value],
+) -> AppResult<FileMatches> {
+    if context.options.depth_histogram {
+        let mut depths = Vec::new();
+        for value in documents {
+            add_depths(
+                &mut depths,
+                &yaml_ops::depth_histogram(value, &context.regex),
+            );
+        }
+        return Ok(FileMatches {
+            path,
+            results: Vec::new(),
+            positions: Vec::new(),
+            depths,
+        });
+    }
+
+    let per_document = match (documents, &context.options.tag) {
+        ([value], Some(tag)) => vec![yaml_ops::grep_tagged(
+            contents,
+            value,
+            &context.regex,
+            &context.options.matching,
+            tag,
+        )?],
+        (_, Some(_)) => {
+            return Err(AppError::message(
+                "--tag does not support multi-document files",
+            ))
+        }
+        (_, None) => documents
+            .iter()
+            .map(|value| yaml_ops::grep(value, &context.regex, &context.options.matching))
+            .collect(),
+    };
+    document_matches(context, path, contents, per_document)
+}
+
+/// Gathers the matches found in each document of a file, prefixing keys
+/// with the document index when there is more than one.
+fn document_matches(
+    context: &GrepContext,
+    path: Option<String>,
+    contents: &str,
+    per_document: Vec<Vec<(String, serde_yaml::Value)>>,
+) -> AppResult<FileMatches> {
+    let single = per_document.len() == 1;
+    let results: Vec<_> = if single {
+        per_document.into_iter().flatten().collect()
+    } else {
+        per_document
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, results)| {
+                results
+                    .into_iter()
+                    .map(move |(key, value)| (format!("[{index}]{key}"), value))
+            })
+            .collect()
+    };
+    let positions = match single {
+        _ if !context.options.with_position => Vec::new(),
+        true => results
+            .iter()
+            .map(|(key, _)| yaml_ops::value_position(contents, key))
+            .collect::<AppResult<_>>()?,
+        false => vec![None; results.len()],
+    };
+
+    Ok(FileMatches {
+        path,
+        results,
+        positions,
+        depths: Vec::new(),
+    })
+}
+
+/// Writes the whole report through one locked, buffered stdout and flushes
+/// once, instead of locking stdout for every matching line.
+fn write_report(context: &GrepContext, matches: &[FileMatches], out: impl Write) -> AppResult<()> {
+    let mut out = BufWriter::new(out);
+    report_matches(context, matches, &mut out)?;
+    out.flush().map_err(AppError::WriteStdout)
+}
+
+fn write_line(out: &mut impl Write, line: impl Display) -> AppResult<()> {
+    writeln!(out, "{line}").map_err(AppError::WriteStdout)
+}
+
+fn report_matches(
+    context: &GrepContext,
+    matches: &[FileMatches],
+    out: &mut impl Write,
+) -> AppResult<()> {
+    if context.options.depth_histogram {
+        return report_depth_histogram(matches, out);
+    }
+
+    if context.options.count {
+        return report_counts(context, matches, out);
+    }
+
+    if context.options.files_with_matches || context.options.files_without_match {
+        return report_file_names(context, matches, out);
+    }
+
+    if matches.iter().all(|file| file.results.is_empty()) {
+        return Err(no_matches_error());
+    }
+
+    if context.options.distinct_values {
+        let values = matches
+            .iter()
+            .flat_map(|file| file.results.iter().map(|(_, value)| value));
+        for (value, count) in yaml_ops::distinct_values(values) {
+            write_line(out, format_args!("{count} {value}"))?;
+        }
+        return Ok(());
+    }
+
+    if context.options.json {
+        return write_line(out, json::grep_results_to_json(&json_matches(matches))?);
+    }
+
+    if context.options.per_file_yaml {
+        return write_line(out, per_file_yaml(matches)?);
+    }
+
+    if context.options.csv {
+        write_line(out, csv::record(&["file", "key", "value"]))?;
+        for file in matches {
+            for (key, value) in &file.results {
+                let value = yaml_ops::format_inline_value(value);
+                write_line(
+                    out,
+                    csv::record(&[file.path.as_deref().unwrap_or_default(), key, &value]),
+                )?;
+            }
+        }
+        return Ok(());
+    }
+
+    let width = get_terminal_width();
+
+    for file in matches {
+        let filename = file.path.as_deref().filter(|_| context.show_filename);
+        let mut printed = HashSet::new();
+        for (key, value) in &file.results {
+            if context.options.ancestors {
+                write_ancestors(filename, key, &mut printed, out)?;
+            }
+            write_grep_result(context, filename, key, value, width, out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists each file (`-` for stdin) with matches for `-l`, or without any
+/// for `-L`.
+fn report_file_names(
+    context: &GrepContext,
+    matches: &[FileMatches],
+    out: &mut impl Write,
+) -> AppResult<()> {
+    let names: Vec<_> = matches
+        .iter()
+        .filter(|file| file.results.is_empty() != context.options.files_with_matches)
+        .map(|file| file.path.as_deref().unwrap_or("-"))
+        .collect();
+    if names.is_empty() {
+        return Err(no_matches_error());
+    }
+
+    for name in names {
+        write_line(out, name)?;
+    }
+    Ok(())
+}
+
+/// Prints `path:count` for each file, or just the count for stdin. Files
+/// without matches are left out unless combined with `-l` or `-L`.
+fn report_counts(
+    context: &GrepContext,
+    matches: &[FileMatches],
+    out: &mut impl Write,
+) -> AppResult<()> {
+    let show_all = context.options.files_with_matches || context.options.files_without_match;
+    let counted: Vec<_> = matches
+        .iter()
+        .filter(|file| show_all || !file.results.is_empty())
+        .collect();
+    if counted.is_empty() {
+        return Err(no_matches_error());
+    }
+
+    for file in counted {
+        let count = file.results.len();
+        match &file.path {
+            Some(path) => write_line(out, format_args!("{path}:{count}"))?,
+            None => write_line(out, count)?,
+        }
+    }
+    Ok(())
+}
+
+/// One YAML mapping from each file with matches (`-` for stdin) to the
+/// matched subset of that file, nested as in the original.
+fn per_file_yaml(matches: &[FileMatches]) -> AppResult<String> {
+    let mut files = serde_yaml::Mapping::new();
+    for file in matches.iter().filter(|file| !file.results.is_empty()) {
+        let path = file.path.as_deref().unwrap_or("-");
+        files.insert(path.into(), yaml_ops::nest_results(&file.results)?);
+    }
+    yaml_ops::format_value(&serde_yaml::Value::Mapping(files))
+}
+
+fn report_depth_histogram(matches: &[FileMatches], out: &mut impl Write) -> AppResult<()> {
+    let mut histogram: Vec<usize> = Vec::new();
+    for file in matches {
+        add_depths(&mut histogram, &file.depths);
+    }
+
+    if histogram.is_empty() {
+        return Err(no_matches_error());
+    }
+
+    for (depth, count) in histogram.iter().enumerate() {
+        write_line(out, format_args!("{}: {count}", depth + 1))?;
+    }
+    Ok(())
+}
+
+fn add_depths(histogram: &mut Vec<usize>, depths: &[usize]) {
+    if histogram.len() < depths.len() {
+        histogram.resize(depths.len(), 0);
+    }
+    for (total, count) in histogram.iter_mut().zip(depths) {
+        *total += count;
+    }
+}
+
+fn json_matches(matches: &[FileMatches]) -> Vec<json::JsonMatch<'_>> {
+    matches
+        .iter()
+        .flat_map(|file| {
+            file.results
+                .iter()
+                .enumerate()
+                .map(|(index, (key, value))| json::JsonMatch {
+                    file: file.path.as_deref(),
+                    key,
+                    value,
+                    position: file.positions.get(index).copied().flatten(),
+                })
+        })
+        .collect()
+}
+
+/// Prints the container paths above `key` that no earlier line of the same
+/// file has shown, outermost first.
+fn write_ancestors(
+    filename: Option<&str>,
+    key: &str,
+    printed: &mut HashSet<String>,
+    out: &mut impl Write,
+) -> AppResult<()> {
+    // Keys from multi-document files start with a `[1]` document index.
+    let (document, path) = match key.split_once(']') {
+        Some((index, rest))
+            if index.starts_with('[') && !rest.is_empty() && !rest.starts_with(['.', '[']) =>
+        {
+            key.split_at(index.len() + 1)
+        }
+        _ => ("", key),
+    };
+    for ancestor in YamlPath::parse(path)?.ancestors() {
+        let ancestor = format!("{document}{}", ancestor.display());
+        if printed.insert(ancestor.clone()) {
+            match filename {
+                Some(filename) => write_line(out, format_args!("{filename}:{ancestor}"))?,
+                None => write_line(out, ancestor)?,
+            }
+        }
+    }
+    printed.insert(key.to_string());
+    Ok(())
+}
+
+fn write_grep_result(
+    context: &GrepContext,
+    filename: Option<&str>,
+    key: &str,
+    value: &serde_yaml::Value,
+    width: usize,
+    out: &mut impl Write,
+) -> AppResult<()> {
+    if let Some(template) = &context.options.format_template {
+        return write_line(
+            out,
+            yaml_ops::format_template(template, &context.regex, key, value),
+        );
+    }
+
+    let mut formatted = yaml_ops::format_result(key, value, width, context.output_mode);
+    if let Some(matched) = context
+        .regex
+        .find(key)
+        .filter(|_| context.options.show_match)
+    {
+        let matched = matched.as_str();
+        formatted = match context.output_mode {
+            GrepOutputMode::Inline => format!("{formatted}  # matched: {matched}"),
+            GrepOutputMode::Full => format!("# matched: {matched}\n{formatted}"),
+        };
+    }
+
+    match (filename, context.output_mode) {
+        (Some(filename), GrepOutputMode::Inline) => {
+            write_line(out, format_args!("{filename}:{formatted}"))
+        }
+        (Some(filename), GrepOutputMode::Full) => {
+            write_line(out, format_args!("--- {filename} ---\n{formatted}"))
+        }
+        (None, _) => write_line(out, formatted),
+    }
+}
+
+fn search_dir(
+    context: &GrepContext,
+    dir: &Path,
+    matches: &mut Vec<FileMatches>,
+    visited: &mut HashSet<PathBuf>,
+) -> AppResult<()> {
+    let options = context.options;
+    let walk = grep_walk(options)?;
+    let mut paths = Vec::new();
+    for path in yaml_files_in(dir, &walk)? {
+        if exceeds_max_file_size(options, &path)? {
+            eprintln!("Skipping '{}': larger than --max-file-size", path.display());
+            continue;
+        }
+        if first_visit(visited, &path) {
+            paths.push(path);
+        }
+    }
+
+    for file in grep_files(context, &paths) {
+        if limit_reached(context, matches) {
+            break;
+        }
+        // Files past the --max-results cut-off may not have been searched.
+        let Some(file) = file else {
+            break;
+        };
+        matches.push(file?);
+    }
+
+    Ok(())
+}
+
+/// How many threads search a directory: `--jobs`, or one per CPU.
+fn grep_jobs(options: &GrepOptions) -> usize {
+    match options.jobs {
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    }
+}
+
+/// Greps `paths` on up to `--jobs` threads, each taking the next unsearched
+/// path in turn, and returns the results in the order of `paths`. Workers
+/// stop taking paths once `--max-results` is exceeded, leaving `None` for
+/// the rest; every path before those has been searched.
+fn grep_files(context: &GrepContext, paths: &[PathBuf]) -> Vec<Option<AppResult<FileMatches>>> {
+    let jobs = grep_jobs(context.options);
+    let next = AtomicUsize::new(0);
+    let found = AtomicUsize::new(0);
+    let slots: Vec<_> = paths.iter().map(|_| Mutex::new(None)).collect();
+
+    let work = || loop {
+        if context
+            .options
+            .max_results
+            .is_some_and(|max| found.load(Ordering::Relaxed) > max)
+        {
+            break;
+        }
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(path) = paths.get(index) else {
+            break;
+        };
+        let result = grep_file(context, path);
+        if let Ok(file) = &result {
+            found.fetch_add(file.results.len(), Ordering::Relaxed);
+        }
+        *slots[index].lock().unwrap() = Some(result);
+    };
+    if jobs <= 1 || paths.len() <= 1 {
+        work();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..jobs.min(paths.len()) {
+                scope.spawn(work);
+            }
+        });
+    }
+
+    slots
+        .into_iter()
+        .map(|slot| slot.into_inner().unwrap())
+        .collect()
+}
+
+fn grep_walk(options: &GrepOptions) -> AppResult<Walk> {
+    Ok(Walk {
+        include_hidden: options.include_hidden,
+        recursive: options.recursive,
+        respect_gitignore: true,
+        follow_symlinks: options.follow_symlinks,
+        include: options
+            .include
+            .iter()
+            .map(|glob| gitignore::glob_regex(glob))
+            .collect::<AppResult<_>>()?,
+    })
+}
+
+/// The files `--watch` polls: each file given, and the YAML files grep
+/// would search in each directory given.
+fn watched_files(files: &[String], walk: &Walk) -> AppResult<Vec<PathBuf>> {
+    let mut watched = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        if path.is_dir() {
+            watched.extend(yaml_files_in(path, walk)?);
+        } else {
+            watched.push(path.to_path_buf());
+        }
+    }
+    Ok(watched)
+}
+
+fn exceeds_max_file_size(options: &GrepOptions, path: &Path) -> AppResult<bool> {
+    let Some(max) = options.max_file_size else {
+        return Ok(false);
+    };
+
+    let metadata = fs::metadata(path)
+        .map_err(|error| AppError::read_file(path.display().to_string(), error))?;
+    Ok(metadata.len() > max)
+}
+
+/// Which entries of a directory `yaml_files_in` lists.
+struct Walk {
+    include_hidden: bool,
+    recursive: bool,
+    /// Leave out entries matched by the `.gitignore` files found from the
+    /// directory downwards.
+    respect_gitignore: bool,
+    /// Descend into symlinked directories and read symlinked files, rather
+    /// than skipping them.
+    follow_symlinks: bool,
+    /// File name globs replacing the `.yaml`/`.yml` extension check.
+    include: Vec<Regex>,
+}
+
+fn yaml_files_in(dir: &Path, walk: &Walk) -> AppResult<Vec<PathBuf>> {
+    let ignore = walk.respect_gitignore.then(Gitignore::default);
+    let mut files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    collect_yaml_files(dir, walk, ignore.as_ref(), &mut visited_dirs, &mut files)?;
+    Ok(files)
+}
+
+fn collect_yaml_files(
+    dir: &Path,
+    walk: &Walk,
+    ignore: Option<&Gitignore>,
+    visited_dirs: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> AppResult<()> {
+    // A symlink back to an ancestor would otherwise recurse forever.
+    if !first_visit(visited_dirs, dir) {
+        return Ok(());
+    }
+    let ignore = ignore.map(|ignore| ignore.descend(dir)).transpose()?;
+    let entries =
+        fs::read_dir(dir).map_err(|error| AppError::read_dir(dir.display().to_string(), error))?;
+
+    for entry in entries {
+        let entry = entry.map_err(AppError::ReadDirEntry)?;
+        if !walk.include_hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let is_symlink = entry
+            .file_type()
+            .map_err(AppError::ReadDirEntry)?
+            .is_symlink();
+        if is_symlink && !walk.follow_symlinks {
+            continue;
+        }
+        let path = entry.path();
+        let is_dir = path.is_dir();
+        if ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_ignored(&path, is_dir))
+        {
+            continue;
+        }
+
+        if is_dir {
+            if walk.recursive {
+                collect_yaml_files(&path, walk, ignore.as_ref(), visited_dirs, files)?;
+            }
+        } else if path.is_file() && should_process_file(&path, &walk.include) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn should_process_file(path: &Path, include: &[Regex]) -> bool {
+    if !include.is_empty() {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        return include.iter().any(|glob| glob.is_match(&name));
+    }
+
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("yaml" | "yml")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::UnsetOptions;
+    use serde_yaml::Value;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let unique = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("ym_{}_{}_{}", name, process::id(), unique));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_yaml(path: &Path) -> Value {
+        serde_yaml::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn grep_context<'a>(pattern: &str, options: &'a GrepOptions) -> GrepContext<'a> {
+        GrepContext {
+            regex: yaml_ops::compile_pattern(pattern, &options.matching).unwrap(),
+            options,
+            output_mode: GrepOutputMode::Inline,
+            show_filename: false,
+            preserve_numbers: false,
+        }
+    }
+
+    #[test]
+    fn test_grep_reader_reports_value_positions() {
+        let options = GrepOptions {
+            json: true,
+            with_position: true,
+            ..GrepOptions::default()
+        };
+        let input = "# db\ndatabase:\n  host: db\n  ports:\n    - 5432\n".as_bytes();
+        let matches = grep_reader(&grep_context(r"host|ports", &options), input).unwrap();
+        assert_eq!(matches.positions, vec![Some((3, 9)), Some((5, 5))]);
+    }
+
+    #[test]
+    fn test_grep_reader_prefixes_keys_with_document_index() {
+        let options = GrepOptions::default();
+        let context = grep_context("name", &options);
+
+        let input = "name: a\n---\nport: 1\n---\nname: b\n".as_bytes();
+        let keys: Vec<_> = grep_reader(&context, input)
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec!["[0]name", "[2]name"]);
+
+        let input = "---\nname: a\n".as_bytes();
+        let matches = grep_reader(&context, input).unwrap();
+        assert_eq!(matches.results[0].0, "name");
+    }
+
+    #[derive(Default)]
+    struct RecordingWriter {
+        bytes: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_grep_merged_attributes_matches_to_winning_file() {
+        let dir = temp_dir("grep_merged");
+        let base = dir.join("base.yaml");
+        let prod = dir.join("prod.yaml");
+        fs::write(
+            &base,
+            "db:\n  host: localhost\n  port: 5432\ncache:\n  host: redis\n",
+        )
+        .unwrap();
+        fs::write(&prod, "db:\n  host: prod-db\n").unwrap();
+        let files = [base.display().to_string(), prod.display().to_string()];
+        let options = GrepOptions {
+            merged: true,
+            with_source: true,
+            ..GrepOptions::default()
+        };
+        let mut context = grep_context(r"host|port", &options);
+        context.show_filename = true;
+
+        let matches = grep_merged(&context, &files).unwrap();
+        let mut out = Vec::new();
+        write_report(&context, &matches, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{prod}:db.host: prod-db\n{base}:db.port: 5432\n{base}:cache.host: redis\n",
+                prod = files[1],
+                base = files[0]
+            )
+        );
+
+        let options = GrepOptions {
+            merged: true,
+            ..GrepOptions::default()
+        };
+        let matches = grep_merged(&grep_context("host", &options), &files).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, None);
+        assert_eq!(
+            matches[0].results,
+            vec![
+                ("db.host".to_string(), Value::String("prod-db".to_string())),
+                ("cache.host".to_string(), Value::String("redis".to_string())),
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_merged_credits_each_key_to_the_file_that_wrote_it() {
+        let dir = temp_dir("grep_merged_layers");
+        let layers = [
+            ("base.yaml", "name: app\n"),
+            ("l1.yaml", "db:\n  port: 5432\n"),
+            ("l2.yaml", "db:\n  port: 5432\n  host: prod-db\n"),
+        ];
+        let files: Vec<String> = layers
+            .iter()
+            .map(|(name, contents)| {
+                let file = dir.join(name);
+                fs::write(&file, contents).unwrap();
+                file.display().to_string()
+            })
+            .collect();
+        let options = GrepOptions {
+            merged: true,
+            with_source: true,
+            ..GrepOptions::default()
+        };
+        let sources = |pattern: &str| {
+            grep_merged(&grep_context(pattern, &options), &files)
+                .unwrap()
+                .into_iter()
+                .flat_map(|group| {
+                    let path = group.path.unwrap();
+                    group
+                        .results
+                        .into_iter()
+                        .map(move |(key, _)| (key, path.clone()))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // l2 repeats db.port without changing it, so l1 keeps the credit.
+        assert_eq!(
+            sources("port|host"),
+            vec![
+                ("db.port".to_string(), files[1].clone()),
+                ("db.host".to_string(), files[2].clone()),
+            ]
+        );
+        assert_eq!(sources("^db$"), vec![("db".to_string(), files[1].clone())]);
+        assert_eq!(
+            sources("name"),
+            vec![("name".to_string(), files[0].clone())]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_report_flushes_once_and_keeps_order() {
+        let options = GrepOptions {
+            csv: true,
+            ..GrepOptions::default()
+        };
+        let input: String = (0..2000).map(|index| format!("key{index}: v\n")).collect();
+        let context = grep_context("key", &options);
+        let matches = grep_reader(&context, input.as_bytes()).unwrap();
+
+        let mut out = RecordingWriter::default();
+        write_report(&context, &[matches], &mut out).unwrap();
+
+        let expected: String = std::iter::once("file,key,value\n".to_string())
+            .chain((0..2000).map(|index| format!(",key{index},v\n")))
+            .collect();
+        assert_eq!(String::from_utf8(out.bytes).unwrap(), expected);
+        assert_eq!(out.flushes, 1);
+    }
+
+    #[test]
+    fn test_paths_from_reader_splits_on_nul() {
+        let input = "a.yaml\0dir/b c.yaml\0\0".as_bytes();
+        assert_eq!(
+            paths_from_reader(input).unwrap(),
+            vec!["a.yaml".to_string(), "dir/b c.yaml".to_string()]
+        );
+        assert!(paths_from_reader("".as_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_profile_reports_grep_phases_when_enabled() {
+        let dir = temp_dir("profile");
+        let file = dir.join("app.yaml");
+        fs::write(&file, "host: a\n").unwrap();
+        let options = GrepOptions::default();
+
+        profile::enable();
+        grep_file(&grep_context("host", &options), &file).unwrap();
+
+        let lines = profile::report();
+        for phase in ["read", "parse", "match"] {
+            let line = lines
+                .iter()
+                .find(|line| line.starts_with(&format!("profile: {phase} ")))
+                .unwrap();
+            let seconds: f64 = line
+                .rsplit(' ')
+                .next()
+                .and_then(|value| value.strip_suffix('s'))
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(seconds >= 0.0);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_skips_hidden_entries_unless_included() {
+        let dir = temp_dir("hidden");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".git/config.yaml"), "host: hidden\n").unwrap();
+        fs::write(dir.join(".local.yaml"), "host: dotfile\n").unwrap();
+        fs::write(dir.join("app.yaml"), "host: visible\n").unwrap();
+        let roots = [dir.display().to_string()];
+
+        let options = GrepOptions::default();
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].results[0].1.as_str(), Some("visible"));
+
+        let options = GrepOptions {
+            include_hidden: true,
+            recursive: true,
+            ..GrepOptions::default()
+        };
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 3);
+
+        let hidden_root = [dir.join(".git").display().to_string()];
+        let options = GrepOptions::default();
+        let matches = collect_matches(&grep_context("host", &options), &hidden_root).unwrap();
+        assert_eq!(matches.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_skips_gitignored_entries_when_recursive() {
+        let dir = temp_dir("gitignore");
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.join("config/.gitignore"), "local.yaml\n").unwrap();
+        fs::write(dir.join("target/out.yaml"), "host: built\n").unwrap();
+        fs::write(dir.join("config/local.yaml"), "host: local\n").unwrap();
+        fs::write(dir.join("config/app.yaml"), "host: app\n").unwrap();
+        let roots = [dir.display().to_string()];
+
+        let options = GrepOptions {
+            recursive: true,
+            ..GrepOptions::default()
+        };
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].results[0].1.as_str(), Some("app"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_include_globs_replace_extension_check() {
+        let dir = temp_dir("include");
+        fs::write(dir.join("app.yaml"), "host: yaml\n").unwrap();
+        fs::write(dir.join("prod.values"), "host: values\n").unwrap();
+        fs::write(dir.join("chart.yaml.tpl"), "host: tpl\n").unwrap();
+        let roots = [dir.display().to_string()];
+
+        let options = GrepOptions {
+            include: vec!["*.values".to_string(), "*.yaml.tpl".to_string()],
+            ..GrepOptions::default()
+        };
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        let mut values: Vec<_> = matches
+            .iter()
+            .map(|file| file.results[0].1.as_str().unwrap())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, ["tpl", "values"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_grep_follows_symlinks_only_when_asked_and_survives_loops() {
+        let dir = temp_dir("symlinks");
+        let shared = temp_dir("symlinks_target");
+        fs::write(shared.join("shared.yaml"), "host: linked\n").unwrap();
+        fs::write(dir.join("app.yaml"), "host: app\n").unwrap();
+        std::os::unix::fs::symlink(&shared, dir.join("shared")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("current")).unwrap();
+        let roots = [dir.display().to_string()];
+        let hosts = |options: GrepOptions| {
+            let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+            let mut hosts: Vec<_> = matches
+                .iter()
+                .map(|file| file.results[0].1.as_str().unwrap().to_string())
+                .collect();
+            hosts.sort_unstable();
+            hosts
+        };
+
+        let skipped = hosts(GrepOptions {
+            recursive: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(skipped, ["app"]);
+
+        let followed = hosts(GrepOptions {
+            recursive: true,
+            follow_symlinks: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(followed, ["app", "linked"]);
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(shared).unwrap();
+    }
+
+    #[test]
+    fn test_grep_descends_into_subdirectories_only_when_recursive() {
+        let dir = temp_dir("recursive");
+        fs::create_dir_all(dir.join("sub/deeper")).unwrap();
+        fs::write(dir.join("top.yaml"), "host: top\n").unwrap();
+        fs::write(dir.join("sub/nested.yaml"), "host: nested\n").unwrap();
+        fs::write(dir.join("sub/deeper/leaf.yaml"), "host: leaf\n").unwrap();
+        let roots = [dir.display().to_string()];
+
+        let options = GrepOptions::default();
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].results[0].1.as_str(), Some("top"));
+
+        let options = GrepOptions {
+            recursive: true,
+            ..GrepOptions::default()
+        };
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 3);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_skips_directory_files_above_max_file_size() {
+        let dir = temp_dir("max_file_size");
+        fs::write(dir.join("small.yaml"), "host: a\n").unwrap();
+        fs::write(
+            dir.join("large.yaml"),
+            format!("host: {}\n", "b".repeat(100)),
+        )
+        .unwrap();
+        let roots = [dir.display().to_string()];
+
+        let options = GrepOptions {
+            max_file_size: Some(64),
+            ..GrepOptions::default()
+        };
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].results[0].1.as_str(), Some("a"));
+
+        let options = GrepOptions::default();
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+        assert_eq!(matches.len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_jobs_keep_directory_order() {
+        let dir = temp_dir("jobs");
+        for index in 0..20 {
+            fs::write(
+                dir.join(format!("{index:02}.yaml")),
+                format!("host: h{index}\n"),
+            )
+            .unwrap();
+        }
+        let roots = [dir.display().to_string()];
+        let hosts = |options: GrepOptions| {
+            collect_matches(&grep_context("host", &options), &roots)
+                .unwrap()
+                .iter()
+                .flat_map(|file| file.results.iter())
+                .map(|(_, value)| value.as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let sequential = hosts(GrepOptions {
+            jobs: Some(1),
+            ..GrepOptions::default()
+        });
+        assert_eq!(sequential.len(), 20);
+        for _ in 0..5 {
+            let parallel = hosts(GrepOptions {
+                jobs: Some(4),
+                ..GrepOptions::default()
+            });
+            assert_eq!(parallel, sequential);
+            let limited = hosts(GrepOptions {
+                jobs: Some(4),
+                max_results: Some(3),
+                ..GrepOptions::default()
+            });
+            assert_eq!(limited[..4], sequential[..4]);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_jobs_follow_jobs_flag_when_profiling() {
+        let options = GrepOptions {
+            jobs: Some(4),
+            ..GrepOptions::default()
+        };
+
+        profile::enable();
+        assert_eq!(grep_jobs(&options), 4);
+    }
+
+    #[test]
+    fn test_profile_prints_parse_match_and_write_timings() {
+        let dir = temp_dir("profile_output");
+        for name in ["a.yaml", "b.yaml", "c.yaml"] {
+            fs::write(dir.join(name), "host: a\n").unwrap();
+        }
+
+        profile::enable();
+        let grep = GrepOptions {
+            recursive: true,
+            jobs: Some(3),
+            ..GrepOptions::default()
+        };
+        run_grep("host", &[dir.display().to_string()], &grep, false).unwrap();
+        execute_command(
+            Command::Set {
+                file: dir.join("a.yaml").display().to_string(),
+                updates: vec![("port".to_string(), "80".to_string())],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        let mut stderr = Vec::new();
+        write_profile(&mut stderr).unwrap();
+        let stderr = String::from_utf8(stderr).unwrap();
+        for phase in ["parse", "match", "write"] {
+            let line = stderr
+                .lines()
+                .find(|line| line.starts_with(&format!("profile: {phase} ")))
+                .unwrap();
+            let seconds: f64 = line
+                .rsplit(' ')
+                .next()
+                .and_then(|value| value.strip_suffix('s'))
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(seconds >= 0.0);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_lists_files_with_and_without_matches() {
+        let dir = temp_dir("file_names");
+        let (a, b) = (dir.join("a.yaml"), dir.join("b.yaml"));
+        fs::write(&a, "db:\n  host: a\n  port: 1\n").unwrap();
+        fs::write(&b, "name: none\n").unwrap();
+        let roots = [a.display().to_string(), b.display().to_string()];
+        let list = |options: GrepOptions| {
+            let context = grep_context("host|port", &options);
+            let matches = collect_matches(&context, &roots).unwrap();
+            let mut out = Vec::new();
+            write_report(&context, &matches, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let with_matches = list(GrepOptions {
+            files_with_matches: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(with_matches, format!("{}\n", a.display()));
+
+        let without_match = list(GrepOptions {
+            files_without_match: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(without_match, format!("{}\n", b.display()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_change_marks_added_removed_and_changed_keys() {
+        let changes = yaml_ops::value_changes(
+            "replicas: 2\nports: [80]\nlegacy: true\n",
+            "ports: [80, 443]\nreplicas: 3\n",
+            false,
+        )
+        .unwrap();
+        let lines: Vec<_> = changes.iter().map(format_change).collect();
+
+        assert_eq!(
+            lines,
+            ["~ replicas: 2 -> 3", "+ ports[1]: 443", "- legacy: true"]
+        );
+    }
+
+    #[test]
+    fn test_diff_only_keeps_one_kind_of_change() {
+        let left = "replicas: 2\nports: [80]\nlegacy: true\n";
+        let right = "ports: [80, 443]\nreplicas: 3\n";
+        let only = |kind| diff_lines(left, right, Some(kind), false, false).unwrap();
+
+        assert_eq!(only(ChangeKind::Added), ["+ ports[1]: 443"]);
+        assert_eq!(only(ChangeKind::Removed), ["- legacy: true"]);
+        assert_eq!(only(ChangeKind::Changed), ["~ replicas: 2 -> 3"]);
+        assert_eq!(
+            diff_lines(left, right, None, false, false).unwrap().len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_diff_patch_applied_to_left_gives_right() {
+        let left = "# app\nreplicas: 2\nports: [80, 443, 8443]\nlegacy: true\ndb:\n  host: old\n";
+        let right = concat!(
+            "replicas: 3\nports: [80]\ndb:\n  host: 'a: b # c'\n  port: '5432'\n",
+            "cache:\n  enabled: true\n  hosts: [x, y]\n",
+        );
+
+        let patch = diff_lines(left, right, None, true, false).unwrap();
+        assert_eq!(
+            patch,
+            [
+                "set replicas=3",
+                "set db.host=\"a: b # c\"",
+                "set db.port=\"5432\"",
+                "set cache={\"enabled\":true,\"hosts\":[\"x\",\"y\"]}",
+                "unset legacy",
+                "unset ports[2]",
+                "unset ports[1]",
+            ]
+        );
+
+        let script = script::Script::parse(&patch.join("\n")).unwrap();
+        let applied = script.apply(left, &EditOptions::default()).unwrap();
+        assert!(applied.starts_with("# app\n"));
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&applied).unwrap(),
+            serde_yaml::from_str::<Value>(right).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_grep_ancestors_are_printed_once_before_matches() {
+        let options = GrepOptions {
+            ancestors: true,
+            ..GrepOptions::default()
+        };
+        let context = grep_context("user|password", &options);
+        let document =
+            serde_yaml::from_str("app:\n  db:\n    primary:\n      user: u\n      password: p\n")
+                .unwrap();
+        let matches = [FileMatches {
+            path: None,
+            results: yaml_ops::grep(&document, &context.regex, &options.matching),
+            positions: Vec::new(),
+            depths: Vec::new(),
+        }];
+
+        let mut out = Vec::new();
+        write_report(&context, &matches, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "app\napp.db\napp.db.primary\napp.db.primary.user: u\napp.db.primary.password: p\n"
+        );
+    }
+
+    #[test]
+    fn test_grep_tag_keeps_only_values_with_that_tag() {
+        let dir = temp_dir("tag");
+        let file = dir.join("secrets.yaml");
+        fs::write(
+            &file,
+            "db:\n  password: !secret hunter2\n  user: admin\n  cert: !!binary aGVsbG8=\ntokens:\n  - !secret abc\n  - plain\n",
+        )
+        .unwrap();
+        let roots = [file.display().to_string()];
+        let keys = |pattern: &str, tag: &str| {
+            let options = GrepOptions {
+                tag: Some(tag.to_string()),
+                ..GrepOptions::default()
+            };
+            let matches = collect_matches(&grep_context(pattern, &options), &roots).unwrap();
+            matches[0]
+                .results
+                .iter()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(".", "!secret"), ["db.password", "tokens[0]"]);
+        assert_eq!(keys("db", "secret"), ["db.password"]);
+        assert_eq!(keys(".", "!!binary"), ["db.cert"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_show_match_reports_matched_alternative() {
+        let options = GrepOptions {
+            show_match: true,
+            ..GrepOptions::default()
+        };
+        let context = grep_context("(host|port|user)$", &options);
+        let matches = [FileMatches {
+            path: None,
+            results: yaml_ops::grep(
+                &serde_yaml::from_str("db:\n  host: a\n  port: 1\nname: x\n").unwrap(),
+                &context.regex,
+                &options.matching,
+            ),
+            positions: Vec::new(),
+            depths: Vec::new(),
+        }];
+
+        let mut out = Vec::new();
+        write_report(&context, &matches, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "db.host: a  # matched: host\ndb.port: 1  # matched: port\n"
+        );
+    }
+
+    #[test]
+    fn test_grep_count_reports_matches_per_file() {
+        let dir = temp_dir("count");
+        let (a, b) = (dir.join("a.yaml"), dir.join("b.yaml"));
+        fs::write(&a, "db:\n  host: a\n  port: 1\n").unwrap();
+        fs::write(&b, "name: none\n").unwrap();
+        let roots = [a.display().to_string(), b.display().to_string()];
+        let count = |options: GrepOptions| {
+            let context = grep_context("host|port", &options);
+            let matches = collect_matches(&context, &roots).unwrap();
+            let mut out = Vec::new();
+            write_report(&context, &matches, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let counts = count(GrepOptions {
+            count: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(counts, format!("{}:2\n", a.display()));
+
+        let all_counts = count(GrepOptions {
+            count: true,
+            files_without_match: true,
+            ..GrepOptions::default()
+        });
+        assert_eq!(
+            all_counts,
+            format!("{}:2\n{}:0\n", a.display(), b.display())
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_per_file_yaml_aggregates_matches_by_file() {
+        let dir = temp_dir("per_file_yaml");
+        fs::write(dir.join("a.yaml"), "db:\n  host: a\n  port: 1\nname: x\n").unwrap();
+        fs::write(dir.join("b.yaml"), "servers:\n  - host: b\n    port: 2\n").unwrap();
+        fs::write(dir.join("c.yaml"), "name: none\n").unwrap();
+        let options = GrepOptions::default();
+        let roots = [dir.display().to_string()];
+        let matches = collect_matches(&grep_context("host", &options), &roots).unwrap();
+
+        let rendered: Value = serde_yaml::from_str(&per_file_yaml(&matches).unwrap()).unwrap();
+        let expected = format!(
+            "'{a}':\n  db:\n    host: a\n'{b}':\n  servers:\n  - host: b\n",
+            a = dir.join("a.yaml").display(),
+            b = dir.join("b.yaml").display(),
+        );
+        assert_eq!(rendered, serde_yaml::from_str::<Value>(&expected).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_visits_files_once_across_overlapping_roots() {
+        let dir = temp_dir("overlapping_roots");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("top.yaml"), "host: a\n").unwrap();
+        fs::write(dir.join("sub/nested.yaml"), "host: b\n").unwrap();
+        let options = GrepOptions::default();
+        let context = grep_context("host", &options);
+
+        let roots = [
+            dir.display().to_string(),
+            dir.join("sub").display().to_string(),
+            dir.join("sub/../top.yaml").display().to_string(),
+        ];
+        let matches = collect_matches(&context, &roots).unwrap();
+        let mut values: Vec<_> = matches
+            .iter()
+            .flat_map(|file| file.results.iter().map(|(_, value)| value.clone()))
+            .collect();
+        values.sort_by_key(|value| value.as_str().map(str::to_string));
+        assert_eq!(
+            values,
+            vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string())
+            ]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_max_results_prints_the_cap_and_the_notice() {
+        let dir = temp_dir("max_results");
+        let file = dir.join("a.yaml");
+        fs::write(&file, "host: h\nport: 1\nname: n\n").unwrap();
+        let options = GrepOptions {
+            max_results: Some(2),
+            ..GrepOptions::default()
+        };
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let files = [file.display().to_string()];
+        search_and_report(&grep_context(".", &options), &files, &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "host: h\nport: 1\n");
+        assert_eq!(String::from_utf8(err).unwrap(), "... (truncated)\n");
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        search_and_report(&grep_context("host", &options), &files, &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "host: h\n");
+        assert!(err.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_max_results_stops_searching_across_files() {
+        let dir = temp_dir("max_results_files");
+        for name in ["a.yaml", "b.yaml", "c.yaml", "d.yaml", "e.yaml"] {
+            fs::write(dir.join(name), "host: h\nport: 1\n").unwrap();
+        }
+        let options = GrepOptions {
+            max_results: Some(2),
+            jobs: Some(1),
+            ..GrepOptions::default()
+        };
+        let context = grep_context(".", &options);
+
+        // The second file exceeds the cap, so the other three are never read.
+        let mut matches = collect_matches(&context, &[dir.display().to_string()]).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(limit_reached(&context, &matches));
+
+        assert!(truncate_matches(&mut matches, 2));
+        let total: usize = matches.iter().map(|file| file.results.len()).sum();
+        assert_eq!(total, 2);
+        assert!(!truncate_matches(&mut matches, 2));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_reader_parses_json_with_stdin_format() {
+        let options = GrepOptions {
+            stdin_format: InputFormat::Json,
+            ..GrepOptions::default()
+        };
+        let input = r#"{"database": {"host": "db"}}"#.as_bytes();
+        let matches = grep_reader(&grep_context(r"database\.host", &options), input).unwrap();
+        assert_eq!(matches.results.len(), 1);
+        assert_eq!(matches.results[0].0, "database.host");
+
+        let input = r#"{"database": {"host": "db"}}"#.as_bytes();
+        let matches = grep_reader(&grep_context("missing", &options), input).unwrap();
+        assert!(matches.results.is_empty());
+
+        let error = grep_reader(&grep_context("host", &options), "a: [".as_bytes()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Failed to parse JSON from stdin"));
+    }
+
+    #[test]
+    fn test_get_reads_stdin_in_stdin_format() {
+        let get = |format, input: &str| {
+            let options = GetOptions {
+                stdin_format: format,
+                ..GetOptions::default()
+            };
+            get_found("-", "database.port", &options, false, input.as_bytes())
+        };
+
+        let toml = get(InputFormat::Toml, "[database]\nport = 5432\n").unwrap();
+        assert_eq!(toml, Value::from(5432));
+        let json = get(InputFormat::Json, r#"{"database": {"port": 5433}}"#).unwrap();
+        assert_eq!(json, Value::from(5433));
+        let yaml = get(InputFormat::Yaml, "database:\n  port: 5434\n").unwrap();
+        assert_eq!(yaml, Value::from(5434));
+        assert!(get(InputFormat::Toml, "database:\n  port: 1\n").is_err());
+    }
+
+    #[test]
+    fn test_grep_reader_parses_toml_with_stdin_format() {
+        let options = GrepOptions {
+            stdin_format: InputFormat::Toml,
+            ..GrepOptions::default()
+        };
+        let input = "[database]\nhost = \"db\"\n".as_bytes();
+        let matches = grep_reader(&grep_context(r"database\.host", &options), input).unwrap();
+        assert_eq!(matches.results.len(), 1);
+        assert_eq!(matches.results[0].0, "database.host");
+    }
+
+    #[test]
+    fn test_set_and_unset_commands_update_yaml_semantics() {
+        let dir = temp_dir("set_unset");
+        let file = dir.join("config.yaml");
+        fs::write(
+            &file,
+            "# config\nname  : \"Alice\"   # keep quote/comment\nstatus: enabled\ncount: 1\n",
+        )
+        .unwrap();
+
+        execute_command(
+            Command::Set {
+                file: file.display().to_string(),
+                updates: vec![
+                    ("name".to_string(), "Bob".to_string()),
+                    ("count".to_string(), "2".to_string()),
+                ],
+                options: SetOptions::default(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        let updated = read_yaml(&file);
+        assert_eq!(updated["name"].as_str(), Some("Bob"));
+        assert_eq!(updated["status"].as_str(), Some("enabled"));
+        assert_eq!(updated["count"].as_i64(), Some(2));
+
+        execute_command(
+            Command::Unset {
+                file: file.display().to_string(),
+                keys: vec!["count".to_string()],
+                options: UnsetOptions::default(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        let updated = read_yaml(&file);
+        assert!(updated.get("count").is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_after_write_hook_passes_file_as_argument() {
+        let hook = after_write_hook("yamllint -d relaxed {}", "config dir/app.yaml");
+        let args: Vec<_> = hook.get_args().collect();
+        assert_eq!(hook.get_program(), "sh");
+        assert_eq!(
+            args,
+            vec![
+                "-c",
+                "yamllint -d relaxed \"$1\"",
+                "ym",
+                "config dir/app.yaml"
+            ]
+        );
+
+        let hook = after_write_hook("yamllint", "app.yaml");
+        let args: Vec<_> = hook.get_args().collect();
+        assert_eq!(args, vec!["-c", "yamllint \"$1\"", "ym", "app.yaml"]);
+    }
+
+    #[test]
+    fn test_exit_code_on_change_only_when_file_changes() {
+        let dir = temp_dir("exit_code_on_change");
+        let file = dir.join("config.yaml");
+        fs::write(&file, "name: web\n").unwrap();
+        let write = || WriteOptions {
+            exit_code_on_change: Some(10),
+            ..WriteOptions::default()
+        };
+        let set = |value: &str| {
+            execute_command(
+                Command::Set {
+                    file: file.display().to_string(),
+                    updates: vec![("name".to_string(), value.to_string())],
+                    options: SetOptions::default(),
+                    write: write(),
+                },
+                &GlobalOptions::default(),
+            )
+        };
+
+        assert!(matches!(set("api"), Err(AppError::Changed(10))));
+        assert!(set("api").is_ok());
+
+        let unset = || {
+            execute_command(
+                Command::Unset {
+                    file: file.display().to_string(),
+                    keys: vec!["name".to_string()],
+                    options: UnsetOptions::default(),
+                    write: write(),
+                },
+                &GlobalOptions::default(),
+            )
+        };
+        assert!(matches!(unset(), Err(AppError::Changed(10))));
+        assert!(unset().is_ok());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_applies_dotenv_file() {
+        let dir = temp_dir("dotenv");
+        let file = dir.join("config.yaml");
+        let env_file = dir.join(".env");
+        fs::write(&file, "# config\nname: web\n").unwrap();
+        fs::write(
+            &env_file,
+            "# settings\nexport APP_PORT=8080 # http\nAPP_MOTD=\"up: yes\"\nAPP_DEBUG='true'\nHOME=/root\n",
+        )
+        .unwrap();
+
+        execute_command(
+            Command::Set {
+                file: file.display().to_string(),
+                updates: vec![("name".to_string(), "api".to_string())],
+                options: SetOptions {
+                    from_dotenv: Some(env_file.display().to_string()),
+                    dotenv_prefix: Some("APP_".to_string()),
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "# config\nname: api\nPORT: 8080\nMOTD: 'up: yes'\nDEBUG: 'true'\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_applies_string_to_dotenv_values() {
+        let dir = temp_dir("dotenv_string");
+        let file = dir.join("d.yaml");
+        let env_file = dir.join("e.env");
+        fs::write(&file, "a: 1\n").unwrap();
+        fs::write(&env_file, "B=3\nC='x'\n").unwrap();
+
+        execute_command(
+            Command::Set {
+                file: file.display().to_string(),
+                updates: Vec::new(),
+                options: SetOptions {
+                    from_dotenv: Some(env_file.display().to_string()),
+                    string: true,
+                    ..SetOptions::default()
+                },
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "a: 1\nB: '3'\nC: x\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_applies_only_when_condition_holds() {
+        let dir = temp_dir("set_when");
+        let file = dir.join("config.yaml");
+        let set_when = |condition: &str| {
+            execute_command(
+                Command::Set {
+                    file: file.display().to_string(),
+                    updates: vec![("logging.level".to_string(), "debug".to_string())],
+                    options: SetOptions {
+                        when: Some(assertion::Assertion::parse(condition).unwrap()),
+                        ..SetOptions::default()
+                    },
+                    write: WriteOptions::default(),
+                },
+                &GlobalOptions::default(),
+            )
+            .unwrap();
+            fs::read_to_string(&file).unwrap()
+        };
+
+        fs::write(&file, "env: production\nlogging:\n  level: info\n").unwrap();
+        assert_eq!(
+            set_when("env==staging"),
+            "env: production\nlogging:\n  level: info\n"
+        );
+
+        fs::write(&file, "env: staging\nlogging:\n  level: info\n").unwrap();
+        assert_eq!(
+            set_when("env==staging"),
+            "env: staging\nlogging:\n  level: debug\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_backs_up_without_clobbering() {
+        let dir = temp_dir("backup");
+        let file = dir.join("config.yaml");
+        let backup = dir.join("config.yaml.bak");
+        fs::write(&file, "# config\ncount: 1\n").unwrap();
+        let set_count = |count: &str| {
+            execute_command(
+                Command::Set {
+                    file: file.display().to_string(),
+                    updates: vec![("count".to_string(), count.to_string())],
+                    options: SetOptions::default(),
+                    write: WriteOptions {
+                        backup: Some(".bak".to_string()),
+                        ..WriteOptions::default()
+                    },
+                },
+                &GlobalOptions::default(),
+            )
+        };
+
+        set_count("2").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "# config\ncount: 2\n");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "# config\ncount: 1\n");
+
+        let error = set_count("3").unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "# config\ncount: 2\n");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "# config\ncount: 1\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_backs_up_into_backup_dir() {
+        let dir = temp_dir("backup_dir");
+        let file = dir.join("conf").join("config.yaml");
+        let backups = dir.join("backups");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "count: 1\n").unwrap();
+
+        execute_command(
+            Command::Set {
+                file: file.display().to_string(),
+                updates: vec![("count".to_string(), "2".to_string())],
+                options: SetOptions::default(),
+                write: WriteOptions {
+                    backup: Some(".bak".to_string()),
+                    backup_dir: Some(backups.display().to_string()),
+                    ..WriteOptions::default()
+                },
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        let relative = file.strip_prefix("/").unwrap();
+        let backup = backups.join(format!("{}.bak", relative.display()));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "count: 1\n");
+        assert!(!dir.join("conf").join("config.yaml.bak").exists());
+        assert_eq!(
+            backup_path("conf/app.yaml", ".bak", Some("backups")),
+            "backups/conf/app.yaml.bak"
+        );
+        assert_eq!(
+            backup_path("../app.yaml", ".orig", Some("backups")),
+            "backups/app.yaml.orig"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_scrub_command_backs_up_before_removing_keys() {
+        let dir = temp_dir("scrub_backup");
+        let file = dir.join("s.yaml");
+        fs::write(&file, "token: secret\nname: app\n").unwrap();
+
+        execute_command(
+            Command::Scrub {
+                file: file.display().to_string(),
+                value_pattern: "secret".to_string(),
+                dry_run: false,
+                write: WriteOptions {
+                    backup: Some(".bak".to_string()),
+                    ..WriteOptions::default()
+                },
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(&file).unwrap(), "name: app\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("s.yaml.bak")).unwrap(),
+            "token: secret\nname: app\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unset_print_removed_reads_values_before_removal() {
+        let dir = temp_dir("print_removed");
+        let file = dir.join("config.yaml");
+        let contents = "db:\n  password: hunter2\n  port: 5432\nhosts: [a, b]\n";
+        fs::write(&file, contents).unwrap();
+        let keys = vec![
+            "db.password".to_string(),
+            "hosts".to_string(),
+            "missing".to_string(),
+        ];
+
+        assert_eq!(
+            removed_values("config.yaml", contents, &keys, false).unwrap(),
+            vec!["hunter2".to_string(), "- a\n- b".to_string()]
+        );
+
+        execute_command(
+            Command::Unset {
+                file: file.display().to_string(),
+                keys: keys[..2].to_vec(),
+                options: UnsetOptions {
+                    print_removed: true,
+                    ..UnsetOptions::default()
+                },
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "db:\n  port: 5432\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unset_regex_removes_overlapping_matches_once() {
+        let dir = temp_dir("unset_regex");
+        let file = dir.join("config.yaml");
+        fs::write(
+            &file,
+            "secrets:\n  a: 1\n  b: 2\nitems:\n  - token: x\n  - name: y\n  - token: z\nkeep: true\n",
+        )
+        .unwrap();
+
+        execute_command(
+            Command::Unset {
+                file: file.display().to_string(),
+                keys: vec![
+                    r"secrets".to_string(),
+                    r"secrets\.a".to_string(),
+                    "token".to_string(),
+                ],
+                options: UnsetOptions {
+                    regex: true,
+                    ..UnsetOptions::default()
+                },
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "items:\n  - name: y\nkeep: true\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_applies_script_operations_in_order() {
+        let dir = temp_dir("run_script");
+        let file = dir.join("config.yaml");
+        let script = dir.join("edits.ym");
+        fs::write(
+            &file,
+            "# app\ndb:\n  host: old # primary\n  user: admin\ntmp: 1\n",
+        )
+        .unwrap();
+        fs::write(
+            &script,
+            "# migrate\nset db.host=new\nset db.port=5432\ncp db.user owner\nmv tmp scratch\nunset db.user scratch\n",
+        )
+        .unwrap();
+
+        execute_command(
+            Command::Run {
+                script: script.display().to_string(),
+                file: file.display().to_string(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "# app\ndb:\n  host: new # primary\n  port: 5432\nowner: admin\n"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_command_surfaces_hook_failure() {
+        let dir = temp_dir("exec_hook");
+        let file = dir.join("config.yaml");
+        fs::write(&file, "count: 1\n").unwrap();
+
+        let error = execute_command(
+            Command::Set {
+                file: file.display().to_string(),
+                updates: vec![("count".to_string(), "2".to_string())],
+                options: SetOptions::default(),
+                write: WriteOptions {
+                    exec: Some("test ! -f {}".to_string()),
+                    ..WriteOptions::default()
+                },
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("Hook 'test ! -f {}' failed"));
+        assert_eq!(read_yaml(&file)["count"].as_i64(), Some(2));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tojson_output_dir_recreates_subtree() {
+        let dir = temp_dir("tojson_tree");
+        let configs = dir.join("configs");
+        let output = dir.join("out");
+        fs::create_dir_all(configs.join("nested")).unwrap();
+        fs::write(configs.join("app.yaml"), "name: app\n").unwrap();
+        fs::write(configs.join("nested/db.yml"), "port: 5432\n").unwrap();
+        fs::write(configs.join("notes.txt"), "ignored").unwrap();
+
+        execute_command(
+            Command::ToJson {
+                paths: vec![configs.display().to_string()],
+                output_dir: Some(output.display().to_string()),
+                recursive: true,
+                ascii: false,
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(output.join("app.json")).unwrap(),
+            "{\n  \"name\": \"app\"\n}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(output.join("nested/db.json")).unwrap(),
+            "{\n  \"port\": 5432\n}\n"
+        );
+        assert!(!output.join("notes.json").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_command_reports_missing_key() {
+        let dir = temp_dir("get_missing");
+        let file = dir.join("config.yaml");
+        fs::write(&file, "database:\n  host: db\n").unwrap();
+
+        let get = |key: &str| {
+            execute_command(
+                Command::Get {
+                    file: file.display().to_string(),
+                    key: key.to_string(),
+                    options: GetOptions::default(),
+                },
+                &GlobalOptions::default(),
+            )
+        };
+        assert!(get("database.host").is_ok());
+        assert!(get("database").is_ok());
+        let error = get("database.port").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Key 'database.port' not found in '{}'", file.display())
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_grep_and_get_on_empty_file_find_nothing() {
+        let dir = temp_dir("empty_read");
+        let file = dir.join("empty.yaml");
+        fs::write(&file, "").unwrap();
+
+        let grep = execute_command(
+            Command::Grep {
+                pattern: "host".to_string(),
+                files: vec![file.display().to_string()],
+                options: GrepOptions::default(),
+            },
+            &GlobalOptions::default(),
+        );
+        // Like any file without a match: exit status 2, no error printed.
+        assert!(is_no_matches_error(&grep.unwrap_err()));
+
+        let get = execute_command(
+            Command::Get {
+                file: file.display().to_string(),
+                key: "host".to_string(),
+                options: GetOptions::default(),
+            },
+            &GlobalOptions::default(),
+        );
+        assert_eq!(
+            get.unwrap_err().to_string(),
+            format!("Key 'host' not found in '{}'", file.display())
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tojson_directory_requires_recursive_flag() {
+        let dir = temp_dir("tojson_no_recursive");
+
+        let error = execute_command(
+            Command::ToJson {
+                paths: vec![dir.display().to_string()],
+                output_dir: Some(dir.join("out").display().to_string()),
+                recursive: false,
+                ascii: false,
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("use -R"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cp_command_copies_value_between_files() {
+        let dir = temp_dir("cp_semantics");
+        let source = dir.join("source.yaml");
+        let dest = dir.join("dest.yaml");
+
+        fs::write(&source, "source:\n  value: copied\n  enabled: true\n").unwrap();
+        fs::write(&dest, "existing: item\n").unwrap();
+
+        execute_command(
+            Command::Cp {
+                source_file: source.display().to_string(),
+                source_keys: vec!["source".to_string()],
+                dest_file: Some(dest.display().to_string()),
+                dest_keys: Some(vec!["copied.settings".to_string()]),
+                options: Default::default(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
+        .unwrap();
+
+        let dest_yaml = read_yaml(&dest);
+        assert_eq!(
+            dest_yaml["copied"]["settings"]["value"].as_str(),
+            Some("copied")
+        );
+        assert_eq!(
+            dest_yaml["copied"]["settings"]["enabled"].as_bool(),
+            Some(true)
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cp_command_preserves_number_text_when_asked() {
+        let dir = temp_dir("cp_preserve_numbers");
+        let file = dir.join("config.yaml");
+        let copy = |global: &GlobalOptions| {
+            fs::write(&file, "version: 1.10\n").unwrap();
+            execute_command(
+                Command::Cp {
+                    source_file: file.display().to_string(),
+                    source_keys: vec!["version".to_string()],
+                    dest_file: None,
+                    dest_keys: Some(vec!["copy".to_string()]),
+                    options: Default::default(),
+                    write: WriteOptions::default(),
+                },
+                global,
+            )
+            .unwrap();
+            fs::read_to_string(&file).unwrap()
+        };
+
+        assert_eq!(
+            copy(&GlobalOptions::default()),
+            "version: 1.10\ncopy: 1.1\n"
+        );
+        let global = GlobalOptions {
+            preserve_numbers_as_strings: true,
+            ..GlobalOptions::default()
+        };
+        assert_eq!(copy(&global), "version: 1.10\ncopy: '1.10'\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mv_command_moves_value_between_files() {
         let dir = temp_dir("mv_semantics");
         let source = dir.join("source.yaml");
         let dest = dir.join("dest.yaml");
@@ -381,12 +3276,17 @@ mod tests {
         fs::write(&source, "move_me:\n  key: value\nkeep: still\n").unwrap();
         fs::write(&dest, "name: app\n").unwrap();
 
-        execute_command(Command::Mv {
-            source_file: source.display().to_string(),
-            source_key: "move_me".to_string(),
-            dest_file: Some(dest.display().to_string()),
-            dest_key: Some("new_key".to_string()),
-        })
+        execute_command(
+            Command::Mv {
+                source_file: source.display().to_string(),
+                source_keys: vec!["move_me".to_string()],
+                dest_file: Some(dest.display().to_string()),
+                dest_keys: Some(vec!["new_key".to_string()]),
+                options: Default::default(),
+                write: WriteOptions::default(),
+            },
+            &GlobalOptions::default(),
+        )
         .unwrap();
 
         let source_yaml = read_yaml(&source);
@@ -397,4 +3297,29 @@ mod tests {
 
         fs::remove_dir_all(dir).unwrap();
     }
+
+    #[test]
+    fn test_keys_glob_keeps_matching_subtree() {
+        let document: Value =
+            serde_yaml::from_str("database:\n  host: a\n  pool:\n    size: 5\nport: 80\n").unwrap();
+
+        assert_eq!(
+            matching_keys(&document, false, Some("database.*")).unwrap(),
+            vec!["database.host", "database.pool", "database.pool.size"]
+        );
+        assert_eq!(
+            matching_keys(&document, true, Some("*.size")).unwrap(),
+            vec!["database.pool.size"]
+        );
+    }
+
+    #[test]
+    fn test_keys_glob_matching_nothing_lists_nothing() {
+        let document: Value = serde_yaml::from_str("database:\n  host: a\nport: 80\n").unwrap();
+
+        assert!(matching_keys(&document, false, Some("cache.*"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(matching_keys(&document, false, None).unwrap().len(), 3);
+    }
 }
@@ -102,6 +102,16 @@ impl YamlPath {
         Ok(Self { segments })
     }
 
+    pub fn from_key(key: impl Into<String>) -> Self {
+        Self {
+            segments: vec![PathSegment::Key(key.into())],
+        }
+    }
+
+    pub fn from_segments(segments: Vec<PathSegment>) -> Self {
+        Self { segments }
+    }
+
     pub fn as_segments(&self) -> &[PathSegment] {
         &self.segments
     }
@@ -128,6 +138,16 @@ impl YamlPath {
         }
     }
 
+    /// Every enclosing path, outermost first: `a.b[0].c` yields `a`, `a.b`
+    /// and `a.b[0]`.
+    pub fn ancestors(&self) -> Vec<Self> {
+        (1..self.segments.len())
+            .map(|len| Self {
+                segments: self.segments[..len].to_vec(),
+            })
+            .collect()
+    }
+
     pub fn last(&self) -> Option<&PathSegment> {
         self.segments.last()
     }
@@ -138,6 +158,12 @@ impl YamlPath {
         Self { segments }
     }
 
+    pub fn push_index(&self, index: usize) -> Self {
+        let mut segments = self.segments.clone();
+        segments.push(PathSegment::Index(index));
+        Self { segments }
+    }
+
     pub fn prefixes_requiring_mapping(&self) -> Vec<Self> {
         (0..self.segments.len().saturating_sub(1))
             .filter(|&index| matches!(self.segments[index + 1], PathSegment::Key(_)))
@@ -179,9 +205,47 @@ impl YamlPath {
     }
 }
 
+/// Splits an RFC 6901 JSON Pointer into its unescaped reference tokens.
+pub fn pointer_tokens(pointer: &str) -> AppResult<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(AppError::invalid_path(
+            pointer,
+            "JSON pointer must start with '/'",
+        ));
+    };
+
+    rest.split('/')
+        .map(|token| {
+            let mut unescaped = String::new();
+            let mut chars = token.chars();
+            while let Some(ch) = chars.next() {
+                if ch != '~' {
+                    unescaped.push(ch);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => {
+                        return Err(AppError::invalid_path(
+                            pointer,
+                            "'~' must be followed by '0' or '1'",
+                        ))
+                    }
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{PathSegment, YamlPath};
+    use super::{pointer_tokens, PathSegment, YamlPath};
 
     #[test]
     fn parses_keys_and_indices() {
@@ -207,8 +271,32 @@ mod tests {
         assert_eq!(path.display(), r"kubernetes\.io/hostname");
     }
 
+    #[test]
+    fn lists_ancestors_outermost_first() {
+        let path = YamlPath::parse("items[1].metadata.name").unwrap();
+        let ancestors: Vec<_> = path.ancestors().iter().map(YamlPath::display).collect();
+
+        assert_eq!(ancestors, ["items", "items[1]", "items[1].metadata"]);
+        assert!(YamlPath::parse("name").unwrap().ancestors().is_empty());
+    }
+
     #[test]
     fn rejects_empty_segment() {
         assert!(YamlPath::parse("a..b").is_err());
     }
+
+    #[test]
+    fn splits_and_unescapes_json_pointers() {
+        assert_eq!(
+            pointer_tokens("/servers/0/host").unwrap(),
+            vec!["servers", "0", "host"]
+        );
+        assert_eq!(
+            pointer_tokens("/labels/kubernetes.io~1name/a~0b~01").unwrap(),
+            vec!["labels", "kubernetes.io/name", "a~b~1"]
+        );
+        assert!(pointer_tokens("").unwrap().is_empty());
+        assert!(pointer_tokens("database").is_err());
+        assert!(pointer_tokens("/a~2").is_err());
+    }
 }
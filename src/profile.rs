@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Phase totals shared by every thread, so that `--jobs` workers add to the
+/// same report.
+static PROFILE: Profile = Profile::new();
+
+struct Profile {
+    enabled: AtomicBool,
+    phases: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Profile {
+    const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn time<T>(&self, phase: &'static str, work: impl FnOnce() -> T) -> T {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return work();
+        }
+
+        let start = Instant::now();
+        let result = work();
+        let elapsed = start.elapsed();
+
+        let mut phases = self.phases.lock().unwrap();
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+        result
+    }
+
+    fn report(&self) -> Vec<String> {
+        self.phases
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(phase, total)| format!("profile: {phase} {:.6}s", total.as_secs_f64()))
+            .collect()
+    }
+}
+
+pub fn enable() {
+    PROFILE.enabled.store(true, Ordering::Relaxed);
+}
+
+/// Runs `work`, adding its duration to `phase` when profiling is enabled.
+pub fn time<T>(phase: &'static str, work: impl FnOnce() -> T) -> T {
+    PROFILE.time(phase, work)
+}
+
+/// One `profile: PHASE SECONDS` line per phase, in the order phases started.
+pub fn report() -> Vec<String> {
+    PROFILE.report()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_time_only_records_when_enabled() {
+        let profile = Profile::new();
+        assert_eq!(profile.time("parse", || 1), 1);
+        assert!(profile.report().is_empty());
+
+        profile.enabled.store(true, Ordering::Relaxed);
+        profile.time("parse", || ());
+        profile.time("match", || ());
+        profile.time("parse", || ());
+        let lines = profile.report();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("profile: parse "));
+        assert!(lines[1].starts_with("profile: match "));
+    }
+
+    #[test]
+    fn test_time_adds_up_phases_from_every_thread() {
+        let profile = Profile::new();
+        profile.enabled.store(true, Ordering::Relaxed);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| profile.time("match", || thread::sleep(Duration::from_millis(5))));
+            }
+        });
+        let total = profile.phases.lock().unwrap()[0].1;
+        assert!(total >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::{AppError, AppResult};
+use crate::yaml_ops::{self, EditOptions};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Operation {
+    Set(String, String),
+    Unset(Vec<String>),
+    Cp(String, String),
+    Mv(String, String),
+}
+
+/// The operations of a `run` script, one per line: `set key=value`,
+/// `unset key...`, `cp source dest` and `mv source dest`, the keys all
+/// naming paths in the edited file. Blank lines and `#` comments are
+/// skipped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Script {
+    operations: Vec<Operation>,
+}
+
+impl Script {
+    pub fn parse(script: &str) -> AppResult<Self> {
+        let operations = script
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                !line.is_empty() && !line.starts_with('#')
+            })
+            .map(|(index, line)| {
+                Operation::parse(line.trim())
+                    .map_err(|error| AppError::cli(format!("Line {}: {error}", index + 1)))
+            })
+            .collect::<AppResult<_>>()?;
+        Ok(Self { operations })
+    }
+
+    /// Applies every operation in turn to `yaml_content`.
+    pub fn apply(&self, yaml_content: &str, options: &EditOptions) -> AppResult<String> {
+        self.operations
+            .iter()
+            .try_fold(yaml_content.to_string(), |content, operation| {
+                operation.apply(&content, options)
+            })
+    }
+}
+
+impl Operation {
+    fn parse(line: &str) -> Result<Self, String> {
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let arguments: Vec<String> = rest.split_whitespace().map(str::to_string).collect();
+
+        match (name, arguments.as_slice()) {
+            // The value runs to the end of the line, spaces included.
+            ("set", [_, ..]) => {
+                let (key, value) = rest
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid key=value pair: {rest}"))?;
+                Ok(Self::Set(key.trim().to_string(), value.trim().to_string()))
+            }
+            ("unset", [_, ..]) => Ok(Self::Unset(arguments)),
+            ("cp", [source, dest]) => Ok(Self::Cp(source.clone(), dest.clone())),
+            ("mv", [source, dest]) => Ok(Self::Mv(source.clone(), dest.clone())),
+            ("set" | "unset" | "cp" | "mv", _) => Err(format!("Invalid arguments: {line}")),
+            _ => Err(format!("Unknown operation: {name}")),
+        }
+    }
+
+    fn apply(&self, yaml_content: &str, options: &EditOptions) -> AppResult<String> {
+        match self {
+            Self::Set(key, value) => {
+                yaml_ops::set_values(yaml_content, &[(key.clone(), value.clone())], options)
+            }
+            Self::Unset(keys) => yaml_ops::unset_values(yaml_content, keys),
+            Self::Cp(source, dest) => {
+                yaml_ops::copy_in_document(yaml_content, source, dest, options)
+            }
+            Self::Mv(source, dest) => {
+                let keys = [(source.clone(), dest.clone())];
+                yaml_ops::move_in_document(yaml_content, &keys, options)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reports_the_failing_line() {
+        let script = Script::parse("# setup\nset a=hello world\n\nunset b c\ncp x y\nmv y z\n");
+        assert_eq!(
+            script.unwrap().operations,
+            vec![
+                Operation::Set("a".to_string(), "hello world".to_string()),
+                Operation::Unset(vec!["b".to_string(), "c".to_string()]),
+                Operation::Cp("x".to_string(), "y".to_string()),
+                Operation::Mv("y".to_string(), "z".to_string()),
+            ]
+        );
+
+        let error = Script::parse("set a=1\ncp x\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "CLI error: Line 2: Invalid arguments: cp x"
+        );
+        let error = Script::parse("rm a\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "CLI error: Line 1: Unknown operation: rm"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+/// Counts the lines inserted and deleted to turn `before` into `after`, as
+/// the longest common subsequence of their lines leaves them.
+pub fn count(before: &str, after: &str) -> (usize, usize) {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+
+    // Edits touch a few lines, so only the middle needs the quadratic table.
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(left, right)| left == right)
+        .count();
+    let before = &before[prefix..before.len() - suffix];
+    let after = &after[prefix..after.len() - suffix];
+
+    let mut previous = vec![0; after.len() + 1];
+    for line in before {
+        let mut current = vec![0; after.len() + 1];
+        for (index, other) in after.iter().enumerate() {
+            current[index + 1] = if line == other {
+                previous[index] + 1
+            } else {
+                current[index].max(previous[index + 1])
+            };
+        }
+        previous = current;
+    }
+
+    let common = previous[after.len()];
+    (after.len() - common, before.len() - common)
+}
+
+/// The `--stat` line for `file`: `file: N insertions(+), M deletions(-)`.
+pub fn summary(file: &str, before: &str, after: &str) -> String {
+    let (insertions, deletions) = count(before, after);
+    format!(
+        "{file}: {insertions} {}(+), {deletions} {}(-)",
+        plural(insertions, "insertion"),
+        plural(deletions, "deletion")
+    )
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        word.to_string()
+    } else {
+        format!("{word}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_counts_changed_lines() {
+        let before = "a: 1\nb: 2\nc: 3\n";
+        let after = "a: 1\nb: 20\nc: 3\nd: 4\ne: 5\n";
+
+        assert_eq!(count(before, after), (3, 1));
+        assert_eq!(count(before, before), (0, 0));
+        assert_eq!(
+            summary("app.yaml", before, after),
+            "app.yaml: 3 insertions(+), 1 deletion(-)"
+        );
+    }
+}
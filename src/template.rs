@@ -0,0 +1,77 @@
+use serde_yaml::Value;
+
+use crate::error::{AppError, AppResult};
+use crate::yaml_ops;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// Replaces each `{{ key.path }}` in `template` with that value of `document`;
+/// `\{{` stands for a literal `{{`. Missing keys are an error unless
+/// `keep_missing` is set, which leaves their placeholder as written.
+pub fn render(template: &str, document: &Value, keep_missing: bool) -> AppResult<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find(OPEN) {
+        if let Some(text) = rest[..start].strip_suffix('\\') {
+            rendered.push_str(text);
+            rendered.push_str(OPEN);
+            rest = &rest[start + OPEN.len()..];
+            continue;
+        }
+
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let end = placeholder.find(CLOSE).ok_or_else(|| {
+            AppError::message(format!("Unclosed placeholder in template: {placeholder}"))
+        })? + CLOSE.len();
+        let key = placeholder[OPEN.len()..end - CLOSE.len()].trim();
+
+        match yaml_ops::get_value(document, key)? {
+            Some(value) => rendered.push_str(&yaml_ops::format_value(&value)?),
+            None if keep_missing => rendered.push_str(&placeholder[..end]),
+            None => {
+                return Err(AppError::message(format!(
+                    "Key '{key}' not found for template placeholder"
+                )))
+            }
+        }
+        rest = &placeholder[end..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> Value {
+        serde_yaml::from_str("database:\n  host: db.internal\n  ports: [5432, 5433]\nname: app\n")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_render_substitutes_nested_paths_and_escaped_braces() {
+        let template = "{{name}} -> {{ database.host }}:{{ database.ports[1] }} \\{{ raw }}\n";
+
+        assert_eq!(
+            render(template, &document(), false).unwrap(),
+            "app -> db.internal:5433 {{ raw }}\n"
+        );
+    }
+
+    #[test]
+    fn test_render_missing_key_errors_or_keeps_placeholder() {
+        let template = "host={{ database.user }}";
+
+        let error = render(template, &document(), false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Key 'database.user' not found for template placeholder"
+        );
+        assert_eq!(render(template, &document(), true).unwrap(), template);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::error::AppResult;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Each watched file with its modification time and length; files appearing,
+/// disappearing or being rewritten all change the snapshot.
+type Snapshot = Vec<(PathBuf, Option<SystemTime>, Option<u64>)>;
+
+/// Polls snapshots from `poll`, waiting between polls with `wait`, and
+/// yields once per burst of changes: after a change is seen, it waits until
+/// the snapshots stop changing.
+pub struct Changes<P, W> {
+    poll: P,
+    wait: W,
+    last: Snapshot,
+}
+
+/// Changes to the files listed by `list`, polled on the real clock.
+pub fn file_changes(
+    mut list: impl FnMut() -> AppResult<Vec<PathBuf>>,
+) -> AppResult<Changes<impl FnMut() -> AppResult<Snapshot>, impl FnMut(Duration)>> {
+    Changes::new(move || Ok(snapshot(list()?)), thread::sleep)
+}
+
+impl<P: FnMut() -> AppResult<Snapshot>, W: FnMut(Duration)> Changes<P, W> {
+    pub fn new(mut poll: P, wait: W) -> AppResult<Self> {
+        let last = poll()?;
+        Ok(Self { poll, wait, last })
+    }
+}
+
+impl<P: FnMut() -> AppResult<Snapshot>, W: FnMut(Duration)> Iterator for Changes<P, W> {
+    type Item = AppResult<()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut changed = loop {
+            (self.wait)(POLL_INTERVAL);
+            match (self.poll)() {
+                Ok(current) if current == self.last => {}
+                current => break current,
+            }
+        };
+
+        while let Ok(current) = &changed {
+            (self.wait)(DEBOUNCE);
+            let again = (self.poll)();
+            if again.as_ref().ok() == Some(current) {
+                break;
+            }
+            changed = again;
+        }
+        Some(changed.map(|current| self.last = current))
+    }
+}
+
+fn snapshot(paths: Vec<PathBuf>) -> Snapshot {
+    paths
+        .into_iter()
+        .map(|path| {
+            let metadata = fs::metadata(&path).ok();
+            let modified = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.modified().ok());
+            (path, modified, metadata.map(|metadata| metadata.len()))
+        })
+        .collect()
+}
+
+/// Runs `search` once, then again after every change until `changes` ends
+/// or fails.
+pub fn run(
+    changes: impl IntoIterator<Item = AppResult<()>>,
+    mut search: impl FnMut(),
+) -> AppResult<()> {
+    search();
+    for change in changes {
+        change?;
+        search();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+
+    #[test]
+    fn test_run_searches_again_after_each_change() {
+        let mut searches = 0;
+        run([Ok(()), Ok(())], || searches += 1).unwrap();
+        assert_eq!(searches, 3);
+
+        let mut searches = 0;
+        let changes = [Ok(()), Err(AppError::message("gone")), Ok(())];
+        assert!(run(changes, || searches += 1).is_err());
+        assert_eq!(searches, 2);
+    }
+
+    /// A source replaying `lengths` as snapshots of one file, failing once
+    /// they run out so that the watch ends.
+    fn replay(lengths: &[u64]) -> impl FnMut() -> AppResult<Snapshot> + '_ {
+        let mut lengths = lengths.iter();
+        move || match lengths.next() {
+            Some(&length) => Ok(vec![(PathBuf::from("a.yaml"), None, Some(length))]),
+            None => Err(AppError::message("done")),
+        }
+    }
+
+    #[test]
+    fn test_changes_search_again_after_a_change() {
+        let mut waits = Vec::new();
+        let changes = Changes::new(replay(&[1, 1, 2, 2]), |wait| waits.push(wait)).unwrap();
+
+        let mut searches = 0;
+        assert!(run(changes, || searches += 1).is_err());
+        assert_eq!(searches, 2);
+        assert_eq!(
+            waits,
+            [POLL_INTERVAL, POLL_INTERVAL, DEBOUNCE, POLL_INTERVAL]
+        );
+    }
+
+    #[test]
+    fn test_changes_debounce_quick_writes_into_one_search() {
+        // Two writes land within the debounce interval, then a third later.
+        let lengths = [1, 2, 3, 3, 1, 4, 4, 4];
+        let changes = Changes::new(replay(&lengths), |_| {}).unwrap();
+
+        let mut searches = 0;
+        assert!(run(changes.take(1), || searches += 1).is_ok());
+        assert_eq!(searches, 2);
+
+        let changes = Changes::new(replay(&lengths), |_| {}).unwrap();
+        let mut searches = 0;
+        assert!(run(changes, || searches += 1).is_err());
+        assert_eq!(searches, 3);
+    }
+
+    #[test]
+    fn test_snapshot_sees_rewrites_and_missing_files() {
+        let dir = std::env::temp_dir().join(format!("ym_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.yaml");
+        fs::write(&file, "a: 1\n").unwrap();
+
+        let before = snapshot(vec![file.clone()]);
+        fs::write(&file, "a: 10\n").unwrap();
+        assert_ne!(snapshot(vec![file.clone()]), before);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(snapshot(vec![file.clone()]), vec![(file, None, None)]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
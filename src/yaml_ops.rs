@@ -1,15 +1,36 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_yaml::value::TaggedValue;
 use serde_yaml::Value;
 use yamlpatch::{Op, Patch};
 use yamlpath::Document;
 
 use crate::error::{AppError, AppResult};
-use crate::path::{PathSegment, YamlPath};
+use crate::path::{pointer_tokens, PathSegment, YamlPath};
 
 const PLACEHOLDER_KEY: &str = "__ym_placeholder__";
+const DEFAULT_INDENT: usize = 2;
+
+/// How edits read values and lay out the YAML they have to create.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EditOptions {
+    /// Spaces newly created mappings are indented past their parent key;
+    /// the file's own indentation when unset.
+    pub indent: Option<usize>,
+    /// Keeps numbers whose text would not survive a round trip (`1.10`,
+    /// `007`, `0x1F`) as strings holding that text, both in files and in
+    /// set values.
+    pub preserve_numbers: bool,
+    /// Lets `key[0]` replace a scalar `key` with a one-item sequence instead
+    /// of rejecting it.
+    pub force_array: bool,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GrepOutputMode {
@@ -17,708 +38,4020 @@ pub enum GrepOutputMode {
     Full,
 }
 
-pub fn grep(value: &Value, pattern: &str) -> AppResult<Vec<(String, Value)>> {
-    let regex = Regex::new(pattern)?;
-    let mut results = Vec::new();
-    let mut path = Vec::new();
-    collect_matching_keys(value, &regex, &mut path, &mut results);
-    Ok(results)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferOptions {
+    pub with_anchor: bool,
+    pub same_type_only: bool,
+    /// Deep-merge a copied mapping into the destination mapping instead of
+    /// replacing it.
+    pub merge: bool,
+    /// Let `cp` overwrite a destination key that already exists; implied for
+    /// values of the same type by `same_type_only`.
+    pub force: bool,
 }
 
-fn collect_matching_keys(
-    value: &Value,
-    regex: &Regex,
-    path: &mut Vec<PathSegment>,
-    results: &mut Vec<(String, Value)>,
-) {
-    match value {
-        Value::Mapping(map) => {
-            for (key, value) in map {
-                let Value::String(key) = key else {
-                    continue;
-                };
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
 
-                path.push(PathSegment::Key(key.clone()));
-                let rendered = render_path(path);
-                if regex.is_match(&rendered) {
-                    results.push((rendered, value.clone()));
-                } else {
-                    collect_matching_keys(value, regex, path, results);
-                }
-                path.pop();
+/// Parses `contents`; with `preserve_numbers`, YAML numbers whose text would
+/// not survive a round trip are read as strings holding that text.
+pub fn parse_input(
+    contents: &str,
+    format: InputFormat,
+    context: &str,
+    preserve_numbers: bool,
+) -> AppResult<Value> {
+    match format {
+        InputFormat::Yaml => read_yaml_document(contents, context, preserve_numbers),
+        InputFormat::Json => serde_json::from_str(contents)
+            .map_err(|error| AppError::parse_json(context.to_string(), error)),
+        InputFormat::Toml => toml::from_str(contents)
+            .map_err(|error| AppError::parse_toml(context.to_string(), error)),
+    }
+}
+
+/// Parses every `---` separated document of `contents`; JSON input and
+/// single-document YAML give exactly one value.
+pub fn parse_input_documents(
+    contents: &str,
+    format: InputFormat,
+    context: &str,
+    preserve_numbers: bool,
+) -> AppResult<Vec<Value>> {
+    if matches!(format, InputFormat::Yaml) && contents.contains("\n---") {
+        let documents = serde_yaml::Deserializer::from_str(contents)
+            .map(Value::deserialize)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| AppError::parse_yaml(context.to_string(), error))?;
+        if documents.len() > 1 && preserve_numbers {
+            // Number text is looked up per document, in that document's text.
+            let texts = document_texts(contents);
+            if texts.len() == documents.len() {
+                return texts
+                    .iter()
+                    .map(|text| read_yaml_document(text, context, true))
+                    .collect();
             }
         }
-        Value::Sequence(sequence) => {
-            for (index, value) in sequence.iter().enumerate() {
-                path.push(PathSegment::Index(index));
-                collect_matching_keys(value, regex, path, results);
-                path.pop();
-            }
+        if documents.len() > 1 {
+            return Ok(documents);
         }
-        _ => {}
     }
+
+    Ok(vec![parse_input(
+        contents,
+        format,
+        context,
+        preserve_numbers,
+    )?])
 }
 
-fn render_path(path: &[PathSegment]) -> String {
-    let mut rendered = String::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Null,
+    Mapping,
+    Sequence,
+}
 
-    for segment in path {
-        match segment {
-            PathSegment::Key(key) => {
-                if !rendered.is_empty() {
-                    rendered.push('.');
-                }
+impl ValueType {
+    pub fn parse(input: &str) -> AppResult<Self> {
+        match input {
+            "string" => Ok(Self::String),
+            "int" => Ok(Self::Int),
+            "float" => Ok(Self::Float),
+            "bool" => Ok(Self::Bool),
+            "null" => Ok(Self::Null),
+            "mapping" => Ok(Self::Mapping),
+            "sequence" => Ok(Self::Sequence),
+            _ => Err(AppError::cli(format!(
+                "Unknown value type: {input} (expected string, int, float, bool, null, mapping or sequence)"
+            ))),
+        }
+    }
 
-                for ch in key.chars() {
-                    match ch {
-                        '.' | '[' | ']' | '\\' => {
-                            rendered.push('\\');
-                            rendered.push(ch);
-                        }
-                        other => rendered.push(other),
-                    }
-                }
-            }
-            PathSegment::Index(index) => {
-                rendered.push('[');
-                rendered.push_str(&index.to_string());
-                rendered.push(']');
-            }
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::String(_) => Self::String,
+            Value::Number(number) if number.is_f64() => Self::Float,
+            Value::Number(_) => Self::Int,
+            Value::Bool(_) => Self::Bool,
+            Value::Null => Self::Null,
+            Value::Mapping(_) => Self::Mapping,
+            Value::Sequence(_) => Self::Sequence,
+            Value::Tagged(tagged) => Self::of(&tagged.value),
         }
     }
 
-    rendered
-}
+    fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Int => "int",
+            Self::Float => "float",
+            Self::Bool => "bool",
+            Self::Null => "null",
+            Self::Mapping => "mapping",
+            Self::Sequence => "sequence",
+        }
+    }
 
-fn parse_user_value(input: &str) -> AppResult<Value> {
-    serde_yaml::from_str(input)
-        .map_err(|error| AppError::parse_yaml(format!("from value '{input}'"), error))
+    pub fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::String, Value::String(_)) => true,
+            (Self::Int, Value::Number(number)) => number.is_i64() || number.is_u64(),
+            (Self::Float, Value::Number(number)) => number.is_f64(),
+            (Self::Bool, Value::Bool(_)) => true,
+            (Self::Null, Value::Null) => true,
+            (Self::Mapping, Value::Mapping(_)) => true,
+            (Self::Sequence, Value::Sequence(_)) => true,
+            _ => false,
+        }
+    }
 }
 
-fn parse_yaml_document(yaml_content: &str, context: &str) -> AppResult<Value> {
-    serde_yaml::from_str(yaml_content)
-        .map_err(|error| AppError::parse_yaml(context.to_string(), error))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    Upper,
+    Lower,
+    Trim,
+    Base64,
+    Length,
 }
 
-fn apply_patch(yaml_content: &str, patch: Patch<'static>) -> AppResult<String> {
-    let document =
-        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
-    let updated = yamlpatch::apply_yaml_patches(&document, &[patch])
-        .map_err(|error| AppError::patch(error.to_string()))?;
-    Ok(updated.source().to_string())
-}
+impl Transform {
+    pub fn parse(flag: &str) -> AppResult<Self> {
+        match flag {
+            "--upper" => Ok(Self::Upper),
+            "--lower" => Ok(Self::Lower),
+            "--trim" => Ok(Self::Trim),
+            "--base64" => Ok(Self::Base64),
+            "--length" => Ok(Self::Length),
+            _ => Err(AppError::cli(format!(
+                "Unknown transform: {flag} (expected --upper, --lower, --trim, --base64 or --length)"
+            ))),
+        }
+    }
 
-fn placeholder_mapping() -> Value {
-    let mut placeholder = serde_yaml::Mapping::new();
-    placeholder.insert(Value::String(PLACEHOLDER_KEY.to_string()), Value::Null);
-    Value::Mapping(placeholder)
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Upper => text.to_uppercase(),
+            Self::Lower => text.to_lowercase(),
+            Self::Trim => text.trim().to_string(),
+            Self::Base64 => base64_encode(text.as_bytes()),
+            Self::Length => text.chars().count().to_string(),
+        }
+    }
 }
 
-fn yaml_set(yaml_content: &str, key_path: &str, new_value: Value) -> AppResult<String> {
-    let path = YamlPath::parse(key_path)?;
-    let mut result = yaml_content.to_string();
-
-    for prefix in path.prefixes_requiring_mapping() {
-        result = ensure_mapping_at_path(&result, &prefix)?;
+/// Applies `transforms` in order to the text of a scalar value.
+pub fn transform_value(value: &Value, transforms: &[Transform]) -> AppResult<String> {
+    if matches!(value, Value::Mapping(_) | Value::Sequence(_)) {
+        return Err(AppError::message("Transforms only apply to scalar values"));
     }
 
-    let current = parse_yaml_document(&result, "from document")?;
-    let existing_value = get_value_at_path(&current, &path)?;
-    let setting_mapping = matches!(new_value, Value::Mapping(_));
-
-    let updated = match new_value {
-        Value::Mapping(new_map) => set_mapping_at_path(&result, &path, existing_value, new_map)?,
-        new_value => match existing_value {
-            Some(_) => replace_value_at_path(&result, &path, new_value)?,
-            None => add_value_at_path(&result, &path, new_value)?,
-        },
-    };
-
-    cleanup_placeholders(&updated, &cleanup_paths_for_set(&path, setting_mapping))
+    Ok(transforms
+        .iter()
+        .fold(format_inline_value(value), |text, transform| {
+            transform.apply(&text)
+        }))
 }
 
-fn ensure_mapping_at_path(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
-    let current = parse_yaml_document(yaml_content, "from document")?;
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
 
-    match get_value_at_path(&current, path)? {
-        Some(Value::Mapping(_)) => Ok(yaml_content.to_string()),
-        Some(_) => replace_with_empty_mapping_at_path(yaml_content, path),
-        None => add_empty_mapping_at_path(yaml_content, path),
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| {
+            group | (u32::from(*byte) << (16 - 8 * index))
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
     }
-}
 
-fn replace_with_empty_mapping_at_path(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
-    let removed = remove_at_path(yaml_content, path)?;
-    add_empty_mapping_at_path(&removed, path)
+    encoded
 }
 
-fn add_empty_mapping_at_path(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
-    let parent = path
-        .parent()
-        .map(|parent| parent.to_route())
-        .unwrap_or_default();
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    #[default]
+    OverlayWins,
+    BaseWins,
+    ErrorOnConflict,
+}
 
-    let Some(PathSegment::Key(key)) = path.last() else {
-        return Err(AppError::message(format!(
-            "Cannot create a mapping at sequence path '{}'",
-            path.display()
-        )));
-    };
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStrategy {
+    #[default]
+    Replace,
+    Append,
+    Union,
+}
 
-    apply_patch(
-        yaml_content,
-        Patch {
-            route: parent,
-            operation: Op::Add {
-                key: key.clone(),
-                value: placeholder_mapping(),
-            },
-        },
-    )
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MergeStrategy {
+    pub conflicts: ConflictStrategy,
+    pub sequences: SequenceStrategy,
 }
 
-fn add_value_at_path(yaml_content: &str, path: &YamlPath, new_value: Value) -> AppResult<String> {
-    match path.last() {
-        Some(PathSegment::Key(key)) => {
-            let parent = path
-                .parent()
-                .map(|parent| parent.to_route())
-                .unwrap_or_default();
-            apply_patch(
-                yaml_content,
-                Patch {
-                    route: parent,
-                    operation: Op::Add {
-                        key: key.clone(),
-                        value: new_value,
-                    },
-                },
-            )
-        }
-        Some(PathSegment::Index(index)) => {
-            append_value_at_path(yaml_content, path, *index, new_value)
+impl MergeStrategy {
+    /// Parses a comma-separated list such as `base-wins,union`; each name
+    /// sets either the conflict or the sequence strategy.
+    pub fn parse(input: &str) -> AppResult<Self> {
+        let mut strategy = Self::default();
+        for name in input.split(',').map(str::trim) {
+            match name {
+                "overlay-wins" => strategy.conflicts = ConflictStrategy::OverlayWins,
+                "base-wins" => strategy.conflicts = ConflictStrategy::BaseWins,
+                "error-on-conflict" => strategy.conflicts = ConflictStrategy::ErrorOnConflict,
+                "replace" => strategy.sequences = SequenceStrategy::Replace,
+                "append" => strategy.sequences = SequenceStrategy::Append,
+                "union" => strategy.sequences = SequenceStrategy::Union,
+                _ => {
+                    return Err(AppError::cli(format!(
+                        "Unknown merge strategy: {name} (expected overlay-wins, base-wins, \
+                         error-on-conflict, replace, append or union)"
+                    )))
+                }
+            }
         }
-        None => Err(AppError::message("Empty key path")),
+        Ok(strategy)
     }
 }
 
-fn append_value_at_path(
-    yaml_content: &str,
-    path: &YamlPath,
-    index: usize,
-    new_value: Value,
-) -> AppResult<String> {
-    let parent_path = path.parent().ok_or_else(|| {
-        AppError::message(format!(
-            "Cannot set root sequence index '{}'",
-            path.display()
-        ))
-    })?;
-    let current = parse_yaml_document(yaml_content, "from document")?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStyle {
+    Snake,
+    Camel,
+    Kebab,
+}
 
-    match get_value_at_path(&current, &parent_path)? {
-        Some(Value::Sequence(sequence)) if index == sequence.len() => apply_patch(
-            yaml_content,
-            Patch {
-                route: parent_path.to_route(),
-                operation: Op::Append { value: new_value },
-            },
-        ),
-        Some(Value::Sequence(sequence)) => Err(AppError::message(format!(
-            "Cannot create sparse sequence entry at '{}'; next valid index is {}",
-            path.display(),
-            sequence.len()
-        ))),
-        Some(_) => Err(AppError::message(format!(
-            "Parent of '{}' is not a sequence",
-            path.display()
-        ))),
-        None => Err(AppError::message(format!(
-            "Parent sequence '{}' does not exist",
-            parent_path.display()
-        ))),
+impl KeyStyle {
+    pub fn parse(input: &str) -> AppResult<Self> {
+        match input {
+            "snake" => Ok(Self::Snake),
+            "camel" => Ok(Self::Camel),
+            "kebab" => Ok(Self::Kebab),
+            _ => Err(AppError::cli(format!(
+                "Unknown key style: {input} (expected snake, camel or kebab)"
+            ))),
+        }
+    }
+
+    /// Rewrites `key` in this style, splitting words on `_`, `-` and
+    /// lower-to-upper case transitions.
+    pub fn apply(self, key: &str) -> String {
+        let mut words: Vec<String> = Vec::new();
+        let mut previous_lower = false;
+        for ch in key.chars() {
+            if ch == '_' || ch == '-' {
+                words.push(String::new());
+                previous_lower = false;
+                continue;
+            }
+            if words.is_empty() || (ch.is_uppercase() && previous_lower) {
+                words.push(String::new());
+            }
+            previous_lower = ch.is_lowercase() || ch.is_ascii_digit();
+            if let Some(word) = words.last_mut() {
+                word.extend(ch.to_lowercase());
+            }
+        }
+        words.retain(|word| !word.is_empty());
+
+        match self {
+            Self::Snake => words.join("_"),
+            Self::Kebab => words.join("-"),
+            Self::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(index, word)| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) if index > 0 => first.to_uppercase().chain(chars).collect(),
+                        _ => word.clone(),
+                    }
+                })
+                .collect(),
+        }
     }
 }
 
-fn replace_value_at_path(
-    yaml_content: &str,
-    path: &YamlPath,
-    new_value: Value,
-) -> AppResult<String> {
-    apply_patch(
-        yaml_content,
-        Patch {
-            route: path.to_route(),
-            operation: Op::Replace(new_value),
-        },
-    )
+/// What grep tests its pattern against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchTarget {
+    #[default]
+    Keys,
+    Values,
+    /// A key matches when either its path or its value does.
+    Either,
 }
 
-fn remove_at_path(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
-    apply_patch(
-        yaml_content,
-        Patch {
-            route: path.to_route(),
-            operation: Op::Remove,
-        },
-    )
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchOptions {
+    pub target: MatchTarget,
+    pub value_type: Option<ValueType>,
+    pub any_separator: bool,
+    pub ignore_case: bool,
+    pub word_regexp: bool,
+    pub exact: bool,
+    /// Only test the document's own keys, never descending into their values.
+    pub top_level: bool,
+    /// Match keys case-insensitively and report each path lowercased, once.
+    pub fold_keys: bool,
+    pub regex_size_limit: Option<usize>,
 }
 
-fn set_mapping_at_path(
-    yaml_content: &str,
-    path: &YamlPath,
-    existing_value: Option<Value>,
-    new_map: serde_yaml::Mapping,
-) -> AppResult<String> {
-    let mut result = match existing_value {
-        Some(Value::Mapping(current_map)) => {
-            remove_missing_mapping_keys(yaml_content, path, &current_map, &new_map)?
-        }
-        Some(_) => replace_with_empty_mapping_at_path(yaml_content, path)?,
-        None => add_empty_mapping_at_path(yaml_content, path)?,
+pub fn compile_pattern(pattern: &str, options: &MatchOptions) -> AppResult<Regex> {
+    let pattern = if options.any_separator {
+        Cow::Owned(pattern.replace('/', r"\."))
+    } else {
+        Cow::Borrowed(pattern)
     };
 
-    for (key, value) in new_map {
-        let Value::String(key) = key else {
-            return Err(AppError::message(format!(
-                "Unsupported non-string key under '{}'",
-                path.display()
-            )));
-        };
+    let pattern = if options.word_regexp {
+        Cow::Owned(format!(r"\b(?:{pattern})\b"))
+    } else {
+        pattern
+    };
+
+    let pattern = if options.exact {
+        Cow::Owned(format!("^(?:{pattern})$"))
+    } else {
+        pattern
+    };
 
-        result = yaml_set(&result, &path.push_key(key).display(), value)?;
+    let mut builder = RegexBuilder::new(&pattern);
+    builder.case_insensitive(options.ignore_case || options.fold_keys);
+    if let Some(limit) = options.regex_size_limit {
+        builder.size_limit(limit).dfa_size_limit(limit);
     }
+    Ok(builder.build()?)
+}
 
-    cleanup_placeholders(&result, std::slice::from_ref(path))
+pub fn grep(value: &Value, regex: &Regex, options: &MatchOptions) -> Vec<(String, Value)> {
+    let mut results = Vec::new();
+    let mut path = Vec::new();
+    collect_matching_keys(value, regex, options, &mut path, &mut results);
+    if options.fold_keys {
+        return fold_keys(results);
+    }
+    results
 }
 
-fn remove_missing_mapping_keys(
-    yaml_content: &str,
-    path: &YamlPath,
-    current_map: &serde_yaml::Mapping,
-    new_map: &serde_yaml::Mapping,
-) -> AppResult<String> {
-    let mut result = yaml_content.to_string();
+/// Greps each document of `contents` as `grep` would, but walks the parser's
+/// output directly so only matched subtrees and scalars are ever built.
+/// Numbers are read as the parser gives them, never preserved as text.
+pub fn grep_stream(
+    contents: &str,
+    regex: &Regex,
+    options: &MatchOptions,
+    context: &str,
+) -> AppResult<Vec<Vec<(String, Value)>>> {
+    serde_yaml::Deserializer::from_str(contents)
+        .map(|document| {
+            let mut matcher = StreamMatcher {
+                regex,
+                options,
+                path: Vec::new(),
+                results: Vec::new(),
+            };
+            NodeSeed {
+                matcher: &mut matcher,
+                descend: true,
+            }
+            .deserialize(document)
+            .map_err(|error| AppError::parse_yaml(context.to_string(), error))?;
+            Ok(if options.fold_keys {
+                fold_keys(matcher.results)
+            } else {
+                matcher.results
+            })
+        })
+        .collect()
+}
 
-    for key in current_map.keys().filter_map(|key| match key {
-        Value::String(key) if key != PLACEHOLDER_KEY => Some(key.clone()),
-        _ => None,
-    }) {
-        if !new_map.contains_key(Value::String(key.clone())) {
-            result = remove_at_path(&result, &path.push_key(key))?;
-        }
-    }
+struct StreamMatcher<'a> {
+    regex: &'a Regex,
+    options: &'a MatchOptions,
+    path: Vec<PathSegment>,
+    results: Vec<(String, Value)>,
+}
 
-    Ok(result)
+/// Visits one node, recording matches below it. Scalars (and tagged nodes,
+/// which `grep` never descends into) are returned for the parent to test;
+/// mappings and sequences are searched in place, or skipped unless `descend`.
+struct NodeSeed<'m, 'a> {
+    matcher: &'m mut StreamMatcher<'a>,
+    descend: bool,
 }
 
-fn cleanup_paths_for_set(path: &YamlPath, setting_mapping: bool) -> Vec<YamlPath> {
-    let mut paths = path.prefixes_requiring_mapping();
-    paths.reverse();
-    if setting_mapping {
-        paths.insert(0, path.clone());
+impl<'de> DeserializeSeed<'de> for NodeSeed<'_, '_> {
+    type Value = Option<Value>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
     }
-    paths
 }
 
-fn cleanup_placeholders(yaml_content: &str, paths: &[YamlPath]) -> AppResult<String> {
-    let mut result = yaml_content.to_string();
-
-    for path in paths {
-        let current = parse_yaml_document(&result, "from document")?;
-        let placeholder_path = path.push_key(PLACEHOLDER_KEY);
-        if get_value_at_path(&current, &placeholder_path)?.is_some() {
-            result = remove_at_path(&result, &placeholder_path)?;
+impl<'a> NodeSeed<'_, 'a> {
+    fn child(&mut self) -> NodeSeed<'_, 'a> {
+        NodeSeed {
+            descend: !self.matcher.options.top_level,
+            matcher: &mut *self.matcher,
         }
     }
 
-    Ok(result)
+    fn record(&mut self, value: &Value, is_key: bool) {
+        let matcher = &mut *self.matcher;
+        if entry_matches(value, matcher.regex, matcher.options, &matcher.path, is_key) {
+            matcher
+                .results
+                .push((render_path(&matcher.path), value.clone()));
+        }
+    }
 }
 
-pub fn set_values(yaml_content: &str, updates: &[(String, String)]) -> AppResult<String> {
-    let mut result = yaml_content.to_string();
+impl<'de> Visitor<'de> for NodeSeed<'_, '_> {
+    type Value = Option<Value>;
 
-    for (key_path, new_value) in updates {
-        result = yaml_set(&result, key_path, parse_user_value(new_value)?)?;
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("any YAML value")
     }
 
-    Ok(result)
-}
-
-pub fn unset_values(yaml_content: &str, keys: &[String]) -> AppResult<String> {
-    let mut result = yaml_content.to_string();
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<Self::Value, A::Error> {
+        if !self.descend {
+            while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {}
+            return Ok(None);
+        }
 
-    for key_path in keys {
-        let path = YamlPath::parse(key_path)?;
-        let current = parse_yaml_document(&result, "from document")?;
-        if get_value_at_path(&current, &path)?.is_some() {
-            result = remove_at_path(&result, &path)?;
+        while let Some(key) = map.next_key::<Value>()? {
+            let Value::String(key) = key else {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            };
+            self.matcher.path.push(PathSegment::Key(key));
+            let matcher = &mut *self.matcher;
+            let key_match = matcher.options.target != MatchTarget::Values
+                && matcher.regex.is_match(&match_candidate(
+                    &render_path(&matcher.path),
+                    matcher.options,
+                ));
+            if key_match {
+                // A matched key keeps its whole value, as in `grep`.
+                let value = map.next_value::<Value>()?;
+                if matches_value_type(&value, matcher.options) {
+                    matcher.results.push((render_path(&matcher.path), value));
+                } else if !matcher.options.top_level {
+                    collect_matching_keys(
+                        &value,
+                        matcher.regex,
+                        matcher.options,
+                        &mut matcher.path,
+                        &mut matcher.results,
+                    );
+                }
+            } else if let Some(value) = map.next_value_seed(self.child())? {
+                self.record(&value, true);
+            }
+            self.matcher.path.pop();
         }
+        Ok(None)
     }
 
-    Ok(result)
-}
-
-pub fn get_value(value: &Value, path: &str) -> AppResult<Option<Value>> {
-    let path = YamlPath::parse(path)?;
-    get_value_at_path(value, &path)
-}
-
-fn get_value_at_path(value: &Value, path: &YamlPath) -> AppResult<Option<Value>> {
-    let mut current = value;
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<Self::Value, A::Error> {
+        if !self.descend {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            return Ok(None);
+        }
 
-    for segment in path.as_segments() {
-        match segment {
-            PathSegment::Key(key) => {
-                let Value::Mapping(map) = current else {
-                    return Ok(None);
-                };
-                match map.get(Value::String(key.clone())) {
-                    Some(next) => current = next,
-                    None => return Ok(None),
-                }
-            }
-            PathSegment::Index(index) => {
-                let Value::Sequence(sequence) = current else {
-                    return Ok(None);
-                };
-                match sequence.get(*index) {
-                    Some(next) => current = next,
-                    None => return Ok(None),
-                }
+        for index in 0.. {
+            self.matcher.path.push(PathSegment::Index(index));
+            let Some(node) = seq.next_element_seed(self.child())? else {
+                self.matcher.path.pop();
+                break;
+            };
+            if let Some(value) = node {
+                self.record(&value, false);
             }
+            self.matcher.path.pop();
         }
+        Ok(None)
     }
 
-    Ok(Some(current.clone()))
-}
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
+    }
 
-pub fn copy_in_document(yaml_content: &str, source_key: &str, dest_key: &str) -> AppResult<String> {
-    let source_yaml = parse_yaml_document(yaml_content, "from source document")?;
-    let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
-        AppError::message(format!("Key '{source_key}' not found in source document"))
-    })?;
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
+    }
 
-    yaml_set(yaml_content, dest_key, value)
-}
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
+    }
 
-pub fn move_in_document(yaml_content: &str, source_key: &str, dest_key: &str) -> AppResult<String> {
-    if source_key == dest_key {
-        return Ok(yaml_content.to_string());
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
     }
 
-    let copied = copy_in_document(yaml_content, source_key, dest_key)?;
-    unset_values(&copied, &[source_key.to_string()])
-}
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
+    }
 
-pub fn copy_value(
-    source_file: &str,
-    source_key: &str,
-    dest_file: &str,
-    dest_key: &str,
-) -> AppResult<()> {
-    let source_contents =
-        fs::read_to_string(source_file).map_err(|error| AppError::read_file(source_file, error))?;
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+        Ok(Some(Value::from(value)))
+    }
 
-    let dest_contents = if source_file == dest_file {
-        source_contents.clone()
-    } else if Path::new(dest_file).exists() {
-        fs::read_to_string(dest_file).map_err(|error| AppError::read_file(dest_file, error))?
-    } else {
-        "{}".to_string()
-    };
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Some(Value::Null))
+    }
 
-    let source_yaml = parse_yaml_document(&source_contents, &format!("from '{source_file}'"))?;
-    let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
-        AppError::message(format!("Key '{source_key}' not found in '{source_file}'"))
-    })?;
-    let updated = yaml_set(&dest_contents, dest_key, value)?;
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(Some(Value::Null))
+    }
 
-    fs::write(dest_file, updated).map_err(|error| AppError::write_file(dest_file, error))?;
-    Ok(())
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let tagged = TaggedValue::deserialize(de::value::EnumAccessDeserializer::new(data))?;
+        Ok(Some(Value::Tagged(Box::new(tagged))))
+    }
 }
 
-pub fn move_value(
-    source_file: &str,
-    source_key: &str,
-    dest_file: &str,
-    dest_key: &str,
-) -> AppResult<()> {
-    let source_contents =
-        fs::read_to_string(source_file).map_err(|error| AppError::read_file(source_file, error))?;
-
-    if source_file == dest_file {
-        let updated = move_in_document(&source_contents, source_key, dest_key)?;
-        fs::write(source_file, updated)
-            .map_err(|error| AppError::write_file(source_file, error))?;
-        return Ok(());
+fn fold_keys(results: Vec<(String, Value)>) -> Vec<(String, Value)> {
+    let mut folded: Vec<(String, Value)> = Vec::new();
+    for (key, value) in results {
+        let key = key.to_lowercase();
+        if !folded.iter().any(|seen| seen.0 == key && seen.1 == value) {
+            folded.push((key, value));
+        }
     }
+    folded
+}
 
-    let source_yaml = parse_yaml_document(&source_contents, &format!("from '{source_file}'"))?;
-    let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
-        AppError::message(format!("Key '{source_key}' not found in '{source_file}'"))
-    })?;
+/// Rebuilds the nested structure holding just the matched `results`, so
+/// `servers[1].host` becomes `servers: [{host: ...}]`. Sequence items keep
+/// their relative order but are compacted.
+pub fn nest_results(results: &[(String, Value)]) -> AppResult<Value> {
+    let mut root = Nested::Value(Value::Null);
+    for (key, value) in results {
+        let path = YamlPath::parse(key)?;
+        root.insert(path.as_segments(), value.clone());
+    }
+    Ok(root.into_value())
+}
 
-    let dest_contents = if Path::new(dest_file).exists() {
-        fs::read_to_string(dest_file).map_err(|error| AppError::read_file(dest_file, error))?
-    } else {
-        "{}".to_string()
-    };
+enum Nested {
+    Value(Value),
+    Mapping(Vec<(String, Nested)>),
+    Sequence(Vec<(usize, Nested)>),
+}
 
-    let updated_dest = yaml_set(&dest_contents, dest_key, value)?;
-    let updated_source = unset_values(&source_contents, &[source_key.to_string()])?;
+impl Nested {
+    fn insert(&mut self, segments: &[PathSegment], value: Value) {
+        let Some((segment, rest)) = segments.split_first() else {
+            *self = Nested::Value(value);
+            return;
+        };
 
-    fs::write(dest_file, updated_dest).map_err(|error| AppError::write_file(dest_file, error))?;
-    fs::write(source_file, updated_source)
-        .map_err(|error| AppError::write_file(source_file, error))?;
-    Ok(())
-}
+        if matches!(self, Nested::Value(Value::Null)) {
+            *self = match segment {
+                PathSegment::Key(_) => Nested::Mapping(Vec::new()),
+                PathSegment::Index(_) => Nested::Sequence(Vec::new()),
+            };
+        }
 
-pub fn format_result(
-    key: &str,
-    value: &Value,
-    terminal_width: usize,
-    mode: GrepOutputMode,
-) -> String {
-    match mode {
-        GrepOutputMode::Inline => {
-            let result = format!("{key}: {}", format_inline_value(value));
-            truncate_if_needed(&result, terminal_width)
+        // Anything else is already covered by a matched ancestor's value.
+        match (self, segment) {
+            (Nested::Mapping(entries), PathSegment::Key(key)) => {
+                Self::child(entries, key.clone()).insert(rest, value)
+            }
+            (Nested::Sequence(items), PathSegment::Index(index)) => {
+                Self::child(items, *index).insert(rest, value)
+            }
+            _ => {}
         }
-        GrepOutputMode::Full => format_full_result(key, value),
     }
-}
 
-fn truncate_if_needed(text: &str, terminal_width: usize) -> String {
-    if text.len() > terminal_width {
-        format!("{}...", &text[..terminal_width.saturating_sub(3)])
-    } else {
-        text.to_string()
+    fn child<K: PartialEq>(entries: &mut Vec<(K, Nested)>, key: K) -> &mut Nested {
+        let position = match entries.iter().position(|(existing, _)| *existing == key) {
+            Some(position) => position,
+            None => {
+                entries.push((key, Nested::Value(Value::Null)));
+                entries.len() - 1
+            }
+        };
+        &mut entries[position].1
     }
-}
 
-fn format_full_result(key: &str, value: &Value) -> String {
-    let mut rendered = render_yaml_with_indent(key, value, 0);
-    if rendered.ends_with('\n') {
-        rendered.pop();
+    fn into_value(self) -> Value {
+        match self {
+            Nested::Value(value) => value,
+            Nested::Mapping(entries) => Value::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(key, node)| (Value::String(key), node.into_value()))
+                    .collect(),
+            ),
+            Nested::Sequence(mut items) => {
+                items.sort_by_key(|(index, _)| *index);
+                Value::Sequence(
+                    items
+                        .into_iter()
+                        .map(|(_, node)| node.into_value())
+                        .collect(),
+                )
+            }
+        }
     }
-    rendered
 }
 
-fn format_inline_value(value: &Value) -> String {
+/// Counts the keys whose path matches `regex` at each nesting depth; index 0
+/// holds top-level keys. Sequence indices do not add a level.
+pub fn depth_histogram(value: &Value, regex: &Regex) -> Vec<usize> {
+    let mut histogram = Vec::new();
+    let mut path = Vec::new();
+    collect_key_depths(value, regex, &mut path, &mut histogram);
+    histogram
+}
+
+fn collect_key_depths(
+    value: &Value,
+    regex: &Regex,
+    path: &mut Vec<PathSegment>,
+    histogram: &mut Vec<usize>,
+) {
     match value {
         Value::Mapping(map) => {
-            let entries = map
-                .iter()
-                .map(|(key, val)| {
-                    format!("{}: {}", format_inline_key(key), format_inline_value(val))
-                })
-                .collect::<Vec<_>>()
-                .join(", ");
-            format!("{{ {entries} }}")
+            for (key, value) in map {
+                let Value::String(key) = key else {
+                    continue;
+                };
+
+                path.push(PathSegment::Key(key.clone()));
+                if regex.is_match(&render_path(path)) {
+                    let depth = path
+                        .iter()
+                        .filter(|segment| matches!(segment, PathSegment::Key(_)))
+                        .count();
+                    if histogram.len() < depth {
+                        histogram.resize(depth, 0);
+                    }
+                    histogram[depth - 1] += 1;
+                }
+                collect_key_depths(value, regex, path, histogram);
+                path.pop();
+            }
         }
-        Value::Sequence(seq) => {
-            let items = seq
-                .iter()
-                .map(format_inline_value)
-                .collect::<Vec<_>>()
-                .join(", ");
-            format!("[{items}]")
+        Value::Sequence(sequence) => {
+            for (index, value) in sequence.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                collect_key_depths(value, regex, path, histogram);
+                path.pop();
+            }
         }
-        Value::String(s) => s.clone(),
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
-        _ => serde_yaml::to_string(value)
-            .unwrap_or_else(|_| "<complex>".to_string())
-            .trim()
-            .replace('\n', " "),
+        _ => {}
     }
 }
 
-fn format_inline_key(key: &Value) -> String {
-    match key {
-        Value::String(s) => s.clone(),
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
-        _ => serde_yaml::to_string(key)
-            .unwrap_or_else(|_| "<key>".to_string())
-            .trim()
-            .replace('\n', " "),
+/// Tallies how many matches hold each distinct scalar value, most frequent first.
+pub fn distinct_values<'a>(values: impl Iterator<Item = &'a Value>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for value in values {
+        if !matches!(value, Value::Mapping(_) | Value::Sequence(_)) {
+            *counts.entry(format_inline_value(value)).or_default() += 1;
+        }
     }
+
+    let mut tallies: Vec<_> = counts.into_iter().collect();
+    tallies.sort_by(|(left_value, left), (right_value, right)| {
+        right.cmp(left).then_with(|| left_value.cmp(right_value))
+    });
+    tallies
 }
 
-fn render_yaml_with_indent(key: &str, value: &Value, indent: usize) -> String {
-    let prefix = "  ".repeat(indent);
+fn collect_matching_keys(
+    value: &Value,
+    regex: &Regex,
+    options: &MatchOptions,
+    path: &mut Vec<PathSegment>,
+    results: &mut Vec<(String, Value)>,
+) {
+    for (segment, value) in entries(value) {
+        let is_key = matches!(segment, PathSegment::Key(_));
+        path.push(segment);
+        if entry_matches(value, regex, options, path, is_key) {
+            results.push((render_path(path), value.clone()));
+        } else if !options.top_level {
+            collect_matching_keys(value, regex, options, path, results);
+        }
+        path.pop();
+    }
+}
 
+/// The addressable children of a mapping (string keys) or sequence (indices).
+fn entries(value: &Value) -> Vec<(PathSegment, &Value)> {
     match value {
-        Value::Mapping(map) => {
-            let mut output = format!("{prefix}{key}:\n");
-            for (child_key, child_value) in map {
-                output.push_str(&render_yaml_with_indent(
-                    &format_inline_key(child_key),
-                    child_value,
-                    indent + 1,
-                ));
-            }
-            output
-        }
-        Value::Sequence(seq) => {
-            let mut output = format!("{prefix}{key}:\n");
-            for item in seq {
-                output.push_str(&render_sequence_item(item, indent + 1));
-            }
-            output
+        Value::Mapping(map) => map
+            .iter()
+            .filter_map(|(key, value)| Some((PathSegment::Key(key.as_str()?.to_string()), value)))
+            .collect(),
+        Value::Sequence(sequence) => sequence
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (PathSegment::Index(index), value))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Every key path of `value` in document order, sequence items included;
+/// with `leaves_only`, paths holding a non-empty mapping or sequence are skipped.
+pub fn list_keys(value: &Value, leaves_only: bool) -> Vec<String> {
+    let mut keys = Vec::new();
+    collect_keys(value, leaves_only, &mut Vec::new(), &mut keys);
+    keys
+}
+
+/// Key paths containing `term` once both are lowercased and stripped of
+/// separators, best first: exact matches, then earlier and shorter matches.
+pub fn find_keys(value: &Value, term: &str) -> Vec<String> {
+    let term = fuzzy_text(term);
+    let mut ranked: Vec<_> = list_keys(value, false)
+        .into_iter()
+        .filter_map(|key| {
+            let text = fuzzy_text(&key);
+            let position = text.find(&term)?;
+            Some(((text != term, position, text.len()), key))
+        })
+        .collect();
+    ranked.sort_by_key(|(score, _)| *score);
+    ranked.into_iter().map(|(_, key)| key).collect()
+}
+
+fn fuzzy_text(text: &str) -> String {
+    text.chars()
+        .filter(|ch| ch.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn collect_keys(
+    value: &Value,
+    leaves_only: bool,
+    path: &mut Vec<PathSegment>,
+    keys: &mut Vec<String>,
+) {
+    for (segment, value) in entries(value) {
+        path.push(segment);
+        if !leaves_only || entries(value).is_empty() {
+            keys.push(render_path(path));
         }
-        _ => format!("{prefix}{key}: {}\n", format_inline_value(value)),
+        collect_keys(value, leaves_only, path, keys);
+        path.pop();
     }
 }
 
-fn render_sequence_item(value: &Value, indent: usize) -> String {
-    let prefix = "  ".repeat(indent);
+/// Tests a mapping key's path and/or a scalar's value, depending on the
+/// match target. Sequence items have no key, so only their value can match.
+fn entry_matches(
+    value: &Value,
+    regex: &Regex,
+    options: &MatchOptions,
+    path: &[PathSegment],
+    is_key: bool,
+) -> bool {
+    let key_match = is_key
+        && options.target != MatchTarget::Values
+        && regex.is_match(&match_candidate(&render_path(path), options));
+    let value_match = options.target != MatchTarget::Keys
+        && !matches!(value, Value::Mapping(_) | Value::Sequence(_))
+        && regex.is_match(&format_inline_value(value));
 
+    (key_match || value_match) && matches_value_type(value, options)
+}
+
+/// Paths of every mapping key whose scalar value matches `regex`.
+pub fn keys_with_matching_values(value: &Value, regex: &Regex) -> Vec<String> {
+    let mut results = Vec::new();
+    collect_keys_with_matching_values(value, regex, &mut Vec::new(), &mut results);
+    results
+}
+
+fn collect_keys_with_matching_values(
+    value: &Value,
+    regex: &Regex,
+    path: &mut Vec<PathSegment>,
+    results: &mut Vec<String>,
+) {
     match value {
         Value::Mapping(map) => {
-            let mut iter = map.iter();
-            if let Some((first_key, first_value)) = iter.next() {
-                let mut output = format!("{prefix}-");
-                match first_value {
+            for (key, value) in map {
+                let Value::String(key) = key else {
+                    continue;
+                };
+
+                path.push(PathSegment::Key(key.clone()));
+                match value {
                     Value::Mapping(_) | Value::Sequence(_) => {
-                        output.push('\n');
-                        output.push_str(&render_yaml_with_indent(
-                            &format_inline_key(first_key),
-                            first_value,
-                            indent + 1,
-                        ));
+                        collect_keys_with_matching_values(value, regex, path, results)
                     }
-                    _ => {
-                        output.push_str(&format!(
-                            " {}: {}\n",
-                            format_inline_key(first_key),
-                            format_inline_value(first_value)
-                        ));
+                    _ if regex.is_match(&format_inline_value(value)) => {
+                        results.push(render_path(path))
                     }
+                    _ => {}
                 }
-
-                for (key, val) in iter {
-                    output.push_str(&render_yaml_with_indent(
-                        &format_inline_key(key),
-                        val,
-                        indent + 1,
-                    ));
-                }
-                output
-            } else {
-                format!("{prefix}- {{}}\n")
+                path.pop();
             }
         }
-        Value::Sequence(seq) => {
-            if seq.is_empty() {
-                format!("{prefix}- []\n")
-            } else {
-                let mut output = format!("{prefix}-\n");
-                for item in seq {
-                    output.push_str(&render_sequence_item(item, indent + 1));
+        Value::Sequence(sequence) => {
+            for (index, value) in sequence.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                collect_keys_with_matching_values(value, regex, path, results);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn match_candidate<'a>(rendered: &'a str, options: &MatchOptions) -> Cow<'a, str> {
+    if options.any_separator {
+        Cow::Owned(rendered.replace('/', "."))
+    } else {
+        Cow::Borrowed(rendered)
+    }
+}
+
+fn matches_value_type(value: &Value, options: &MatchOptions) -> bool {
+    options
+        .value_type
+        .is_none_or(|value_type| value_type.matches(value))
+}
+
+fn render_path(path: &[PathSegment]) -> String {
+    let mut rendered = String::new();
+
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => {
+                if !rendered.is_empty() {
+                    rendered.push('.');
                 }
-                output
+
+                for ch in key.chars() {
+                    match ch {
+                        '.' | '[' | ']' | '\\' => {
+                            rendered.push('\\');
+                            rendered.push(ch);
+                        }
+                        other => rendered.push(other),
+                    }
+                }
+            }
+            PathSegment::Index(index) => {
+                rendered.push('[');
+                rendered.push_str(&index.to_string());
+                rendered.push(']');
             }
         }
-        _ => format!("{prefix}- {}\n", format_inline_value(value)),
     }
+
+    rendered
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Renders `text` as a quoted scalar so `set` stores it as a string even
+/// when it looks like a number, boolean or null.
+pub fn string_literal(text: &str) -> String {
+    serde_json::Value::String(text.to_string()).to_string()
+}
 
-    fn parse_yaml(yaml_str: &str) -> Value {
-        serde_yaml::from_str(yaml_str).expect("Failed to parse YAML")
+fn parse_user_value(input: &str, preserve_numbers: bool) -> AppResult<Value> {
+    let value = serde_yaml::from_str(input)
+        .map_err(|error| AppError::parse_yaml(format!("from value '{input}'"), error))?;
+    Ok(match preserve_numbers {
+        true => preserve_number_text(value, input.trim()),
+        false => value,
+    })
+}
+
+fn parse_yaml_document(yaml_content: &str, context: &str) -> AppResult<Value> {
+    serde_yaml::from_str(yaml_content)
+        .map_err(|error| AppError::parse_yaml(context.to_string(), error))
+}
+
+/// Like `parse_yaml_document`, reading the numbers whose text would not
+/// survive a round trip as that text when `preserve_numbers` is set.
+fn read_yaml_document(
+    yaml_content: &str,
+    context: &str,
+    preserve_numbers: bool,
+) -> AppResult<Value> {
+    let mut value = parse_yaml_document(yaml_content, context)?;
+    if preserve_numbers {
+        let document =
+            Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+        restore_number_text(&document, &mut value, &mut Vec::new());
     }
+    Ok(value)
+}
 
-    fn temp_test_dir(name: &str) -> String {
-        let unique = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let dir = format!("{name}_{}_{}", std::process::id(), unique);
-        let _ = fs::remove_dir_all(&dir);
-        fs::create_dir(&dir).unwrap();
-        dir
+/// The text of each `---` separated document of `contents`, leaving out
+/// the separators and anything before a leading one.
+fn document_texts(contents: &str) -> Vec<String> {
+    let mut texts = vec![String::new()];
+    for line in contents.split_inclusive('\n') {
+        let marker = line.trim_end();
+        if marker == "---" || marker.starts_with("--- ") {
+            texts.push(String::new());
+        } else {
+            texts.last_mut().unwrap().push_str(line);
+        }
     }
+    if !has_document(&texts[0]) {
+        texts.remove(0);
+    }
+    texts
+}
 
-    #[test]
-    fn test_grep_simple_key() {
-        let yaml = parse_yaml("name: Alice\nage: 30");
-        let results = grep(&yaml, "name").unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].0, "name");
-        assert_eq!(results[0].1.as_str(), Some("Alice"));
+fn preserve_number_text(value: Value, text: &str) -> Value {
+    match value {
+        Value::Number(number) if number.to_string() != text => Value::String(text.to_string()),
+        value => value,
     }
+}
 
-    #[test]
-    fn test_grep_compiles_regex_once_and_matches_nested_keys() {
-        let yaml = parse_yaml("database:\n  host: localhost\n  port: 5432\n");
-        let results = grep(&yaml, r"^database\.(host|port)$").unwrap();
-        let keys: Vec<_> = results.into_iter().map(|result| result.0).collect();
-        assert_eq!(keys, vec!["database.host", "database.port"]);
+fn restore_number_text(document: &Document, value: &mut Value, path: &mut Vec<PathSegment>) {
+    match value {
+        Value::Mapping(map) => {
+            for (key, value) in map.iter_mut() {
+                let Value::String(key) = key else {
+                    continue;
+                };
+                path.push(PathSegment::Key(key.clone()));
+                restore_number_text(document, value, path);
+                path.pop();
+            }
+        }
+        Value::Sequence(sequence) => {
+            for (index, value) in sequence.iter_mut().enumerate() {
+                path.push(PathSegment::Index(index));
+                restore_number_text(document, value, path);
+                path.pop();
+            }
+        }
+        Value::Number(_) => {
+            let route = YamlPath::from_segments(path.clone()).to_route();
+            if let Ok(Some(feature)) = document.query_exact(&route) {
+                let text = document.extract(&feature).trim();
+                *value = preserve_number_text(value.clone(), text);
+            }
+        }
+        _ => {}
     }
+}
 
-    #[test]
-    fn test_grep_escapes_dotted_keys() {
-        let yaml = parse_yaml("metadata:\n  kubernetes.io/hostname: node-a\n");
-        let results = grep(&yaml, r"metadata\.kubernetes\\\.io/hostname$").unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].0, r"metadata.kubernetes\.io/hostname");
+fn apply_patch(yaml_content: &str, patch: Patch<'static>) -> AppResult<String> {
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let updated = yamlpatch::apply_yaml_patches(&document, &[patch])
+        .map_err(|error| AppError::patch(error.to_string()))?;
+    Ok(updated.source().to_string())
+}
+
+fn placeholder_mapping() -> Value {
+    let mut placeholder = serde_yaml::Mapping::new();
+    placeholder.insert(Value::String(PLACEHOLDER_KEY.to_string()), Value::Null);
+    Value::Mapping(placeholder)
+}
+
+fn yaml_set(
+    yaml_content: &str,
+    key_path: &str,
+    new_value: Value,
+    options: &EditOptions,
+) -> AppResult<String> {
+    if !has_document(yaml_content) {
+        return with_document(yaml_content, |seeded| {
+            yaml_set(seeded, key_path, new_value, options)
+        });
     }
 
-    #[test]
-    fn test_grep_sequence_paths() {
-        let yaml = parse_yaml("items:\n  - name: first\n  - name: second\n");
-        let results = grep(&yaml, r"items\[1\]\.name").unwrap();
-        assert_eq!(results.len(), 1);
-        assert_eq!(results[0].0, "items[1].name");
-        assert_eq!(results[0].1.as_str(), Some("second"));
+    let path = resolve_append_path(yaml_content, key_path)?;
+    let mut result = yaml_content.to_string();
+
+    for prefix in path.prefixes_requiring_mapping() {
+        result = ensure_mapping_at_path(&result, &prefix, options)?;
     }
 
-    #[test]
-    fn test_grep_invalid_regex() {
-        let yaml = parse_yaml("name: Alice");
-        assert!(grep(&yaml, "[invalid").is_err());
+    let current = parse_yaml_document(&result, "from document")?;
+    let existing_value = get_value_at_path(&current, &path)?;
+    let setting_mapping = matches!(new_value, Value::Mapping(_));
+
+    let updated = match new_value {
+        Value::Mapping(new_map) => {
+            set_mapping_at_path(&result, &path, existing_value, new_map, options)?
+        }
+        new_value => match existing_value {
+            Some(_) => replace_value_at_path(&result, &path, new_value)?,
+            None => add_value_at_path(&result, &path, new_value, options)?,
+        },
+    };
+
+    cleanup_placeholders(&updated, &cleanup_paths_for_set(&path, setting_mapping))
+}
+
+/// Resolves a trailing `[]` to the next free index of the sequence it names.
+fn resolve_append_path(yaml_content: &str, key_path: &str) -> AppResult<YamlPath> {
+    let current = parse_yaml_document(yaml_content, "from document")?;
+    let Some(parent) = key_path.strip_suffix("[]") else {
+        return resolve_path(&current, YamlPath::parse(key_path)?);
+    };
+
+    let parent = resolve_path(&current, YamlPath::parse(parent)?)?;
+    let next_index = match get_value_at_path(&current, &parent)? {
+        Some(Value::Sequence(sequence)) => sequence.len(),
+        _ => 0,
+    };
+    Ok(parent.push_index(next_index))
+}
+
+/// Reads numeric key segments (`servers.0.host`) as indices wherever the
+/// document holds a sequence, and rejects indices past the end of one; the
+/// index right after the last item is allowed so it can be appended to.
+fn resolve_path(value: &Value, path: YamlPath) -> AppResult<YamlPath> {
+    let mut current = Some(value);
+    let mut segments = Vec::new();
+
+    for segment in path.as_segments() {
+        let segment = match (segment, current) {
+            (PathSegment::Key(key), Some(Value::Sequence(_))) if is_index(key) => {
+                PathSegment::Index(key.parse().map_err(|_| {
+                    AppError::invalid_path(path.display(), "sequence index is too large")
+                })?)
+            }
+            (segment, _) => segment.clone(),
+        };
+
+        current = match (&segment, current) {
+            (PathSegment::Index(index), Some(Value::Sequence(sequence))) => {
+                if *index > sequence.len() {
+                    return Err(AppError::message(format!(
+                        "Index {index} is out of bounds for '{}' (length {})",
+                        YamlPath::from_segments(segments).display(),
+                        sequence.len()
+                    )));
+                }
+                sequence.get(*index)
+            }
+            (PathSegment::Key(key), Some(Value::Mapping(map))) => map.get(key.as_str()),
+            _ => None,
+        };
+        segments.push(segment);
     }
 
-    #[test]
-    fn test_set_and_unset_values_update_yaml_semantics() {
-        let yaml_str = "database:\n  host: localhost\n  port: 5432\nconfig:\n  level: info\n";
-        let updates = vec![
-            ("database.port".to_string(), "3306".to_string()),
-            ("database.username".to_string(), "admin".to_string()),
-            ("app.server.config.timeout".to_string(), "30".to_string()),
-        ];
+    Ok(YamlPath::from_segments(segments))
+}
+
+fn is_index(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// Parses `key_path` and resolves it against the document in `yaml_content`.
+fn document_path(yaml_content: &str, key_path: &str) -> AppResult<YamlPath> {
+    let current = parse_yaml_document(yaml_content, "from document")?;
+    resolve_path(&current, YamlPath::parse(key_path)?)
+}
+
+fn ensure_mapping_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let current = parse_yaml_document(yaml_content, "from document")?;
+
+    match get_value_at_path(&current, path)? {
+        Some(Value::Mapping(_)) => Ok(yaml_content.to_string()),
+        Some(_) => replace_with_empty_mapping_at_path(yaml_content, path, options),
+        None => add_empty_mapping_at_path(yaml_content, path, options),
+    }
+}
+
+fn replace_with_empty_mapping_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let removed = remove_at_path(yaml_content, path)?;
+    add_empty_mapping_at_path(&removed, path, options)
+}
+
+fn add_empty_mapping_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let parent = path
+        .parent()
+        .map(|parent| parent.to_route())
+        .unwrap_or_default();
+
+    let Some(PathSegment::Key(key)) = path.last() else {
+        return Err(AppError::message(format!(
+            "Cannot create a mapping at sequence path '{}'",
+            path.display()
+        )));
+    };
+
+    let added = apply_patch(
+        yaml_content,
+        Patch {
+            route: parent,
+            operation: Op::Add {
+                key: key_token(key),
+                value: placeholder_mapping(),
+            },
+        },
+    )?;
+    let width = options
+        .indent
+        .unwrap_or_else(|| detect_indent(yaml_content));
+    reindent_placeholder(added, path, width)
+}
+
+/// The most common number of spaces between a `key:` line and the keys
+/// nested under it, or 2 when the content has no nested mappings.
+fn detect_indent(yaml_content: &str) -> usize {
+    let mut steps: HashMap<usize, usize> = HashMap::new();
+    let mut previous: Option<(usize, bool)> = None;
+
+    for line in yaml_content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let item = trimmed.trim_start_matches(['-', ' ']);
+        let column = line.len() - item.len();
+        if let Some((parent, true)) = previous {
+            if column > parent && !trimmed.starts_with('-') {
+                *steps.entry(column - parent).or_default() += 1;
+            }
+        }
+        previous = Some((column, item.trim_end().ends_with(':')));
+    }
+
+    steps
+        .into_iter()
+        .max_by_key(|&(step, count)| (count, std::cmp::Reverse(step)))
+        .map_or(DEFAULT_INDENT, |(step, _)| step)
+}
+
+/// yamlpatch nests new mappings 2 spaces deeper; shift the placeholder line
+/// so the mapping's children follow the configured indent width instead.
+fn reindent_placeholder(
+    mut yaml_content: String,
+    path: &YamlPath,
+    width: usize,
+) -> AppResult<String> {
+    if width == DEFAULT_INDENT {
+        return Ok(yaml_content);
+    }
+
+    let document =
+        Document::new(yaml_content.as_str()).map_err(|error| AppError::patch(error.to_string()))?;
+    let feature = document
+        .query_pretty(&path.push_key(PLACEHOLDER_KEY).to_route())
+        .map_err(|error| AppError::patch(error.to_string()))?;
+    let key_start = feature.location.byte_span.0;
+    let line_start = yaml_content[..key_start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let indent = key_start - line_start;
+
+    let parent_indent = indent.saturating_sub(DEFAULT_INDENT);
+    yaml_content.replace_range(line_start..key_start, &" ".repeat(parent_indent + width));
+    Ok(yaml_content)
+}
+
+fn add_value_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    new_value: Value,
+    options: &EditOptions,
+) -> AppResult<String> {
+    match path.last() {
+        Some(PathSegment::Key(key)) => {
+            let parent = path
+                .parent()
+                .map(|parent| parent.to_route())
+                .unwrap_or_default();
+            apply_patch(
+                yaml_content,
+                Patch {
+                    route: parent,
+                    operation: Op::Add {
+                        key: key_token(key),
+                        value: new_value,
+                    },
+                },
+            )
+        }
+        Some(PathSegment::Index(index)) => {
+            append_value_at_path(yaml_content, path, *index, new_value, options)
+        }
+        None => Err(AppError::message("Empty key path")),
+    }
+}
+
+fn append_value_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    index: usize,
+    new_value: Value,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let parent_path = path.parent().ok_or_else(|| {
+        AppError::message(format!(
+            "Cannot set root sequence index '{}'",
+            path.display()
+        ))
+    })?;
+    let current = parse_yaml_document(yaml_content, "from document")?;
+
+    match get_value_at_path(&current, &parent_path)? {
+        Some(Value::Sequence(sequence)) if index == sequence.len() => {
+            let (_, start, end) = value_offsets(yaml_content, &parent_path)?;
+            if yaml_content[start..end].starts_with('[') {
+                return append_to_flow_sequence(yaml_content, end, &new_value);
+            }
+
+            apply_patch(
+                yaml_content,
+                Patch {
+                    route: parent_path.to_route(),
+                    operation: Op::Append { value: new_value },
+                },
+            )
+        }
+        Some(Value::Sequence(sequence)) => Err(AppError::message(format!(
+            "Cannot create sparse sequence entry at '{}'; next valid index is {}",
+            path.display(),
+            sequence.len()
+        ))),
+        Some(_) if index == 0 && options.force_array => {
+            replace_value_at_path(yaml_content, &parent_path, Value::Sequence(vec![new_value]))
+        }
+        Some(_) => Err(AppError::message(format!(
+            "Parent of '{}' is not a sequence (use --force-array to replace it)",
+            path.display()
+        ))),
+        None if index == 0 => {
+            let sequence = Value::Sequence(vec![new_value]);
+            add_value_at_path(yaml_content, &parent_path, sequence, options)
+        }
+        None => Err(AppError::message(format!(
+            "Parent sequence '{}' does not exist",
+            parent_path.display()
+        ))),
+    }
+}
+
+/// Inserts `new_value` before the closing `]` of the flow sequence ending at
+/// `end`, keeping the surrounding spacing and any trailing comma.
+fn append_to_flow_sequence(yaml_content: &str, end: usize, new_value: &Value) -> AppResult<String> {
+    let close = yaml_content[..end]
+        .rfind(']')
+        .ok_or_else(|| AppError::message("Malformed flow sequence"))?;
+    let body = yaml_content[..close].trim_end();
+    let item = render_flow_item(new_value)?;
+
+    let insertion = if body.ends_with('[') {
+        item
+    } else if body.ends_with(',') {
+        format!(" {item},")
+    } else {
+        format!(", {item}")
+    };
+
+    let mut updated = yaml_content.to_string();
+    updated.insert_str(body.len(), &insertion);
+    Ok(updated)
+}
+
+/// Quotes a new key when writing it bare would re-parse as another key
+/// (`true`, `123`) or break the line (`a: b`).
+fn key_token(key: &str) -> String {
+    serde_yaml::to_string(&Value::String(key.to_string()))
+        .map(|rendered| rendered.trim_end().to_string())
+        .unwrap_or_else(|_| key.to_string())
+}
+
+fn render_flow_item(value: &Value) -> AppResult<String> {
+    let needs_json = match value {
+        Value::String(text) => text.contains([',', '[', ']', '{', '}']),
+        Value::Mapping(_) | Value::Sequence(_) => true,
+        _ => false,
+    };
+
+    if needs_json {
+        return serde_json::to_string(value).map_err(|error| AppError::to_json("value", error));
+    }
+
+    serde_yaml::to_string(value)
+        .map(|rendered| rendered.trim_end().to_string())
+        .map_err(|error| AppError::message(format!("Failed to render value: {error}")))
+}
+
+fn replace_value_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    new_value: Value,
+) -> AppResult<String> {
+    if let Value::Sequence(items) = &new_value {
+        return replace_sequence_span(yaml_content, path, items);
+    }
+
+    if let (Value::String(text), Ok((_, start, end))) =
+        (&new_value, value_offsets(yaml_content, path))
+    {
+        if let Some(quoted) = quote_like(&yaml_content[start..end], text) {
+            return replace_value_span(yaml_content, path, |_| Ok(quoted));
+        }
+    }
+
+    let colon_in_key = matches!(path.last(), Some(PathSegment::Key(key)) if key.contains(':'));
+    if colon_in_key && !matches!(new_value, Value::Mapping(_)) {
+        return replace_value_span(yaml_content, path, |_| render_flow_item(&new_value));
+    }
+
+    apply_patch(
+        yaml_content,
+        Patch {
+            route: path.to_route(),
+            operation: Op::Replace(new_value),
+        },
+    )
+}
+
+/// Quotes `text` with the same quote character as the `current` scalar, so
+/// editing `name: "Alice"` keeps its double quotes.
+fn quote_like(current: &str, text: &str) -> Option<String> {
+    match current.chars().next()? {
+        '"' => Some(string_literal(text)),
+        '\'' if !text.contains(char::is_control) => Some(format!("'{}'", text.replace('\'', "''"))),
+        _ => None,
+    }
+}
+
+/// yamlpatch renders a replacement sequence in block style right after the
+/// key's colon, which is invalid YAML; keep a block sequence as a block and
+/// write anything else as a flow sequence.
+fn replace_sequence_span(
+    yaml_content: &str,
+    path: &YamlPath,
+    items: &[Value],
+) -> AppResult<String> {
+    let items = items
+        .iter()
+        .map(render_flow_item)
+        .collect::<AppResult<Vec<_>>>()?;
+
+    replace_value_span(yaml_content, path, |current| {
+        let block_indent = current.value.starts_with('-') && current.prefix.trim().is_empty();
+        if block_indent && !items.is_empty() {
+            let separator = format!("\n{}- ", current.prefix);
+            Ok(format!("- {}", items.join(&separator)))
+        } else {
+            Ok(format!("[{}]", items.join(", ")))
+        }
+    })
+}
+
+struct CurrentValue<'a> {
+    value: &'a str,
+    /// Text between the start of the value's line and the value.
+    prefix: &'a str,
+}
+
+/// Rewrites only the value's own span, leaving the key and any trailing
+/// comment untouched. yamlpatch splits a `key: value` line at its first
+/// colon, which lands inside keys like `'k: v'`.
+fn replace_value_span(
+    yaml_content: &str,
+    path: &YamlPath,
+    render: impl FnOnce(CurrentValue) -> AppResult<String>,
+) -> AppResult<String> {
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let feature = document
+        .query_exact(&path.to_route())
+        .map_err(|error| AppError::patch(error.to_string()))?
+        .ok_or_else(|| AppError::patch(format!("No value at '{}'", path.display())))?;
+    let (start, end) = feature.location.byte_span;
+    let end = start + yaml_content[start..end].trim_end().len();
+    let line_start = yaml_content[..start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+
+    let replacement = render(CurrentValue {
+        value: &yaml_content[start..end],
+        prefix: &yaml_content[line_start..start],
+    })?;
+    let mut result = yaml_content.to_string();
+    result.replace_range(start..end, &replacement);
+    Ok(result)
+}
+
+fn remove_at_path(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
+    if let Some(parent) = path.parent() {
+        let (_, start, end) = value_offsets(yaml_content, &parent)?;
+        if yaml_content[start..end].starts_with(['[', '{']) {
+            return remove_flow_entry(yaml_content, path);
+        }
+    }
+
+    apply_patch(
+        yaml_content,
+        Patch {
+            route: path.to_route(),
+            operation: Op::Remove,
+        },
+    )
+}
+
+/// yamlpatch drops the whole key when removing an entry of a flow sequence
+/// or mapping; cut the entry together with the comma separating it from its
+/// neighbour instead.
+fn remove_flow_entry(yaml_content: &str, path: &YamlPath) -> AppResult<String> {
+    let (_, mut start, end) = value_offsets(yaml_content, path)?;
+    if let Some(PathSegment::Key(_)) = path.last() {
+        let key_start = yaml_content[..start]
+            .rfind([',', '{'])
+            .map_or(0, |index| index + 1);
+        start = key_start + yaml_content[key_start..start].len()
+            - yaml_content[key_start..start].trim_start().len();
+    }
+    let after = &yaml_content[end..];
+    let before = &yaml_content[..start];
+
+    let range = if let Some(rest) = after.trim_start().strip_prefix(',') {
+        start..yaml_content.len() - rest.trim_start().len()
+    } else if let Some(rest) = before.trim_end().strip_suffix(',') {
+        rest.len()..end
+    } else {
+        start..end
+    };
+
+    let mut result = yaml_content.to_string();
+    result.replace_range(range, "");
+    Ok(result)
+}
+
+fn set_mapping_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    existing_value: Option<Value>,
+    new_map: serde_yaml::Mapping,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let mut result = match existing_value {
+        Some(Value::Mapping(current_map)) => {
+            remove_missing_mapping_keys(yaml_content, path, &current_map, &new_map)?
+        }
+        Some(_) => replace_with_empty_mapping_at_path(yaml_content, path, options)?,
+        None => add_empty_mapping_at_path(yaml_content, path, options)?,
+    };
+
+    for (key, value) in new_map {
+        let Value::String(key) = key else {
+            return Err(AppError::message(format!(
+                "Unsupported non-string key under '{}'",
+                path.display()
+            )));
+        };
+
+        result = yaml_set(&result, &path.push_key(key).display(), value, options)?;
+    }
+
+    cleanup_placeholders(&result, std::slice::from_ref(path))
+}
+
+fn remove_missing_mapping_keys(
+    yaml_content: &str,
+    path: &YamlPath,
+    current_map: &serde_yaml::Mapping,
+    new_map: &serde_yaml::Mapping,
+) -> AppResult<String> {
+    let mut result = yaml_content.to_string();
+
+    for key in current_map.keys().filter_map(|key| match key {
+        Value::String(key) if key != PLACEHOLDER_KEY => Some(key.clone()),
+        _ => None,
+    }) {
+        if !new_map.contains_key(Value::String(key.clone())) {
+            result = remove_at_path(&result, &path.push_key(key))?;
+        }
+    }
+
+    Ok(result)
+}
+
+fn cleanup_paths_for_set(path: &YamlPath, setting_mapping: bool) -> Vec<YamlPath> {
+    let mut paths = path.prefixes_requiring_mapping();
+    paths.reverse();
+    if setting_mapping {
+        paths.insert(0, path.clone());
+    }
+    paths
+}
+
+fn cleanup_placeholders(yaml_content: &str, paths: &[YamlPath]) -> AppResult<String> {
+    let mut result = yaml_content.to_string();
+
+    for path in paths {
+        let current = parse_yaml_document(&result, "from document")?;
+        let placeholder_path = path.push_key(PLACEHOLDER_KEY);
+        if get_value_at_path(&current, &placeholder_path)?.is_some() {
+            result = remove_at_path(&result, &placeholder_path)?;
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn set_values(
+    yaml_content: &str,
+    updates: &[(String, String)],
+    options: &EditOptions,
+) -> AppResult<String> {
+    let mut result = yaml_content.to_string();
+
+    for (key_path, new_value) in updates {
+        let new_value = parse_user_value(new_value, options.preserve_numbers)?;
+        result = yaml_set(&result, key_path, new_value, options)?;
+    }
+
+    Ok(result)
+}
+
+/// Removes every comment, dropping lines that held only a comment and
+/// trimming trailing ones; `#` inside strings and block scalars is kept.
+pub fn strip_comments(yaml_content: &str) -> AppResult<String> {
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let mut stripped = String::with_capacity(yaml_content.len());
+    let mut line_start = 0;
+
+    for line in yaml_content.split_inclusive('\n') {
+        let comment = line
+            .match_indices('#')
+            .map(|(index, _)| index)
+            .find(|index| document.offset_inside_comment(line_start + index));
+        line_start += line.len();
+
+        let Some(comment) = comment else {
+            stripped.push_str(line);
+            continue;
+        };
+        let code = line[..comment].trim_end();
+        if !code.is_empty() {
+            stripped.push_str(code);
+            stripped.push_str(&line[line.trim_end_matches(['\r', '\n']).len()..]);
+        }
+    }
+
+    Ok(stripped)
+}
+
+/// Runs `edit` with lines starting with `prefix` masked as `#` comments, so
+/// they survive the edit verbatim, then restores them in order.
+pub fn with_comment_prefix(
+    yaml_content: &str,
+    prefix: &str,
+    edit: impl FnOnce(&str) -> AppResult<String>,
+) -> AppResult<String> {
+    if prefix.is_empty() {
+        return Err(AppError::cli("--comment-prefix cannot be empty"));
+    }
+
+    let mut masked_lines = Vec::new();
+    let masked = yaml_content
+        .split_inclusive('\n')
+        .map(|line| {
+            let indent = line.len() - line.trim_start().len();
+            if !line[indent..].starts_with(prefix) {
+                return line.to_string();
+            }
+            let first_len = prefix.chars().next().map_or(0, char::len_utf8);
+            let masked = format!("{}#{}", &line[..indent], &line[indent + first_len..]);
+            masked_lines.push((masked.clone(), line.to_string()));
+            masked
+        })
+        .collect::<String>();
+
+    let edited = edit(&masked)?;
+    let mut pending = masked_lines.into_iter().peekable();
+    Ok(edited
+        .split_inclusive('\n')
+        .map(|line| {
+            match pending.peek() {
+                Some((masked, _)) if masked == line => pending.next().map(|(_, original)| original),
+                _ => None,
+            }
+            .unwrap_or_else(|| line.to_string())
+        })
+        .collect())
+}
+
+/// Rewrites the segments of `key_path` that do not exist yet in the document
+/// in `style`, leaving existing keys untouched.
+pub fn normalize_new_keys(
+    yaml_content: &str,
+    key_path: &str,
+    style: KeyStyle,
+) -> AppResult<String> {
+    let (base, suffix) = match key_path.strip_suffix("[]") {
+        Some(base) => (base, "[]"),
+        None => (key_path, ""),
+    };
+    let document = parse_yaml_document(yaml_content, "from document")?;
+    let mut current = Some(&document);
+    let mut segments = Vec::new();
+
+    for segment in YamlPath::parse(base)?.as_segments() {
+        let segment = match (segment, current) {
+            (PathSegment::Key(key), Some(Value::Mapping(map))) => {
+                current = map.get(Value::String(key.clone()));
+                match current {
+                    Some(_) => segment.clone(),
+                    None => PathSegment::Key(style.apply(key)),
+                }
+            }
+            (PathSegment::Key(key), _) => PathSegment::Key(style.apply(key)),
+            (PathSegment::Index(index), Some(Value::Sequence(sequence))) => {
+                current = sequence.get(*index);
+                segment.clone()
+            }
+            (PathSegment::Index(_), _) => {
+                current = None;
+                segment.clone()
+            }
+        };
+        segments.push(segment);
+    }
+
+    Ok(format!("{}{suffix}", render_path(&segments)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyChange {
+    Created,
+    Changed,
+    Unchanged,
+}
+
+impl KeyChange {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Changed => "changed",
+            Self::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Classifies each of `keys` by comparing its value before and after an edit.
+pub fn classify_changes(
+    before: &str,
+    after: &str,
+    keys: &[String],
+    preserve_numbers: bool,
+) -> AppResult<Vec<(String, KeyChange)>> {
+    let before = read_yaml_document(before, "from original document", preserve_numbers)?;
+    let after = read_yaml_document(after, "from updated document", preserve_numbers)?;
+
+    keys.iter()
+        .map(|key| {
+            if key.ends_with("[]") {
+                return Ok((key.clone(), KeyChange::Created));
+            }
+
+            let previous = get_value(&before, key)?;
+            let change = if previous.is_none() {
+                KeyChange::Created
+            } else if previous == get_value(&after, key)? {
+                KeyChange::Unchanged
+            } else {
+                KeyChange::Changed
+            };
+            Ok((key.clone(), change))
+        })
+        .collect()
+}
+
+/// Renames every mapping key matching `regex` to its `replacement`, at any
+/// depth, rewriting only the key tokens. Fails when a new name collides with
+/// another key of the same mapping.
+pub fn rename_keys(yaml_content: &str, regex: &Regex, replacement: &str) -> AppResult<String> {
+    let document = parse_yaml_document(yaml_content, "from document")?;
+    let mut renames = Vec::new();
+    collect_key_renames(&document, regex, replacement, &mut Vec::new(), &mut renames)?;
+
+    let source = Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let mut spans = renames
+        .into_iter()
+        .map(|(path, new_key)| {
+            let feature = source
+                .query_key_only(&path.to_route())
+                .map_err(|error| AppError::patch(error.to_string()))?;
+            Ok((feature.location.byte_span, new_key))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+    spans.sort_by_key(|((start, _), _)| std::cmp::Reverse(*start));
+
+    let mut result = yaml_content.to_string();
+    for ((start, end), new_key) in spans {
+        result.replace_range(start..end, &key_token(&new_key));
+    }
+    Ok(result)
+}
+
+fn collect_key_renames(
+    value: &Value,
+    regex: &Regex,
+    replacement: &str,
+    path: &mut Vec<PathSegment>,
+    renames: &mut Vec<(YamlPath, String)>,
+) -> AppResult<()> {
+    match value {
+        Value::Mapping(map) => {
+            let mut names: HashMap<String, String> = HashMap::new();
+            for (key, value) in map {
+                let Value::String(key) = key else {
+                    continue;
+                };
+
+                let new_key = regex.replace_all(key, replacement).into_owned();
+                if let Some(other) = names.insert(new_key.clone(), key.clone()) {
+                    return Err(AppError::message(format!(
+                        "Renaming keys would merge '{}' and '{}' into '{new_key}'",
+                        render_path(&[path.as_slice(), &[PathSegment::Key(other)]].concat()),
+                        render_path(&[path.as_slice(), &[PathSegment::Key(key.clone())]].concat()),
+                    )));
+                }
+
+                path.push(PathSegment::Key(key.clone()));
+                if new_key != *key {
+                    renames.push((YamlPath::from_segments(path.clone()), new_key));
+                }
+                collect_key_renames(value, regex, replacement, path, renames)?;
+                path.pop();
+            }
+        }
+        Value::Sequence(sequence) => {
+            for (index, value) in sequence.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                collect_key_renames(value, regex, replacement, path, renames)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Lists the paths of every leaf value added, removed or changed by an edit.
+pub fn changed_keys(before: &str, after: &str) -> AppResult<Vec<String>> {
+    // A number rewritten as other text (`1.10` to `1.1`) is a changed key.
+    Ok(value_changes(before, after, true)?
+        .into_iter()
+        .map(|change| change.key)
+        .collect())
+}
+
+/// A key path whose value differs between two documents; `before` or
+/// `after` is `None` when the key was added or removed.
+#[derive(Debug, PartialEq)]
+pub struct ValueChange {
+    pub key: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    pub fn parse(input: &str) -> AppResult<Self> {
+        match input {
+            "added" => Ok(Self::Added),
+            "removed" => Ok(Self::Removed),
+            "changed" => Ok(Self::Changed),
+            _ => Err(AppError::cli(format!(
+                "Unknown change kind: {input} (expected added, removed or changed)"
+            ))),
+        }
+    }
+}
+
+impl ValueChange {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ => ChangeKind::Changed,
+        }
+    }
+}
+
+/// Compares two documents structurally, ignoring key order and formatting.
+pub fn value_changes(
+    before: &str,
+    after: &str,
+    preserve_numbers: bool,
+) -> AppResult<Vec<ValueChange>> {
+    // A file that did not exist or was empty reads as null; diff it as an
+    // empty mapping so the keys it gained are listed one by one.
+    let document = |content: &str, context: &str| {
+        read_yaml_document(content, context, preserve_numbers).map(|value| match value {
+            Value::Null => Value::Mapping(serde_yaml::Mapping::new()),
+            value => value,
+        })
+    };
+    let before = document(before, "from original document")?;
+    let after = document(after, "from updated document")?;
+
+    let mut results = Vec::new();
+    collect_value_changes(Some(&before), Some(&after), &mut Vec::new(), &mut results);
+    Ok(results)
+}
+
+fn collect_value_changes(
+    before: Option<&Value>,
+    after: Option<&Value>,
+    path: &mut Vec<PathSegment>,
+    results: &mut Vec<ValueChange>,
+) {
+    match (before, after) {
+        (Some(Value::Mapping(before)), Some(Value::Mapping(after))) => {
+            let keys = before
+                .keys()
+                .chain(after.keys().filter(|key| !before.contains_key(*key)));
+            for key in keys {
+                path.push(PathSegment::Key(format_inline_key(key)));
+                collect_value_changes(before.get(key), after.get(key), path, results);
+                path.pop();
+            }
+        }
+        (Some(Value::Sequence(before)), Some(Value::Sequence(after))) => {
+            for index in 0..before.len().max(after.len()) {
+                path.push(PathSegment::Index(index));
+                collect_value_changes(before.get(index), after.get(index), path, results);
+                path.pop();
+            }
+        }
+        (before, after) if before != after => results.push(ValueChange {
+            key: render_path(path),
+            before: before.cloned(),
+            after: after.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Builds a fresh block-style document by running `edit` on an empty one.
+pub fn new_document(edit: impl FnOnce(&str) -> AppResult<String>) -> AppResult<String> {
+    let built = with_document("", edit)?;
+
+    if built.trim().is_empty() {
+        return Err(AppError::message("Cannot append an empty document"));
+    }
+    Ok(built)
+}
+
+/// Whether `yaml_content` holds anything besides blank lines, comments and
+/// document markers.
+fn has_document(yaml_content: &str) -> bool {
+    yaml_content.lines().any(|line| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#') && line != "---" && line != "..."
+    })
+}
+
+/// Runs `edit` with a placeholder key appended to `yaml_content`, giving a
+/// file without a document a mapping to edit, then removes the placeholder.
+fn with_document(
+    yaml_content: &str,
+    edit: impl FnOnce(&str) -> AppResult<String>,
+) -> AppResult<String> {
+    let separator = if yaml_content.is_empty() || yaml_content.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    let built = edit(&format!(
+        "{yaml_content}{separator}{PLACEHOLDER_KEY}: null\n"
+    ))?;
+    unset_values(&built, &[PLACEHOLDER_KEY.to_string()])
+}
+
+/// Appends `document` to a (possibly multi-document) stream after a `---`
+/// separator, leaving the existing content untouched.
+pub fn append_document(yaml_content: &str, document: &str) -> String {
+    if yaml_content.trim().is_empty() {
+        return document.to_string();
+    }
+
+    let separator = if yaml_content.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{yaml_content}{separator}---\n{document}")
+}
+
+/// Deep-merges `overlay` into the document: mappings present on both sides
+/// are merged key by key, sequences and other conflicting values are
+/// resolved by `strategy`.
+pub fn deep_merge(
+    yaml_content: &str,
+    overlay: &Value,
+    strategy: &MergeStrategy,
+    options: &EditOptions,
+) -> AppResult<String> {
+    Ok(deep_merge_with_paths(yaml_content, overlay, strategy, options)?.0)
+}
+
+/// Like `deep_merge`, also returning the path of every value written from
+/// `overlay`, in the order written. A written mapping or sequence is one
+/// path covering everything below it.
+pub fn deep_merge_with_paths(
+    yaml_content: &str,
+    overlay: &Value,
+    strategy: &MergeStrategy,
+    options: &EditOptions,
+) -> AppResult<(String, Vec<YamlPath>)> {
+    let Value::Mapping(overlay) = overlay else {
+        return Err(AppError::message(
+            "Only a mapping can be merged into a document",
+        ));
+    };
+
+    let mut result = yaml_content.to_string();
+    let mut written = Vec::new();
+    for (key, value) in overlay {
+        let path = YamlPath::from_key(mapping_key(key, None)?);
+        result = merge_at_path(&result, &path, value, strategy, options, &mut written)?;
+    }
+    Ok((result, written))
+}
+
+fn merge_at_path(
+    yaml_content: &str,
+    path: &YamlPath,
+    overlay: &Value,
+    strategy: &MergeStrategy,
+    options: &EditOptions,
+    written: &mut Vec<YamlPath>,
+) -> AppResult<String> {
+    let current = parse_yaml_document(yaml_content, "from document")?;
+
+    match (get_value_at_path(&current, path)?, overlay) {
+        (None, _) => {
+            written.push(path.clone());
+            yaml_set(yaml_content, &path.display(), overlay.clone(), options)
+        }
+        (Some(existing), _) if existing == *overlay => Ok(yaml_content.to_string()),
+        (Some(Value::Mapping(_)), Value::Mapping(overlay)) => {
+            let mut result = yaml_content.to_string();
+            for (key, value) in overlay {
+                let child = path.push_key(mapping_key(key, Some(path))?);
+                result = merge_at_path(&result, &child, value, strategy, options, written)?;
+            }
+            Ok(result)
+        }
+        (Some(Value::Sequence(mut existing)), Value::Sequence(items))
+            if strategy.sequences != SequenceStrategy::Replace =>
+        {
+            let mut result = yaml_content.to_string();
+            for item in items {
+                if strategy.sequences == SequenceStrategy::Union && existing.contains(item) {
+                    continue;
+                }
+                let append = format!("{}[]", path.display());
+                result = yaml_set(&result, &append, item.clone(), options)?;
+                written.push(path.push_index(existing.len()));
+                existing.push(item.clone());
+            }
+            Ok(result)
+        }
+        (Some(_), _) => match strategy.conflicts {
+            ConflictStrategy::OverlayWins => {
+                written.push(path.clone());
+                yaml_set(yaml_content, &path.display(), overlay.clone(), options)
+            }
+            ConflictStrategy::BaseWins => Ok(yaml_content.to_string()),
+            ConflictStrategy::ErrorOnConflict => Err(AppError::message(format!(
+                "Conflicting values at '{}'",
+                path.display()
+            ))),
+        },
+    }
+}
+
+fn mapping_key(key: &Value, parent: Option<&YamlPath>) -> AppResult<String> {
+    match key {
+        Value::String(key) => Ok(key.clone()),
+        _ => Err(AppError::message(format!(
+            "Unsupported non-string key under '{}'",
+            parent.map(YamlPath::display).unwrap_or_default()
+        ))),
+    }
+}
+
+pub fn unset_values(yaml_content: &str, keys: &[String]) -> AppResult<String> {
+    edit_existing_keys(yaml_content, keys, remove_at_path)
+}
+
+/// Like `unset_values`, then removes each enclosing mapping the removal left
+/// empty, up to the first non-empty one; an emptied sequence is kept. The
+/// document itself is kept even when it ends up empty.
+pub fn prune_values(yaml_content: &str, keys: &[String]) -> AppResult<String> {
+    edit_existing_keys(yaml_content, keys, |content, path| {
+        let original = parse_yaml_document(content, "from document")?;
+        let mut result = remove_at_path(content, path)?;
+        let mut parent = path.parent();
+        while let Some(path) = parent {
+            if !matches!(
+                get_value_at_path(&original, &path)?,
+                Some(Value::Mapping(_))
+            ) {
+                break;
+            }
+            let document = parse_yaml_document(&result, "from document")?;
+            match get_value_at_path(&document, &path)? {
+                // A block mapping emptied by the removal reads back as null.
+                Some(Value::Null) => result = remove_at_path(&result, &path)?,
+                Some(Value::Mapping(map)) if map.is_empty() => {
+                    result = remove_at_path(&result, &path)?;
+                }
+                _ => break,
+            }
+            parent = path.parent();
+        }
+        Ok(result)
+    })
+}
+
+/// Sets each existing key of `keys` to an explicit `null`, keeping the key,
+/// its position and any trailing comment.
+pub fn null_out_values(yaml_content: &str, keys: &[String]) -> AppResult<String> {
+    edit_existing_keys(yaml_content, keys, |content, path| {
+        replace_value_at_path(content, path, Value::Null)
+    })
+}
+
+/// Applies `edit` to each of `keys` that exists, in order; missing keys are
+/// skipped.
+fn edit_existing_keys(
+    yaml_content: &str,
+    keys: &[String],
+    edit: impl Fn(&str, &YamlPath) -> AppResult<String>,
+) -> AppResult<String> {
+    let mut result = yaml_content.to_string();
+
+    for key_path in keys {
+        let current = parse_yaml_document(&result, "from document")?;
+        let path = resolve_path(&current, YamlPath::parse(key_path)?)?;
+        if get_value_at_path(&current, &path)?.is_some() {
+            result = edit(&result, &path)?;
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn get_value(value: &Value, path: &str) -> AppResult<Option<Value>> {
+    let path = resolve_path(value, YamlPath::parse(path)?)?;
+    get_value_at_path(value, &path)
+}
+
+/// Looks up `pointer` (RFC 6901), using numeric tokens as indices only
+/// where the current node is a sequence.
+pub fn get_pointer(value: &Value, pointer: &str) -> AppResult<Option<Value>> {
+    let mut current = value;
+
+    for token in pointer_tokens(pointer)? {
+        let next = match current {
+            Value::Mapping(map) => map.get(Value::String(token)),
+            Value::Sequence(sequence) => token
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| sequence.get(index)),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(current.clone()))
+}
+
+fn get_value_at_path(value: &Value, path: &YamlPath) -> AppResult<Option<Value>> {
+    let mut current = value;
+
+    for segment in path.as_segments() {
+        match segment {
+            PathSegment::Key(key) => {
+                let Value::Mapping(map) = current else {
+                    return Ok(None);
+                };
+                match map.get(Value::String(key.clone())) {
+                    Some(next) => current = next,
+                    None => return Ok(None),
+                }
+            }
+            PathSegment::Index(index) => {
+                let Value::Sequence(sequence) = current else {
+                    return Ok(None);
+                };
+                match sequence.get(*index) {
+                    Some(next) => current = next,
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+
+    Ok(Some(current.clone()))
+}
+
+pub fn copy_in_document(
+    yaml_content: &str,
+    source_key: &str,
+    dest_key: &str,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let source_yaml = read_yaml_document(
+        yaml_content,
+        "from source document",
+        options.preserve_numbers,
+    )?;
+    let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
+        AppError::message(format!("Key '{source_key}' not found in source document"))
+    })?;
+
+    yaml_set(yaml_content, dest_key, value, options)
+}
+
+/// Copies each source key to its destination key in turn, then removes all
+/// the source keys in one pass. A source key that is also a destination is
+/// kept.
+pub fn move_in_document(
+    yaml_content: &str,
+    keys: &[(String, String)],
+    options: &EditOptions,
+) -> AppResult<String> {
+    let moved: Vec<_> = keys
+        .iter()
+        .filter(|(source_key, dest_key)| source_key != dest_key)
+        .collect();
+
+    let mut copied = yaml_content.to_string();
+    for (source_key, dest_key) in &moved {
+        copied = copy_in_document(&copied, source_key, dest_key, options)?;
+    }
+
+    let sources: Vec<String> = moved
+        .iter()
+        .map(|(source_key, _)| source_key.clone())
+        .filter(|source_key| !keys.iter().any(|(_, dest_key)| dest_key == source_key))
+        .collect();
+    unset_values(&copied, &sources)
+}
+
+/// Copies each `(source key, destination key)` pair from `source_file` into
+/// `dest_file`, reading both files once and writing the destination once.
+pub fn copy_values(
+    source_file: &str,
+    dest_file: &str,
+    keys: &[(String, String)],
+    options: &TransferOptions,
+    edit: &EditOptions,
+) -> AppResult<()> {
+    let source_contents =
+        fs::read_to_string(source_file).map_err(|error| AppError::read_file(source_file, error))?;
+
+    let mut updated = if source_file == dest_file {
+        source_contents.clone()
+    } else if Path::new(dest_file).exists() {
+        fs::read_to_string(dest_file).map_err(|error| AppError::read_file(dest_file, error))?
+    } else {
+        String::new()
+    };
+
+    let source_yaml = read_yaml_document(
+        &source_contents,
+        &format!("from '{source_file}'"),
+        edit.preserve_numbers,
+    )?;
+    for (source_key, dest_key) in keys {
+        let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
+            AppError::message(format!("Key '{source_key}' not found in '{source_file}'"))
+        })?;
+        // --same-type-only already guards what an overwrite may replace.
+        if options.same_type_only {
+            ensure_same_type(&updated, dest_file, dest_key, &value, edit)?;
+        } else if !options.force && !options.merge {
+            ensure_missing(&updated, dest_file, dest_key)?;
+        }
+        let anchor = if options.with_anchor {
+            find_anchor(
+                &source_contents,
+                &resolve_path(&source_yaml, YamlPath::parse(source_key)?)?,
+            )?
+        } else {
+            None
+        };
+
+        updated = match anchor {
+            _ if options.merge => {
+                if !matches!(value, Value::Mapping(_)) {
+                    return Err(AppError::message(format!(
+                        "--merge requires '{source_key}' in '{source_file}' to be a mapping"
+                    )));
+                }
+                merge_into_mapping(&updated, dest_file, dest_key, &value, edit)?
+            }
+            Some(anchor) if source_file == dest_file => {
+                set_alias(&updated, dest_key, &anchor, edit)?
+            }
+            Some(anchor) => declare_anchor(
+                &yaml_set(&updated, dest_key, value, edit)?,
+                dest_key,
+                &anchor,
+            )?,
+            None => yaml_set(&updated, dest_key, value, edit)?,
+        };
+    }
+
+    fs::write(dest_file, updated).map_err(|error| AppError::write_file(dest_file, error))?;
+    Ok(())
+}
+
+/// Byte offsets of the value at `path`: where the text preceding the value
+/// (key, `-` indicator and any anchor) ends, and the value's own span.
+fn value_offsets(yaml_content: &str, path: &YamlPath) -> AppResult<(usize, usize, usize)> {
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let feature = document
+        .query_exact(&path.to_route())
+        .map_err(|error| AppError::patch(error.to_string()))?
+        .ok_or_else(|| AppError::message(format!("Key '{}' not found", path.display())))?;
+    let (start, end) = feature.location.byte_span;
+    Ok((yaml_content[..start].trim_end().len(), start, end))
+}
+
+/// 1-based line and column where the value at `key_path` starts.
+pub fn value_position(yaml_content: &str, key_path: &str) -> AppResult<Option<(usize, usize)>> {
+    let route = YamlPath::parse(key_path)?.to_route();
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let feature = match document.query_exact(&route) {
+        Ok(Some(feature)) => feature,
+        _ => match document.query_pretty(&route) {
+            Ok(feature) => feature,
+            Err(_) => return Ok(None),
+        },
+    };
+
+    let (line, column) = feature.location.point_span.0;
+    Ok(Some((line + 1, column + 1)))
+}
+
+/// Like `grep`, but over the nodes written with `tag` (`!secret`,
+/// `!!binary`; the leading `!` may be omitted), at any depth. serde_yaml
+/// drops standard tags, so they are read from the source text. A tagged
+/// sequence item matches on its path as well as its value.
+pub fn grep_tagged(
+    yaml_content: &str,
+    value: &Value,
+    regex: &Regex,
+    options: &MatchOptions,
+    tag: &str,
+) -> AppResult<Vec<(String, Value)>> {
+    let tag = match tag.starts_with('!') {
+        true => Cow::Borrowed(tag),
+        false => Cow::Owned(format!("!{tag}")),
+    };
+    let document =
+        Document::new(yaml_content).map_err(|error| AppError::patch(error.to_string()))?;
+    let is_tagged = |path: &[PathSegment]| {
+        let route = YamlPath::from_segments(path.to_vec()).to_route();
+        let Ok(Some(feature)) = document.query_exact(&route) else {
+            return false;
+        };
+        let (start, end) = feature.location.byte_span;
+        yaml_content[start..end].split_whitespace().next() == Some(tag.as_ref())
+    };
+
+    let mut results = Vec::new();
+    collect_tagged(
+        value,
+        regex,
+        options,
+        &is_tagged,
+        &mut Vec::new(),
+        &mut results,
+    );
+    Ok(results)
+}
+
+fn collect_tagged(
+    value: &Value,
+    regex: &Regex,
+    options: &MatchOptions,
+    is_tagged: &impl Fn(&[PathSegment]) -> bool,
+    path: &mut Vec<PathSegment>,
+    results: &mut Vec<(String, Value)>,
+) {
+    for (segment, value) in entries(value) {
+        path.push(segment);
+        if is_tagged(path) && entry_matches(value, regex, options, path, true) {
+            results.push((render_path(path), value.clone()));
+        }
+        if !options.top_level {
+            collect_tagged(value, regex, options, is_tagged, path, results);
+        }
+        path.pop();
+    }
+}
+
+fn find_anchor(yaml_content: &str, path: &YamlPath) -> AppResult<Option<String>> {
+    let (prefix_end, _, _) = value_offsets(yaml_content, path)?;
+    let prefix = &yaml_content[..prefix_end];
+    let token_start = prefix
+        .rfind(char::is_whitespace)
+        .map_or(0, |index| index + 1);
+
+    Ok(prefix[token_start..]
+        .strip_prefix('&')
+        .map(|anchor| anchor.to_string()))
+}
+
+fn set_alias(
+    yaml_content: &str,
+    key_path: &str,
+    anchor: &str,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let mut result = yaml_set(yaml_content, key_path, Value::Null, options)?;
+    let path = document_path(&result, key_path)?;
+    let (_, start, end) = value_offsets(&result, &path)?;
+    result.replace_range(start..end, &format!("*{anchor}"));
+
+    parse_yaml_document(&result, "from document").map_err(|_| {
+        AppError::message(format!(
+            "Cannot alias '&{anchor}' at '{key_path}': the alias would precede its anchor"
+        ))
+    })?;
+    Ok(result)
+}
+
+fn declare_anchor(yaml_content: &str, key_path: &str, anchor: &str) -> AppResult<String> {
+    let (prefix_end, _, _) = value_offsets(yaml_content, &document_path(yaml_content, key_path)?)?;
+    let mut result = yaml_content.to_string();
+    result.insert_str(prefix_end, &format!(" &{anchor}"));
+    Ok(result)
+}
+
+fn merge_into_mapping(
+    dest_contents: &str,
+    dest_file: &str,
+    dest_key: &str,
+    value: &Value,
+    options: &EditOptions,
+) -> AppResult<String> {
+    let dest_yaml = parse_yaml_document(dest_contents, &format!("from '{dest_file}'"))?;
+    let path = resolve_path(&dest_yaml, YamlPath::parse(dest_key)?)?;
+    let Some(Value::Mapping(_)) = get_value_at_path(&dest_yaml, &path)? else {
+        return Err(AppError::message(format!(
+            "--merge requires '{dest_key}' in '{dest_file}' to be a mapping"
+        )));
+    };
+
+    merge_at_path(
+        dest_contents,
+        &path,
+        value,
+        &MergeStrategy::default(),
+        options,
+        &mut Vec::new(),
+    )
+}
+
+fn ensure_missing(dest_contents: &str, dest_file: &str, dest_key: &str) -> AppResult<()> {
+    let dest_yaml = parse_yaml_document(dest_contents, &format!("from '{dest_file}'"))?;
+    match get_value(&dest_yaml, dest_key)? {
+        Some(_) => Err(AppError::message(format!(
+            "Key '{dest_key}' already exists in '{dest_file}' (use --force to overwrite it)"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Refuses to overwrite the value at `dest_key` with a value of another type.
+/// A missing destination key is always accepted.
+fn ensure_same_type(
+    dest_contents: &str,
+    dest_file: &str,
+    dest_key: &str,
+    value: &Value,
+    options: &EditOptions,
+) -> AppResult<()> {
+    let dest_yaml = read_yaml_document(
+        dest_contents,
+        &format!("from '{dest_file}'"),
+        options.preserve_numbers,
+    )?;
+    let Some(existing) = get_value(&dest_yaml, dest_key)? else {
+        return Ok(());
+    };
+
+    let (existing_type, new_type) = (ValueType::of(&existing), ValueType::of(value));
+    if existing_type == new_type {
+        return Ok(());
+    }
+
+    Err(AppError::message(format!(
+        "Refusing to overwrite {} at '{dest_key}' in '{dest_file}' with {}",
+        existing_type.name(),
+        new_type.name()
+    )))
+}
+
+/// Moves each `(source key, destination key)` pair from `source_file` into
+/// `dest_file`, writing each file once and removing the sources in one pass.
+pub fn move_values(
+    source_file: &str,
+    dest_file: &str,
+    keys: &[(String, String)],
+    options: &TransferOptions,
+    edit: &EditOptions,
+) -> AppResult<()> {
+    let source_contents =
+        fs::read_to_string(source_file).map_err(|error| AppError::read_file(source_file, error))?;
+    let source_yaml = read_yaml_document(
+        &source_contents,
+        &format!("from '{source_file}'"),
+        edit.preserve_numbers,
+    )?;
+
+    if source_file == dest_file {
+        if options.same_type_only {
+            for (source_key, dest_key) in keys {
+                if let Some(value) = get_value(&source_yaml, source_key)? {
+                    ensure_same_type(&source_contents, dest_file, dest_key, &value, edit)?;
+                }
+            }
+        }
+
+        let updated = move_in_document(&source_contents, keys, edit)?;
+        fs::write(source_file, updated)
+            .map_err(|error| AppError::write_file(source_file, error))?;
+        return Ok(());
+    }
+
+    let mut updated_dest = if Path::new(dest_file).exists() {
+        fs::read_to_string(dest_file).map_err(|error| AppError::read_file(dest_file, error))?
+    } else {
+        "{}".to_string()
+    };
+
+    for (source_key, dest_key) in keys {
+        let value = get_value(&source_yaml, source_key)?.ok_or_else(|| {
+            AppError::message(format!("Key '{source_key}' not found in '{source_file}'"))
+        })?;
+        if options.same_type_only {
+            ensure_same_type(&updated_dest, dest_file, dest_key, &value, edit)?;
+        }
+        updated_dest = yaml_set(&updated_dest, dest_key, value, edit)?;
+    }
+    let source_keys: Vec<String> = keys
+        .iter()
+        .map(|(source_key, _)| source_key.clone())
+        .collect();
+    let updated_source = unset_values(&source_contents, &source_keys)?;
+
+    fs::write(dest_file, updated_dest).map_err(|error| AppError::write_file(dest_file, error))?;
+    fs::write(source_file, updated_source)
+        .map_err(|error| AppError::write_file(source_file, error))?;
+    Ok(())
+}
+
+pub fn format_result(
+    key: &str,
+    value: &Value,
+    terminal_width: usize,
+    mode: GrepOutputMode,
+) -> String {
+    match mode {
+        GrepOutputMode::Inline => {
+            let result = format!("{key}: {}", format_inline_value(value));
+            truncate_if_needed(&result, terminal_width)
+        }
+        GrepOutputMode::Full => format_full_result(key, value),
+    }
+}
+
+/// Renders `template`, replacing `{key}`, `{value}` and `{name}` for every
+/// named capture group of `regex` matched against `key`, which wins over
+/// `{key}` and `{value}`. The template is read once, so braces in the text
+/// substituted are left as they are; unknown placeholders are kept.
+pub fn format_template(template: &str, regex: &Regex, key: &str, value: &Value) -> String {
+    let captures = regex.captures(key);
+    let placeholder = |name: &str| {
+        if let Some(captures) = &captures {
+            if regex.capture_names().flatten().any(|group| group == name) {
+                let text = captures.name(name).map_or("", |capture| capture.as_str());
+                return Some(text.to_string());
+            }
+        }
+        match name {
+            "key" => Some(key.to_string()),
+            "value" => Some(format_inline_value(value)),
+            _ => None,
+        }
+    };
+
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let text = rest
+            .find('}')
+            .and_then(|end| Some((placeholder(&rest[1..end])?, end)));
+        match text {
+            Some((text, end)) => {
+                rendered.push_str(&text);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn truncate_if_needed(text: &str, terminal_width: usize) -> String {
+    if text.len() > terminal_width {
+        format!("{}...", &text[..terminal_width.saturating_sub(3)])
+    } else {
+        text.to_string()
+    }
+}
+
+fn format_full_result(key: &str, value: &Value) -> String {
+    let mut rendered = render_yaml_with_indent(key, value, 0);
+    if rendered.ends_with('\n') {
+        rendered.pop();
+    }
+    rendered
+}
+
+/// Renders a value on its own: scalars as plain text, collections as YAML.
+pub fn format_value(value: &Value) -> AppResult<String> {
+    match value {
+        Value::Mapping(_) | Value::Sequence(_) => serde_yaml::to_string(value)
+            .map(|rendered| rendered.trim_end().to_string())
+            .map_err(|error| AppError::message(format!("Failed to render value: {error}"))),
+        _ => Ok(format_inline_value(value)),
+    }
+}
+
+pub fn format_inline_value(value: &Value) -> String {
+    match value {
+        Value::Mapping(map) => {
+            let entries = map
+                .iter()
+                .map(|(key, val)| {
+                    format!("{}: {}", format_inline_key(key), format_inline_value(val))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{{ {entries} }}")
+        }
+        Value::Sequence(seq) => {
+            let items = seq
+                .iter()
+                .map(format_inline_value)
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("[{items}]")
+        }
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        _ => serde_yaml::to_string(value)
+            .unwrap_or_else(|_| "<complex>".to_string())
+            .trim()
+            .replace('\n', " "),
+    }
+}
+
+fn format_inline_key(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        _ => serde_yaml::to_string(key)
+            .unwrap_or_else(|_| "<key>".to_string())
+            .trim()
+            .replace('\n', " "),
+    }
+}
+
+fn render_yaml_with_indent(key: &str, value: &Value, indent: usize) -> String {
+    let prefix = "  ".repeat(indent);
+
+    match value {
+        Value::Mapping(map) => {
+            let mut output = format!("{prefix}{key}:\n");
+            for (child_key, child_value) in map {
+                output.push_str(&render_yaml_with_indent(
+                    &format_inline_key(child_key),
+                    child_value,
+                    indent + 1,
+                ));
+            }
+            output
+        }
+        Value::Sequence(seq) => {
+            let mut output = format!("{prefix}{key}:\n");
+            for item in seq {
+                output.push_str(&render_sequence_item(item, indent + 1));
+            }
+            output
+        }
+        _ => format!("{prefix}{key}: {}\n", format_inline_value(value)),
+    }
+}
+
+fn render_sequence_item(value: &Value, indent: usize) -> String {
+    let prefix = "  ".repeat(indent);
+
+    match value {
+        Value::Mapping(map) => {
+            let mut iter = map.iter();
+            if let Some((first_key, first_value)) = iter.next() {
+                let mut output = format!("{prefix}-");
+                match first_value {
+                    Value::Mapping(_) | Value::Sequence(_) => {
+                        output.push('\n');
+                        output.push_str(&render_yaml_with_indent(
+                            &format_inline_key(first_key),
+                            first_value,
+                            indent + 1,
+                        ));
+                    }
+                    _ => {
+                        output.push_str(&format!(
+                            " {}: {}\n",
+                            format_inline_key(first_key),
+                            format_inline_value(first_value)
+                        ));
+                    }
+                }
+
+                for (key, val) in iter {
+                    output.push_str(&render_yaml_with_indent(
+                        &format_inline_key(key),
+                        val,
+                        indent + 1,
+                    ));
+                }
+                output
+            } else {
+                format!("{prefix}- {{}}\n")
+            }
+        }
+        Value::Sequence(seq) => {
+            if seq.is_empty() {
+                format!("{prefix}- []\n")
+            } else {
+                let mut output = format!("{prefix}-\n");
+                for item in seq {
+                    output.push_str(&render_sequence_item(item, indent + 1));
+                }
+                output
+            }
+        }
+        _ => format!("{prefix}- {}\n", format_inline_value(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_yaml(yaml_str: &str) -> Value {
+        serde_yaml::from_str(yaml_str).expect("Failed to parse YAML")
+    }
+
+    fn grep(value: &Value, pattern: &str) -> AppResult<Vec<(String, Value)>> {
+        Ok(super::grep(
+            value,
+            &compile_pattern(pattern, &MatchOptions::default())?,
+            &MatchOptions::default(),
+        ))
+    }
+
+    fn key_pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(source, dest)| (source.to_string(), dest.to_string()))
+            .collect()
+    }
+
+    fn temp_test_dir(name: &str) -> String {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = format!("{name}_{}_{}", std::process::id(), unique);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_grep_simple_key() {
+        let yaml = parse_yaml("name: Alice\nage: 30");
+        let results = grep(&yaml, "name").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "name");
+        assert_eq!(results[0].1.as_str(), Some("Alice"));
+    }
+
+    #[test]
+    fn test_grep_compiles_regex_once_and_matches_nested_keys() {
+        let yaml = parse_yaml("database:\n  host: localhost\n  port: 5432\n");
+        let results = grep(&yaml, r"^database\.(host|port)$").unwrap();
+        let keys: Vec<_> = results.into_iter().map(|result| result.0).collect();
+        assert_eq!(keys, vec!["database.host", "database.port"]);
+    }
+
+    #[test]
+    fn test_grep_escapes_dotted_keys() {
+        let yaml = parse_yaml("metadata:\n  kubernetes.io/hostname: node-a\n");
+        let results = grep(&yaml, r"metadata\.kubernetes\\\.io/hostname$").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, r"metadata.kubernetes\.io/hostname");
+    }
+
+    #[test]
+    fn test_grep_sequence_paths() {
+        let yaml = parse_yaml("items:\n  - name: first\n  - name: second\n");
+        let results = grep(&yaml, r"items\[1\]\.name").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "items[1].name");
+        assert_eq!(results[0].1.as_str(), Some("second"));
+    }
+
+    #[test]
+    fn test_grep_stream_matches_like_grep() {
+        let contents = "base: &base\n  host: a\n  port: 1\nservers:\n  - host: b\n    tags: [web, host]\n  - *base\nsecret: !vault host\n1: host\n---\nhost: c\n";
+        let documents = parse_input_documents(contents, InputFormat::Yaml, "", false).unwrap();
+        let cases = [
+            ("host", MatchOptions::default()),
+            (
+                "host",
+                MatchOptions {
+                    target: MatchTarget::Values,
+                    ..MatchOptions::default()
+                },
+            ),
+            (
+                "base|servers",
+                MatchOptions {
+                    target: MatchTarget::Either,
+                    value_type: Some(ValueType::Sequence),
+                    ..MatchOptions::default()
+                },
+            ),
+            (
+                "HOST",
+                MatchOptions {
+                    top_level: true,
+                    fold_keys: true,
+                    ..MatchOptions::default()
+                },
+            ),
+        ];
+
+        for (pattern, options) in cases {
+            let regex = compile_pattern(pattern, &options).unwrap();
+            let expected: Vec<_> = documents
+                .iter()
+                .map(|document| super::grep(document, &regex, &options))
+                .collect();
+            assert_eq!(
+                grep_stream(contents, &regex, &options, "").unwrap(),
+                expected,
+                "{pattern} {options:?}"
+            );
+        }
+        assert!(grep_stream(
+            "a: [1\n",
+            &Regex::new("a").unwrap(),
+            &MatchOptions::default(),
+            ""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_grep_invalid_regex() {
+        let yaml = parse_yaml("name: Alice");
+        assert!(grep(&yaml, "[invalid").is_err());
+    }
+
+    #[test]
+    fn test_grep_type_filter_composes_with_key_match() {
+        let yaml = parse_yaml(concat!(
+            "http:\n  timeout: 30\n",
+            "db:\n  timeout: 30s\n",
+            "timeouts:\n  read: 5\n  write: slow\n",
+        ));
+        let options = MatchOptions {
+            value_type: Some(ValueType::Int),
+            ..MatchOptions::default()
+        };
+        let regex = compile_pattern("timeout", &options).unwrap();
+        let results = super::grep(&yaml, &regex, &options);
+        let keys: Vec<_> = results.into_iter().map(|result| result.0).collect();
+        assert_eq!(keys, vec!["http.timeout", "timeouts.read"]);
+    }
+
+    #[test]
+    fn test_grep_matches_values_or_either_target() {
+        let yaml = parse_yaml(concat!(
+            "db:\n  host: localhost\n  port: 5432\n",
+            "cache:\n  hosts: [localhost, redis]\n",
+            "localhost_alias: db\n",
+        ));
+        let keys = |target| {
+            let options = MatchOptions {
+                target,
+                ..MatchOptions::default()
+            };
+            let regex = compile_pattern("^localhost", &options).unwrap();
+            super::grep(&yaml, &regex, &options)
+                .into_iter()
+                .map(|result| result.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(MatchTarget::Keys), vec!["localhost_alias"]);
+        assert_eq!(keys(MatchTarget::Values), vec!["db.host", "cache.hosts[0]"]);
+        assert_eq!(
+            keys(MatchTarget::Either),
+            vec!["db.host", "cache.hosts[0]", "localhost_alias"]
+        );
+    }
+
+    #[test]
+    fn test_grep_top_level_skips_nested_keys() {
+        let yaml = parse_yaml("database:\n  host: db\nhosts:\n  - host: a\nlegacy:\n  host: x\n");
+        let options = MatchOptions {
+            top_level: true,
+            ..MatchOptions::default()
+        };
+        let regex = compile_pattern("host", &options).unwrap();
+        let keys: Vec<_> = super::grep(&yaml, &regex, &options)
+            .into_iter()
+            .map(|result| result.0)
+            .collect();
+        assert_eq!(keys, vec!["hosts"]);
+
+        let regex = compile_pattern("legacy", &options).unwrap();
+        assert_eq!(super::grep(&yaml, &regex, &options).len(), 1);
+    }
+
+    #[test]
+    fn test_grep_fold_keys_lowercases_and_dedups_paths() {
+        let yaml =
+            parse_yaml("Host: a\nhost: a\nHOST: b\nServer:\n  Port: 80\nserver:\n  port: 80\n");
+        let options = MatchOptions {
+            fold_keys: true,
+            ..MatchOptions::default()
+        };
+
+        let regex = compile_pattern("host", &options).unwrap();
+        assert_eq!(
+            super::grep(&yaml, &regex, &options),
+            vec![
+                ("host".to_string(), Value::String("a".to_string())),
+                ("host".to_string(), Value::String("b".to_string())),
+            ]
+        );
+
+        let regex = compile_pattern(r"SERVER\.port", &options).unwrap();
+        assert_eq!(
+            super::grep(&yaml, &regex, &options),
+            vec![("server.port".to_string(), Value::Number(80.into()))]
+        );
+    }
+
+    #[test]
+    fn test_list_keys_with_and_without_intermediate_keys() {
+        let yaml = parse_yaml("db:\n  host: a\nservers:\n  - name: x\n  - y\nempty: {}\n");
+
+        assert_eq!(
+            list_keys(&yaml, false),
+            vec![
+                "db",
+                "db.host",
+                "servers",
+                "servers[0]",
+                "servers[0].name",
+                "servers[1]",
+                "empty",
+            ]
+        );
+        assert_eq!(
+            list_keys(&yaml, true),
+            vec!["db.host", "servers[0].name", "servers[1]", "empty"]
+        );
+    }
+
+    #[test]
+    fn test_find_keys_ranks_fuzzy_matches() {
+        let yaml = parse_yaml(concat!(
+            "database:\n  db_host: a\n  replica:\n    DB-Host: b\n",
+            "dbhost: c\nhostname: d\n",
+        ));
+
+        assert_eq!(
+            find_keys(&yaml, "db.host"),
+            vec!["dbhost", "database.db_host", "database.replica.DB-Host"]
+        );
+        assert_eq!(
+            find_keys(&yaml, "HOST"),
+            vec![
+                "hostname",
+                "dbhost",
+                "database.db_host",
+                "database.replica.DB-Host"
+            ]
+        );
+        assert!(find_keys(&yaml, "port").is_empty());
+    }
+
+    #[test]
+    fn test_nest_results_rebuilds_matched_subset() {
+        let yaml = parse_yaml(concat!(
+            "database:\n  host: db\n  port: 5432\n",
+            "servers:\n  - name: a\n    host: x\n  - name: b\n    host: y\n",
+            "cache:\n  host: c\n  ttl: 60\n",
+        ));
+        let options = MatchOptions::default();
+
+        let results = super::grep(
+            &yaml,
+            &compile_pattern(r"host$|^cache$", &options).unwrap(),
+            &options,
+        );
+        assert_eq!(
+            nest_results(&results).unwrap(),
+            parse_yaml(concat!(
+                "database:\n  host: db\n",
+                "servers:\n  - host: x\n  - host: y\n",
+                "cache:\n  host: c\n  ttl: 60\n",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_grep_exact_matches_whole_key_paths_only() {
+        let yaml = parse_yaml("host: a\nhostname: b\ndatabase:\n  host: c\n");
+        let keys = |pattern: &str, exact| {
+            let options = MatchOptions {
+                exact,
+                ..MatchOptions::default()
+            };
+            let regex = compile_pattern(pattern, &options).unwrap();
+            super::grep(&yaml, &regex, &options)
+                .into_iter()
+                .map(|result| result.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            keys("host", false),
+            vec!["host", "hostname", "database.host"]
+        );
+        assert_eq!(keys("host", true), vec!["host"]);
+        assert_eq!(
+            keys("database.host|host", true),
+            vec!["host", "database.host"]
+        );
+    }
+
+    #[test]
+    fn test_grep_word_regexp_matches_whole_words_only() {
+        let yaml = parse_yaml("server:\n  port: 80\nexport: true\nportal: web\n");
+        let keys = |word_regexp| {
+            let options = MatchOptions {
+                word_regexp,
+                ..MatchOptions::default()
+            };
+            let regex = compile_pattern("port", &options).unwrap();
+            super::grep(&yaml, &regex, &options)
+                .into_iter()
+                .map(|result| result.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(false), vec!["server.port", "export", "portal"]);
+        assert_eq!(keys(true), vec!["server.port"]);
+    }
+
+    #[test]
+    fn test_grep_ignore_case() {
+        let yaml = parse_yaml("database:\n  host: db\n");
+        let keys = |ignore_case| {
+            let options = MatchOptions {
+                ignore_case,
+                ..MatchOptions::default()
+            };
+            let regex = compile_pattern(r"DATABASE\.host", &options).unwrap();
+            super::grep(&yaml, &regex, &options)
+                .into_iter()
+                .map(|result| result.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(keys(false).is_empty());
+        assert_eq!(keys(true), vec!["database.host"]);
+    }
+
+    #[test]
+    fn test_compile_pattern_enforces_regex_size_limit() {
+        let options = MatchOptions {
+            regex_size_limit: Some(1024),
+            ..MatchOptions::default()
+        };
+        assert!(compile_pattern("host", &options).is_ok());
+
+        let error = compile_pattern("a{1000}", &options).unwrap_err();
+        assert!(error.to_string().contains("exceeds size limit"));
+        assert!(compile_pattern("a{1000}", &MatchOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_grep_any_separator_accepts_slash_and_dot_patterns() {
+        let yaml = parse_yaml("database:\n  host: db\nlabels:\n  team/owner: infra\n");
+        let options = MatchOptions {
+            any_separator: true,
+            ..MatchOptions::default()
+        };
+
+        for pattern in ["^database/host$", r"^database\.host$"] {
+            let regex = compile_pattern(pattern, &options).unwrap();
+            let keys: Vec<_> = super::grep(&yaml, &regex, &options)
+                .into_iter()
+                .map(|result| result.0)
+                .collect();
+            assert_eq!(keys, vec!["database.host"]);
+        }
+
+        let regex = compile_pattern("labels/team/owner", &options).unwrap();
+        let keys: Vec<_> = super::grep(&yaml, &regex, &options)
+            .into_iter()
+            .map(|result| result.0)
+            .collect();
+        assert_eq!(keys, vec!["labels.team/owner"]);
+        assert!(grep(&yaml, "^database/host$").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_value_type_parse_rejects_unknown_type() {
+        assert_eq!(ValueType::parse("float").unwrap(), ValueType::Float);
+        assert!(ValueType::parse("integer").is_err());
+    }
+
+    #[test]
+    fn test_depth_histogram_counts_keys_per_level() {
+        let yaml = parse_yaml(concat!(
+            "app:\n  name: web\n  server:\n    port: 80\n    host: 0.0.0.0\n",
+            "db:\n  replicas:\n    - host: a\n    - host: b\n",
+            "debug: true\n",
+        ));
+        let regex = compile_pattern(".", &MatchOptions::default()).unwrap();
+        assert_eq!(depth_histogram(&yaml, &regex), vec![3, 3, 4]);
+
+        let regex = compile_pattern("host", &MatchOptions::default()).unwrap();
+        assert_eq!(depth_histogram(&yaml, &regex), vec![0, 0, 3]);
+    }
+
+    #[test]
+    fn test_distinct_values_tallies_matched_scalars() {
+        let yaml = parse_yaml(concat!(
+            "api:\n  host: db.internal\n",
+            "worker:\n  host: db.internal\n",
+            "cache:\n  host: redis.internal\n",
+            "proxy:\n  host:\n    name: nested\n",
+        ));
+        let results = grep(&yaml, r"\.host$").unwrap();
+        let tallies = distinct_values(results.iter().map(|(_, value)| value));
+        assert_eq!(
+            tallies,
+            vec![
+                ("db.internal".to_string(), 2),
+                ("redis.internal".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_input_json() {
+        let value = parse_input(
+            r#"{"database": {"host": "db", "port": 5432}}"#,
+            InputFormat::Json,
+            "from stdin",
+            false,
+        )
+        .unwrap();
+        assert_eq!(value["database"]["host"].as_str(), Some("db"));
+        assert_eq!(value["database"]["port"].as_i64(), Some(5432));
+        assert!(parse_input("name: x", InputFormat::Json, "from stdin", false).is_err());
+    }
+
+    #[test]
+    fn test_parse_input_toml() {
+        let value = parse_input(
+            "[database]\nhost = \"db\"\nport = 5432\n",
+            InputFormat::Toml,
+            "from stdin",
+            false,
+        )
+        .unwrap();
+        assert_eq!(value["database"]["host"].as_str(), Some("db"));
+        assert_eq!(value["database"]["port"].as_i64(), Some(5432));
+        let error = parse_input("name: x", InputFormat::Toml, "from stdin", false).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Failed to parse TOML from stdin"));
+    }
+
+    #[test]
+    fn test_format_template_with_named_captures() {
+        let yaml = parse_yaml("servers:\n  web:\n    port: 80\n  api:\n    port: 8080\n");
+        let regex =
+            compile_pattern(r"servers\.(?P<svc>[^.]+)\.port", &MatchOptions::default()).unwrap();
+        let rendered: Vec<_> = super::grep(&yaml, &regex, &MatchOptions::default())
+            .iter()
+            .map(|(key, value)| format_template("{svc} {value}", &regex, key, value))
+            .collect();
+        assert_eq!(rendered, vec!["web 80", "api 8080"]);
+    }
+
+    #[test]
+    fn test_format_template_with_key_and_unmatched_group() {
+        let regex = compile_pattern(r"name(?P<suffix>_x)?", &MatchOptions::default()).unwrap();
+        let value = Value::String("Alice".to_string());
+        assert_eq!(
+            format_template("{key}={value}[{suffix}]", &regex, "name", &value),
+            "name=Alice[]"
+        );
+    }
+
+    #[test]
+    fn test_format_template_leaves_braces_in_values_alone() {
+        let regex = compile_pattern(r"(?P<svc>\w+)\.cmd", &MatchOptions::default()).unwrap();
+        let value = Value::String("echo {svc} {key}".to_string());
+        assert_eq!(
+            format_template("{svc}: {value} {other}", &regex, "web.cmd", &value),
+            "web: echo {svc} {key} {other}"
+        );
+    }
+
+    #[test]
+    fn test_set_and_unset_values_update_yaml_semantics() {
+        let yaml_str = "database:\n  host: localhost\n  port: 5432\nconfig:\n  level: info\n";
+        let updates = vec![
+            ("database.port".to_string(), "3306".to_string()),
+            ("database.username".to_string(), "admin".to_string()),
+            ("app.server.config.timeout".to_string(), "30".to_string()),
+        ];
+
+        let updated = set_values(yaml_str, &updates, &EditOptions::default()).unwrap();
+        let parsed = parse_yaml(&updated);
+        assert_eq!(parsed["database"]["host"].as_str(), Some("localhost"));
+        assert_eq!(parsed["database"]["port"].as_i64(), Some(3306));
+        assert_eq!(parsed["database"]["username"].as_str(), Some("admin"));
+        assert_eq!(
+            parsed["app"]["server"]["config"]["timeout"].as_i64(),
+            Some(30)
+        );
+
+        let removed = unset_values(
+            &updated,
+            &[
+                "database.port".to_string(),
+                "app.server.config.timeout".to_string(),
+            ],
+        )
+        .unwrap();
+        let parsed = parse_yaml(&removed);
+        assert!(get_value(&parsed, "database.port").unwrap().is_none());
+        assert!(get_value(&parsed, "app.server.config.timeout")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_set_creates_new_parent_once_with_children_in_update_order() {
+        let updates = vec![
+            ("a.c".to_string(), "2".to_string()),
+            ("a.b".to_string(), "1".to_string()),
+            ("a.d.e".to_string(), "3".to_string()),
+        ];
+
+        let updated = set_values("x: 1\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "x: 1\na:\n  c: 2\n  b: 1\n  d:\n    e: 3\n");
+    }
+
+    #[test]
+    fn test_set_appends_to_flow_sequence_inline() {
+        let updates = vec![("ports[]".to_string(), "8080".to_string())];
+        let updated = set_values(
+            "ports: [80, 443] # public\nname: web\n",
+            &updates,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(updated, "ports: [80, 443, 8080] # public\nname: web\n");
+
+        let updated =
+            set_values("ports: [ 80, 443, ]\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "ports: [ 80, 443, 8080, ]\n");
+
+        let updated = set_values("ports: []\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "ports: [8080]\n");
+
+        let updates = vec![("tags[2]".to_string(), "a, b".to_string())];
+        let updated = set_values("tags: [x, y]\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "tags: [x, y, \"a, b\"]\n");
+    }
+
+    #[test]
+    fn test_set_appends_to_block_sequence_with_empty_index() {
+        let updates = vec![("ports[]".to_string(), "8080".to_string())];
+        let updated = set_values("ports:\n  - 80\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "ports:\n  - 80\n  - 8080\n");
+    }
+
+    #[test]
+    fn test_set_index_zero_creates_missing_sequence() {
+        let updates = vec![("db.servers[0]".to_string(), "web".to_string())];
+        let updated = set_values("name: app\n", &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "name: app\ndb:\n  servers: [web]\n");
+
+        let updates = vec![("servers[1]".to_string(), "web".to_string())];
+        assert!(set_values("name: app\n", &updates, &EditOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_set_force_array_replaces_scalar_with_sequence() {
+        let updates = vec![("servers[0]".to_string(), "api".to_string())];
+        let yaml = "servers: web # primary\nname: app\n";
+
+        let error = set_values(yaml, &updates, &EditOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("--force-array"));
+
+        let options = EditOptions {
+            force_array: true,
+            ..EditOptions::default()
+        };
+        let updated = set_values(yaml, &updates, &options).unwrap();
+        assert_eq!(updated, "servers: [api] # primary\nname: app\n");
+    }
+
+    #[test]
+    fn test_transform_value_applies_transforms_in_order() {
+        let name = Value::String("  Web App ".to_string());
+        assert_eq!(
+            transform_value(&name, &[Transform::Upper]).unwrap(),
+            "  WEB APP "
+        );
+        assert_eq!(transform_value(&name, &[Transform::Length]).unwrap(), "10");
+        assert_eq!(
+            transform_value(&name, &[Transform::Trim, Transform::Upper]).unwrap(),
+            "WEB APP"
+        );
+        assert_eq!(
+            transform_value(&name, &[Transform::Trim, Transform::Length]).unwrap(),
+            "7"
+        );
+        assert_eq!(
+            transform_value(&Value::Number(5432.into()), &[Transform::Length]).unwrap(),
+            "4"
+        );
+        assert!(transform_value(&parse_yaml("a: 1"), &[Transform::Upper]).is_err());
+    }
+
+    #[test]
+    fn test_base64_transform_pads_output() {
+        for (input, expected) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            let value = Value::String(input.to_string());
+            assert_eq!(
+                transform_value(&value, &[Transform::Base64]).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_style_converts_between_styles() {
+        for key in ["myNewKey", "my_new_key", "my-new-key", "MyNewKey"] {
+            assert_eq!(KeyStyle::Snake.apply(key), "my_new_key");
+            assert_eq!(KeyStyle::Camel.apply(key), "myNewKey");
+            assert_eq!(KeyStyle::Kebab.apply(key), "my-new-key");
+        }
+        assert_eq!(KeyStyle::Snake.apply("maxRetries2"), "max_retries2");
+        assert!(KeyStyle::parse("pascal").is_err());
+    }
+
+    #[test]
+    fn test_normalize_new_keys_only_rewrites_created_segments() {
+        let original = "databaseConfig:\n  hostName: db\nservers:\n  - name: a\n";
+
+        assert_eq!(
+            normalize_new_keys(original, "databaseConfig.maxPoolSize", KeyStyle::Snake).unwrap(),
+            "databaseConfig.max_pool_size"
+        );
+        assert_eq!(
+            normalize_new_keys(original, "newSection.subKey", KeyStyle::Kebab).unwrap(),
+            "new-section.sub-key"
+        );
+        assert_eq!(
+            normalize_new_keys(original, "servers[0].public_ip", KeyStyle::Camel).unwrap(),
+            "servers[0].publicIp"
+        );
+        assert_eq!(
+            normalize_new_keys(original, "databaseConfig.hostName", KeyStyle::Snake).unwrap(),
+            "databaseConfig.hostName"
+        );
+        assert_eq!(
+            normalize_new_keys(original, "extraTags[]", KeyStyle::Snake).unwrap(),
+            "extra_tags[]"
+        );
+    }
+
+    #[test]
+    fn test_scrub_removes_keys_with_matching_values_only() {
+        let original = "# creds\ndb:\n  password: hunter2 # leaked\n  user: app\nservers:\n  - name: a\n    token: hunter2\napi_key: hunter2\n";
+        let regex = Regex::new("^hunter2$").unwrap();
+        let keys = keys_with_matching_values(&parse_yaml(original), &regex);
+        assert_eq!(keys, vec!["db.password", "servers[0].token", "api_key"]);
+
+        let updated = unset_values(original, &keys).unwrap();
+        assert_eq!(
+            updated,
+            "# creds\ndb:\n  user: app\nservers:\n  - name: a\n"
+        );
+    }
+
+    #[test]
+    fn test_get_pointer_navigates_mappings_and_sequences() {
+        let yaml = parse_yaml(
+            "database:\n  host: db\nservers:\n  - host: a\n  - host: b\nlabels:\n  a/b: x\n  \"0\": zero\n",
+        );
+
+        assert_eq!(
+            get_pointer(&yaml, "/database/host").unwrap(),
+            Some(Value::String("db".to_string()))
+        );
+        assert_eq!(
+            get_pointer(&yaml, "/servers/1/host").unwrap(),
+            Some(Value::String("b".to_string()))
+        );
+        assert_eq!(
+            get_pointer(&yaml, "/labels/a~1b").unwrap(),
+            Some(Value::String("x".to_string()))
+        );
+        assert_eq!(
+            get_pointer(&yaml, "/labels/0").unwrap(),
+            Some(Value::String("zero".to_string()))
+        );
+        assert_eq!(get_pointer(&yaml, "").unwrap(), Some(yaml.clone()));
+        assert_eq!(get_pointer(&yaml, "/servers/5").unwrap(), None);
+        assert_eq!(get_pointer(&yaml, "/database/host/x").unwrap(), None);
+    }
+
+    #[test]
+    fn test_format_value_prints_scalars_plainly_and_collections_as_yaml() {
+        let yaml = parse_yaml("name: web\ndb:\n  host: a\n  ports: [1, 2]\n");
+        assert_eq!(format_value(&yaml["name"]).unwrap(), "web");
+        assert_eq!(
+            format_value(&yaml["db"]).unwrap(),
+            "host: a\nports:\n- 1\n- 2"
+        );
+    }
+
+    #[test]
+    fn test_set_and_unset_keep_untouched_lines_byte_for_byte() {
+        let original =
+            "# head   \r\nname: web\r\n\r\n\r\n# note  \t\nreplicas: 1\n  \nimage: a   # c  \n";
+        let updates = vec![("replicas".to_string(), "3".to_string())];
+
+        let updated = set_values(original, &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, original.replace("replicas: 1", "replicas: 3"));
+
+        let updated = unset_values(&updated, &["name".to_string()]).unwrap();
+        assert_eq!(
+            updated,
+            "# head   \r\n\r\n\r\n# note  \t\nreplicas: 3\n  \nimage: a   # c  \n"
+        );
+    }
+
+    #[test]
+    fn test_set_with_indent_creates_nested_mappings_with_given_width() {
+        let updates = vec![
+            ("database.primary.host".to_string(), "db".to_string()),
+            ("database.primary.port".to_string(), "5432".to_string()),
+            ("servers[0].tls.enabled".to_string(), "true".to_string()),
+        ];
+
+        let options = EditOptions {
+            indent: Some(4),
+            ..EditOptions::default()
+        };
+        let updated =
+            set_values("name: app\nservers:\n  - name: web\n", &updates, &options).unwrap();
+        assert_eq!(
+            updated,
+            concat!(
+                "name: app\nservers:\n  - name: web\n    tls:\n        enabled: true\n",
+                "database:\n    primary:\n        host: db\n        port: 5432\n",
+            )
+        );
+
+        let updated = set_values("name: app\n", &updates[..1], &EditOptions::default()).unwrap();
+        assert_eq!(updated, "name: app\ndatabase:\n  primary:\n    host: db\n");
+    }
+
+    #[test]
+    fn test_detect_indent_uses_dominant_mapping_step() {
+        assert_eq!(detect_indent("name: app\n"), 2);
+        assert_eq!(
+            detect_indent("server:\n    host: a\n    tls:\n        cert: c\nlist:\n  - a\n"),
+            4
+        );
+        assert_eq!(
+            detect_indent("items:\n- name: a\n  nested:\n     deep: 1\nother:\n   x: 1\n"),
+            3
+        );
+    }
+
+    #[test]
+    fn test_set_creates_nested_mappings_with_detected_width() {
+        let updated = set_values(
+            "server:\n    host: a\n",
+            &[("database.primary.host".to_string(), "db".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "server:\n    host: a\ndatabase:\n    primary:\n        host: db\n"
+        );
+    }
+
+    #[test]
+    fn test_set_keeps_trailing_comments_on_sequence_items() {
+        let original = "hosts:\n  - web   # primary\n  - db    # replica\nname: app\n";
+
+        let updated = set_values(
+            original,
+            &[("name".to_string(), "svc".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "hosts:\n  - web   # primary\n  - db    # replica\nname: svc\n"
+        );
+
+        let updated = set_values(
+            &updated,
+            &[("hosts[1]".to_string(), "cache".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "hosts:\n  - web   # primary\n  - cache    # replica\nname: svc\n"
+        );
+    }
+
+    #[test]
+    fn test_rename_keys_rewrites_key_tokens_at_every_level() {
+        let original = concat!(
+            "# service\n",
+            "serviceName: web # public\n",
+            "dbConfig:\n",
+            "  hostName: db\n",
+            "  replicaHosts: [{hostName: r1}]\n",
+            "plain: value with hostName inside\n",
+        );
+        let regex = Regex::new("([a-z])([A-Z])").unwrap();
+
+        let updated = rename_keys(original, &regex, "${1}_$2").unwrap();
+        assert_eq!(
+            updated,
+            concat!(
+                "# service\n",
+                "service_Name: web # public\n",
+                "db_Config:\n",
+                "  host_Name: db\n",
+                "  replica_Hosts: [{host_Name: r1}]\n",
+                "plain: value with hostName inside\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_rename_keys_rejects_collisions() {
+        let regex = Regex::new("^old_").unwrap();
+        let error = rename_keys("db:\n  old_host: a\n  host: b\n", &regex, "").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Renaming keys would merge 'db.old_host' and 'db.host' into 'host'"
+        );
+    }
+
+    #[test]
+    fn test_value_changes_ignore_key_order_and_keep_both_values() {
+        let before = "db:\n  host: a\n  port: 5432\nname: web\n";
+        let after = "name: web # renamed later\ndb: {port: 5432, host: b}\ntier: gold\n";
+
+        assert_eq!(
+            value_changes(before, after, false).unwrap(),
+            vec![
+                ValueChange {
+                    key: "db.host".to_string(),
+                    before: Some(Value::from("a")),
+                    after: Some(Value::from("b")),
+                },
+                ValueChange {
+                    key: "tier".to_string(),
+                    before: None,
+                    after: Some(Value::from("gold")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_null_out_values_keeps_keys_and_comments() {
+        let yaml = "# db\ndb:\n  password: hunter2 # rotate monthly\n  hosts:\n    - a\n    - b\nname: web\n";
+        let keys = [
+            "db.password".to_string(),
+            "db.hosts".to_string(),
+            "missing".to_string(),
+        ];
+
+        assert_eq!(
+            null_out_values(yaml, &keys).unwrap(),
+            "# db\ndb:\n  password: null # rotate monthly\n  hosts: null\nname: web\n"
+        );
+    }
+
+    #[test]
+    fn test_changed_keys_lists_added_removed_and_changed_leaves() {
+        let before = "name: web\nports: [80, 443]\ndb:\n  host: a\n  user: root\n";
+        let after = "name: web\nports: [80, 8443]\ndb:\n  host: b\nreplicas: 2\n";
+
+        assert_eq!(
+            changed_keys(before, after).unwrap(),
+            vec!["ports[1]", "db.host", "db.user", "replicas"]
+        );
+        assert!(changed_keys(before, before).unwrap().is_empty());
+        assert_eq!(changed_keys("", "z: 1\n").unwrap(), vec!["z"]);
+    }
+
+    #[test]
+    fn test_set_replaces_sequences_in_their_existing_style() {
+        let original = "hosts:\n  - web\n  - db\nports: [80, 443] # public\nname: app\n";
+        let updates = vec![
+            ("hosts".to_string(), "[db, cache]".to_string()),
+            ("ports".to_string(), "[443]".to_string()),
+            ("name".to_string(), "[a, b]".to_string()),
+        ];
+
+        let updated = set_values(original, &updates, &EditOptions::default()).unwrap();
+        assert_eq!(
+            updated,
+            "hosts:\n  - db\n  - cache\nports: [443] # public\nname: [a, b]\n"
+        );
+    }
+
+    #[test]
+    fn test_set_infers_scalar_types_unless_quoted() {
+        let updates: Vec<_> = [
+            ("flag", "true"),
+            ("port", "42"),
+            ("ratio", "3.14"),
+            ("empty", "null"),
+            ("quoted", "\"42\""),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let updated = set_values("name: app\n", &updates, &EditOptions::default()).unwrap();
+        let value: Value = serde_yaml::from_str(&updated).unwrap();
+
+        assert_eq!(value["flag"], Value::Bool(true));
+        assert_eq!(value["port"], Value::Number(42.into()));
+        assert_eq!(
+            value["ratio"],
+            serde_yaml::from_str::<Value>("3.14").unwrap()
+        );
+        assert_eq!(value["empty"], Value::Null);
+        assert_eq!(value["quoted"], Value::String("42".to_string()));
+    }
+
+    #[test]
+    fn test_string_literal_forces_string_values() {
+        let texts = ["true", "42", "3.14", "null"];
+        let updates: Vec<_> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| (format!("k{index}"), string_literal(text)))
+            .collect();
+
+        let updated = set_values("name: app\n", &updates, &EditOptions::default()).unwrap();
+        let value: Value = serde_yaml::from_str(&updated).unwrap();
+
+        for (index, text) in texts.iter().enumerate() {
+            assert_eq!(
+                value[format!("k{index}").as_str()],
+                Value::String(text.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_keeps_quote_style_of_replaced_strings() {
+        let original = "name: \"Alice\"\nnick: 'al'\nplain: v\nport: \"80\"\n";
+        let updates: Vec<_> = [
+            ("name", "Bob \"B\""),
+            ("nick", "it's"),
+            ("plain", "w"),
+            ("port", "81"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        assert_eq!(
+            set_values(original, &updates, &EditOptions::default()).unwrap(),
+            "name: \"Bob \\\"B\\\"\"\nnick: 'it''s'\nplain: w\nport: 81\n"
+        );
+    }
+
+    #[test]
+    fn test_set_leaves_version_like_sibling_untouched() {
+        let original = "version: 1.10\nname: app\n";
+        let updated = set_values(
+            original,
+            &[("name".to_string(), "api".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(updated, "version: 1.10\nname: api\n");
+    }
+
+    #[test]
+    fn test_preserve_numbers_as_strings_keeps_number_text() {
+        let original = "version: 1.10\nport: 8080\nmode: 0755\nhosts: [1.20, 3]\n";
+        assert_eq!(
+            parse_yaml_document(original, "").unwrap()["version"],
+            Value::from(1.1)
+        );
+
+        let document = read_yaml_document(original, "", true).unwrap();
+        assert_eq!(document["version"], Value::from("1.10"));
+        assert_eq!(document["port"], Value::from(8080));
+        assert_eq!(document["mode"], Value::from("0755"));
+        assert_eq!(document["hosts"][0], Value::from("1.20"));
+
+        let options = EditOptions {
+            preserve_numbers: true,
+            ..EditOptions::default()
+        };
+        let updated = set_values(
+            original,
+            &[("next".to_string(), "2.10".to_string())],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            read_yaml_document(&updated, "", true).unwrap()["next"],
+            Value::from("2.10")
+        );
+        assert!(updated.starts_with(original));
+    }
+
+    #[test]
+    fn test_preserve_numbers_as_strings_in_every_document() {
+        let contents = "version: 1.10\n---\nversion: 1.20\nport: 8080\n";
+
+        let documents = parse_input_documents(contents, InputFormat::Yaml, "", true).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0]["version"], Value::from("1.10"));
+        assert_eq!(documents[1]["version"], Value::from("1.20"));
+        assert_eq!(documents[1]["port"], Value::from(8080));
+
+        let documents = parse_input_documents(contents, InputFormat::Yaml, "", false).unwrap();
+        assert_eq!(documents[1]["version"], Value::from(1.2));
+    }
+
+    #[test]
+    fn test_set_keeps_keys_that_need_quoting() {
+        let original = "\"true\": old\n'k: v': x # note\nother: 1\n";
+        let updates = vec![
+            ("true".to_string(), "new".to_string()),
+            ("k: v".to_string(), "y".to_string()),
+        ];
+
+        let updated = set_values(original, &updates, &EditOptions::default()).unwrap();
+        assert_eq!(updated, "\"true\": new\n'k: v': y # note\nother: 1\n");
+
+        let updates = vec![
+            ("flags.true".to_string(), "on".to_string()),
+            ("flags.123".to_string(), "n".to_string()),
+            ("flags.a: b".to_string(), "c".to_string()),
+        ];
+        let updated = set_values("name: app\n", &updates, &EditOptions::default()).unwrap();
+        let flags = parse_yaml(&updated)["flags"].clone();
+        assert_eq!(flags["true"], Value::String("on".to_string()));
+        assert_eq!(flags["123"], Value::String("n".to_string()));
+        assert_eq!(flags["a: b"], Value::String("c".to_string()));
+    }
+
+    #[test]
+    fn test_set_adds_top_level_key_before_trailing_comment_block() {
+        let original = "name: web\nreplicas: 1\n\n# trailing notes\n# more\n";
+        let updates = vec![("image".to_string(), "nginx".to_string())];
+
+        let updated = set_values(original, &updates, &EditOptions::default()).unwrap();
+        assert_eq!(
+            updated,
+            "name: web\nreplicas: 1\nimage: nginx\n\n# trailing notes\n# more\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_comments_removes_standalone_and_trailing_comments() {
+        let original = concat!(
+            "# header\n",
+            "name: web # public name\n",
+            "\n",
+            "database:\n",
+            "  # primary only\n",
+            "  host: db   # internal\n",
+            "  url: \"http://x/#anchor\" # quoted\n",
+            "  tag: a#b\n",
+            "script: |\n",
+            "  # kept, part of the scalar\n",
+            "  run\n",
+        );
+
+        assert_eq!(
+            strip_comments(original).unwrap(),
+            concat!(
+                "name: web\n",
+                "\n",
+                "database:\n",
+                "  host: db\n",
+                "  url: \"http://x/#anchor\"\n",
+                "  tag: a#b\n",
+                "script: |\n",
+                "  # kept, part of the scalar\n",
+                "  run\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_with_comment_prefix_preserves_custom_comment_lines() {
+        let original = "// generated\ndatabase:\n  // primary only\n  host: db\nreplicas: 1\n";
+        let updates = vec![
+            ("database.host".to_string(), "db2".to_string()),
+            ("replicas".to_string(), "3".to_string()),
+        ];
+
+        let updated = with_comment_prefix(original, "//", |contents| {
+            set_values(contents, &updates, &EditOptions::default())
+        })
+        .unwrap();
+        assert_eq!(
+            updated,
+            "// generated\ndatabase:\n  // primary only\n  host: db2\nreplicas: 3\n"
+        );
+        assert!(set_values(original, &updates, &EditOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_classify_changes_reports_created_changed_and_unchanged_keys() {
+        let original = "name: web\nreplicas: 2\n";
+        let updates = vec![
+            ("name".to_string(), "api".to_string()),
+            ("replicas".to_string(), "2".to_string()),
+            ("image.tag".to_string(), "v1".to_string()),
+        ];
+        let updated = set_values(original, &updates, &EditOptions::default()).unwrap();
+        let keys: Vec<_> = updates.into_iter().map(|(key, _)| key).collect();
+
+        assert_eq!(
+            classify_changes(original, &updated, &keys, false).unwrap(),
+            vec![
+                ("name".to_string(), KeyChange::Changed),
+                ("replicas".to_string(), KeyChange::Unchanged),
+                ("image.tag".to_string(), KeyChange::Created),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_append_document_adds_new_document_after_separator() {
+        let original = "# deployment\nkind: Deployment\nmetadata:\n  name: web\n";
+        let updates = vec![
+            ("kind".to_string(), "Service".to_string()),
+            ("service.name".to_string(), "web".to_string()),
+        ];
+
+        let document =
+            new_document(|seed| set_values(seed, &updates, &EditOptions::default())).unwrap();
+        assert_eq!(document, "kind: Service\nservice:\n  name: web\n");
+
+        let appended = append_document(original, &document);
+        assert_eq!(
+            appended,
+            format!("{original}---\nkind: Service\nservice:\n  name: web\n")
+        );
+        assert_eq!(append_document("a: 1", "b: 2\n"), "a: 1\n---\nb: 2\n");
+        assert_eq!(append_document("", "b: 2\n"), "b: 2\n");
+        assert!(new_document(|seed| Ok(seed.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_deep_merge_overrides_and_adds_keys_from_json() {
+        let original = "# db\ndatabase:\n  host: localhost # primary\n  port: 5432\nname: app\n";
+        let overlay: Value = serde_json::from_str(
+            r#"{"database": {"host": "db.internal", "pool": {"size": 5}}, "debug": true}"#,
+        )
+        .unwrap();
+
+        let updated = deep_merge(
+            original,
+            &overlay,
+            &MergeStrategy::default(),
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            concat!(
+                "# db\ndatabase:\n  host: db.internal # primary\n  port: 5432\n",
+                "  pool:\n    size: 5\nname: app\ndebug: true\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_deep_merge_replaces_non_mapping_values() {
+        let overlay: Value = serde_json::from_str(r#"{"ports": {"http": 80}}"#).unwrap();
+        let strategy = MergeStrategy::default();
+        let updated = deep_merge(
+            "ports: 80\nname: app\n",
+            &overlay,
+            &strategy,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(parse_yaml(&updated)["ports"]["http"].as_i64(), Some(80));
+        assert!(deep_merge(
+            "a: 1\n",
+            &Value::Bool(true),
+            &strategy,
+            &EditOptions::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_deep_merge_scalar_conflict_strategies() {
+        let original = "db:\n  host: localhost # primary\n  port: 5432\nname: app\n";
+        let overlay: Value =
+            serde_json::from_str(r#"{"db": {"host": "db.internal", "user": "ops"}}"#).unwrap();
+        let merge = |spec: &str| {
+            deep_merge(
+                original,
+                &overlay,
+                &MergeStrategy::parse(spec)?,
+                &EditOptions::default(),
+            )
+        };
+
+        assert_eq!(
+            merge("overlay-wins").unwrap(),
+            "db:\n  host: db.internal # primary\n  port: 5432\n  user: ops\nname: app\n"
+        );
+        assert_eq!(
+            merge("base-wins").unwrap(),
+            "db:\n  host: localhost # primary\n  port: 5432\n  user: ops\nname: app\n"
+        );
+        assert!(merge("error-on-conflict")
+            .unwrap_err()
+            .to_string()
+            .contains("Conflicting values at 'db.host'"));
+
+        let same: Value = serde_json::from_str(r#"{"db": {"port": 5432}}"#).unwrap();
+        let strategy = MergeStrategy::parse("error-on-conflict").unwrap();
+        assert_eq!(
+            deep_merge(original, &same, &strategy, &EditOptions::default()).unwrap(),
+            original
+        );
+    }
+
+    #[test]
+    fn test_deep_merge_sequence_strategies() {
+        let original = "hosts:\n  - web\n  - db\nports: [80, 443]\n";
+        let overlay: Value =
+            serde_json::from_str(r#"{"hosts": ["db", "cache"], "ports": [443, 8080]}"#).unwrap();
+        let merge = |spec: &str| {
+            deep_merge(
+                original,
+                &overlay,
+                &MergeStrategy::parse(spec)?,
+                &EditOptions::default(),
+            )
+        };
 
-        let updated = set_values(yaml_str, &updates).unwrap();
-        let parsed = parse_yaml(&updated);
-        assert_eq!(parsed["database"]["host"].as_str(), Some("localhost"));
-        assert_eq!(parsed["database"]["port"].as_i64(), Some(3306));
-        assert_eq!(parsed["database"]["username"].as_str(), Some("admin"));
         assert_eq!(
-            parsed["app"]["server"]["config"]["timeout"].as_i64(),
-            Some(30)
+            parse_yaml(&merge("replace").unwrap()),
+            parse_yaml("hosts: [db, cache]\nports: [443, 8080]\n")
         );
-
-        let removed = unset_values(
-            &updated,
-            &[
-                "database.port".to_string(),
-                "app.server.config.timeout".to_string(),
-            ],
-        )
-        .unwrap();
-        let parsed = parse_yaml(&removed);
-        assert!(get_value(&parsed, "database.port").unwrap().is_none());
-        assert!(get_value(&parsed, "app.server.config.timeout")
-            .unwrap()
-            .is_none());
+        assert_eq!(
+            merge("append").unwrap(),
+            "hosts:\n  - web\n  - db\n  - db\n  - cache\nports: [80, 443, 443, 8080]\n"
+        );
+        assert_eq!(
+            merge("union").unwrap(),
+            "hosts:\n  - web\n  - db\n  - cache\nports: [80, 443, 8080]\n"
+        );
+        assert!(merge("replace,error-on-conflict").is_err());
+        assert!(merge("zip").is_err());
     }
 
     #[test]
@@ -729,6 +4062,7 @@ mod tests {
                 r"metadata.kubernetes\.io/hostname".to_string(),
                 "node-a".to_string(),
             )],
+            &EditOptions::default(),
         )
         .unwrap();
         let parsed = parse_yaml(&updated);
@@ -743,6 +4077,7 @@ mod tests {
         let updated = set_values(
             "items:\n  - first\n",
             &[("items[1]".to_string(), "second".to_string())],
+            &EditOptions::default(),
         )
         .unwrap();
         let parsed = parse_yaml(&updated);
@@ -756,6 +4091,190 @@ mod tests {
         assert_eq!(value.unwrap().as_str(), Some("node-a"));
     }
 
+    #[test]
+    fn test_numeric_segments_index_sequences_across_operations() {
+        let original = "servers: [{host: a}, {host: b}]\nports:\n  \"0\": zero\n";
+        let yaml = parse_yaml(original);
+        let host = get_value(&yaml, "servers.1.host").unwrap();
+        assert_eq!(host.unwrap().as_str(), Some("b"));
+        assert_eq!(
+            get_value(&yaml, "ports.0").unwrap().unwrap().as_str(),
+            Some("zero")
+        );
+
+        let updated = set_values(
+            original,
+            &[("servers.1.host".to_string(), "c".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "servers: [{host: a}, { host: c }]\nports:\n  \"0\": zero\n"
+        );
+
+        let updated = unset_values(original, &["servers.0.host".to_string()]).unwrap();
+        assert_eq!(updated, "servers: [{}, {host: b}]\nports:\n  \"0\": zero\n");
+
+        let updated = move_in_document(
+            original,
+            &key_pairs(&[("servers.0", "first")]),
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "servers: [{host: b}]\nports:\n  \"0\": zero\nfirst:\n  host: a\n"
+        );
+    }
+
+    #[test]
+    fn test_sequence_indices_past_the_end_are_out_of_bounds() {
+        let original = "servers:\n  - host: a\n  - host: b\n";
+        let expected = "Index 5 is out of bounds for 'servers' (length 2)";
+
+        let error = get_value(&parse_yaml(original), "servers[5].host").unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        let error = set_values(
+            original,
+            &[("servers.5.host".to_string(), "c".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), expected);
+        let error = unset_values(original, &["servers[5]".to_string()]).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+
+        assert!(get_value(&parse_yaml(original), "servers[2]")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_unset_removes_single_flow_entries() {
+        let original = "p: [1, 2, 3] # c\nm: {a: 1, b: {c: 2}}\nx: 1\n";
+        let unset = |key: &str| unset_values(original, &[key.to_string()]).unwrap();
+
+        assert_eq!(unset("p[0]"), "p: [2, 3] # c\nm: {a: 1, b: {c: 2}}\nx: 1\n");
+        assert_eq!(unset("p[2]"), "p: [1, 2] # c\nm: {a: 1, b: {c: 2}}\nx: 1\n");
+        assert_eq!(unset("m.a"), "p: [1, 2, 3] # c\nm: {b: {c: 2}}\nx: 1\n");
+        assert_eq!(unset("m.b.c"), "p: [1, 2, 3] # c\nm: {a: 1, b: {}}\nx: 1\n");
+    }
+
+    #[test]
+    fn test_prune_removes_emptied_parents_up_to_a_non_empty_one() {
+        let original = "app:\n  name: x\n  db:\n    auth:\n      password: p\nm: {a: {b: 1}}\nsolo:\n  only: 1\n";
+        let prune = |key: &str| prune_values(original, &[key.to_string()]).unwrap();
+
+        assert_eq!(
+            prune("app.db.auth.password"),
+            "app:\n  name: x\nm: {a: {b: 1}}\nsolo:\n  only: 1\n"
+        );
+        assert_eq!(
+            prune("m.a.b"),
+            "app:\n  name: x\n  db:\n    auth:\n      password: p\nsolo:\n  only: 1\n"
+        );
+        assert_eq!(
+            prune_values("solo:\n  only: 1\n", &["solo.only".to_string()]).unwrap(),
+            "\n"
+        );
+    }
+
+    #[test]
+    fn test_prune_keeps_an_emptied_sequence() {
+        let original = "a:\n  - x\nb: 1\n";
+        let pruned = prune_values(original, &["a.0".to_string()]).unwrap();
+        let document = parse_yaml_document(&pruned, "").unwrap();
+
+        assert!(document.get("a").is_some());
+        assert_eq!(document["b"], Value::from(1));
+
+        let nested = "m:\n  list:\n    - x\n";
+        let pruned = prune_values(nested, &["m.list[0]".to_string()]).unwrap();
+        assert!(parse_yaml_document(&pruned, "").unwrap()["m"]
+            .get("list")
+            .is_some());
+    }
+
+    #[test]
+    fn test_tabs_in_block_scalars_do_not_shift_edits() {
+        let original = "a:\n  script: |\n    echo\t\"x\"\n    \tindented\n  b: 1\nc: 2\n";
+        let updated = set_values(
+            original,
+            &[
+                ("a.b".to_string(), "5".to_string()),
+                ("a.x.y".to_string(), "1".to_string()),
+            ],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "a:\n  script: |\n    echo\t\"x\"\n    \tindented\n  b: 5\n  x:\n    y: 1\nc: 2\n"
+        );
+
+        let error = set_values(
+            "a:\n\tb: 1\n",
+            &[("a.b".to_string(), "2".to_string())],
+            &EditOptions::default(),
+        );
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("at line 2 column 1"));
+    }
+
+    #[test]
+    fn test_set_get_and_unset_on_files_without_a_document() {
+        let set = |contents: &str| {
+            set_values(
+                contents,
+                &[("db.host".to_string(), "x".to_string())],
+                &EditOptions::default(),
+            )
+            .unwrap()
+        };
+        assert_eq!(set(""), "db:\n  host: x\n");
+        assert_eq!(set("# settings\n\n"), "# settings\n\ndb:\n  host: x\n");
+        assert_eq!(set("# settings"), "# settings\ndb:\n  host: x\n");
+        assert_eq!(set("---\n"), "---\ndb:\n  host: x\n");
+
+        let empty = parse_input("", InputFormat::Yaml, "", false).unwrap();
+        assert_eq!(get_value(&empty, "db.host").unwrap(), None);
+        assert!(
+            super::grep(&empty, &Regex::new(".").unwrap(), &MatchOptions::default()).is_empty()
+        );
+        assert_eq!(unset_values("", &["db".to_string()]).unwrap(), "");
+        assert_eq!(unset_values("# c\n", &["db".to_string()]).unwrap(), "# c\n");
+    }
+
+    #[test]
+    fn test_set_parses_structured_values_and_reports_invalid_ones() {
+        let updated = set_values(
+            "name: app\n",
+            &[
+                ("tags".to_string(), "[a, b, c]".to_string()),
+                ("db".to_string(), "{host: x, port: 1}".to_string()),
+            ],
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "name: app\ntags: [a, b, c]\ndb:\n  host: x\n  port: 1\n"
+        );
+
+        let error = set_values(
+            "name: app\n",
+            &[("tags".to_string(), "[a, b".to_string())],
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Failed to parse YAML from value '[a, b'"));
+    }
+
     #[test]
     fn test_set_mapping_may_rewrite_touched_key_but_preserves_untouched_layout() {
         let original = concat!(
@@ -778,6 +4297,7 @@ mod tests {
                 String::from("app"),
                 String::from("debug: false\nlogging:\n  level: info\n  format: json"),
             )],
+            &EditOptions::default(),
         )
         .unwrap();
 
@@ -792,7 +4312,13 @@ mod tests {
     #[test]
     fn test_copy_in_document_and_move_in_document() {
         let original = "source:\n  nested:\n    key: value\nkeep: yes\n";
-        let copied = copy_in_document(original, "source.nested", "dest.nested").unwrap();
+        let copied = copy_in_document(
+            original,
+            "source.nested",
+            "dest.nested",
+            &EditOptions::default(),
+        )
+        .unwrap();
         let copied_yaml = parse_yaml(&copied);
         assert_eq!(copied_yaml["dest"]["nested"]["key"].as_str(), Some("value"));
         assert_eq!(
@@ -800,7 +4326,12 @@ mod tests {
             Some("value")
         );
 
-        let moved = move_in_document(original, "source.nested", "dest.nested").unwrap();
+        let moved = move_in_document(
+            original,
+            &key_pairs(&[("source.nested", "dest.nested")]),
+            &EditOptions::default(),
+        )
+        .unwrap();
         let moved_yaml = parse_yaml(&moved);
         assert!(get_value(&moved_yaml, "source.nested").unwrap().is_none());
         assert_eq!(moved_yaml["dest"]["nested"]["key"].as_str(), Some("value"));
@@ -819,8 +4350,23 @@ mod tests {
         .unwrap();
         fs::write(&dest_file, "other: value\n").unwrap();
 
-        copy_value(&source_file, "data.value", &dest_file, "copied.value").unwrap();
-        copy_value(&source_file, "config.nested", &dest_file, "backup.config").unwrap();
+        let options = TransferOptions::default();
+        copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("data.value", "copied.value")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("config.nested", "backup.config")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
 
         let yaml = serde_yaml::from_str::<Value>(&fs::read_to_string(&dest_file).unwrap()).unwrap();
         assert_eq!(yaml["other"].as_str(), Some("value"));
@@ -830,6 +4376,166 @@ mod tests {
         fs::remove_dir_all(test_dir).unwrap();
     }
 
+    #[test]
+    fn test_copy_value_merge_layers_section_into_existing_mapping() {
+        let test_dir = temp_test_dir("test_copy_value_merge");
+        let source_file = format!("{test_dir}/source.yaml");
+        let dest_file = format!("{test_dir}/dest.yaml");
+        fs::write(
+            &source_file,
+            "overrides:\n  timeout: 30\n  tls:\n    enabled: true\nname: x\n",
+        )
+        .unwrap();
+        fs::write(
+            &dest_file,
+            "server:\n  # seconds\n  timeout: 10\n  port: 80\n  tls:\n    cert: a.pem\n",
+        )
+        .unwrap();
+        let options = TransferOptions {
+            merge: true,
+            ..TransferOptions::default()
+        };
+
+        copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("overrides", "server")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&dest_file).unwrap(),
+            "server:\n  # seconds\n  timeout: 30\n  port: 80\n  tls:\n    cert: a.pem\n    enabled: true\n"
+        );
+
+        let error = copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("name", "server")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("'name'"));
+        let error = copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("overrides", "server.port")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("'server.port'"));
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_value_with_anchor_emits_alias_in_same_file() {
+        let test_dir = temp_test_dir("test_copy_value_with_anchor");
+        let file = format!("{test_dir}/config.yaml");
+        fs::write(&file, "defaults: &defaults\n  retries: 3\nname: app\n").unwrap();
+
+        let options = TransferOptions {
+            with_anchor: true,
+            ..TransferOptions::default()
+        };
+        copy_values(
+            &file,
+            &file,
+            &key_pairs(&[("defaults", "service.settings")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+
+        let contents = fs::read_to_string(&file).unwrap();
+        assert!(contents.starts_with("defaults: &defaults\n  retries: 3\n"));
+        assert!(contents.contains("settings: *defaults"));
+        let yaml = parse_yaml(&contents);
+        assert_eq!(yaml["service"]["settings"]["retries"].as_i64(), Some(3));
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_value_with_anchor_redeclares_anchor_across_files() {
+        let test_dir = temp_test_dir("test_copy_value_with_anchor_files");
+        let source_file = format!("{test_dir}/source.yaml");
+        let dest_file = format!("{test_dir}/dest.yaml");
+        fs::write(&source_file, "image: &image nginx:1.25\n").unwrap();
+        fs::write(&dest_file, "name: app\n").unwrap();
+
+        let options = TransferOptions {
+            with_anchor: true,
+            ..TransferOptions::default()
+        };
+        copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("image", "image")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+
+        let contents = fs::read_to_string(&dest_file).unwrap();
+        assert_eq!(contents, "name: app\nimage: &image nginx:1.25\n");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_value_with_anchor_into_missing_file() {
+        let test_dir = temp_test_dir("test_copy_value_with_anchor_missing");
+        let source_file = format!("{test_dir}/source.yaml");
+        let dest_file = format!("{test_dir}/new.yaml");
+        fs::write(&source_file, "base: &anch\n  a: 1\nuse: *anch\n").unwrap();
+
+        let options = TransferOptions {
+            with_anchor: true,
+            ..TransferOptions::default()
+        };
+        copy_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("base", "z")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+
+        let contents = fs::read_to_string(&dest_file).unwrap();
+        assert_eq!(contents, "z: &anch\n  a: 1\n");
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_value_with_anchor_rejects_alias_before_anchor() {
+        let test_dir = temp_test_dir("test_copy_value_alias_order");
+        let file = format!("{test_dir}/config.yaml");
+        fs::write(&file, "target: old\nsource: &src value\n").unwrap();
+
+        let options = TransferOptions {
+            with_anchor: true,
+            force: true,
+            ..TransferOptions::default()
+        };
+        let error = copy_values(
+            &file,
+            &file,
+            &key_pairs(&[("source", "target")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("would precede its anchor"));
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
     #[test]
     fn test_move_value_updates_destination_and_removes_source() {
         let test_dir = temp_test_dir("test_move_value");
@@ -843,7 +4549,14 @@ mod tests {
         .unwrap();
         fs::write(&dest_file, "other: data\n").unwrap();
 
-        move_value(&source_file, "source.nested", &dest_file, "dest.nested").unwrap();
+        move_values(
+            &source_file,
+            &dest_file,
+            &key_pairs(&[("source.nested", "dest.nested")]),
+            &TransferOptions::default(),
+            &EditOptions::default(),
+        )
+        .unwrap();
 
         let dest_yaml =
             serde_yaml::from_str::<Value>(&fs::read_to_string(&dest_file).unwrap()).unwrap();
@@ -859,6 +4572,178 @@ mod tests {
         fs::remove_dir_all(test_dir).unwrap();
     }
 
+    #[test]
+    fn test_move_values_batches_keys_across_files() {
+        let test_dir = temp_test_dir("test_move_values_batch");
+        let source_file = format!("{test_dir}/source.yaml");
+        let dest_file = format!("{test_dir}/dest.yaml");
+        fs::write(&source_file, "# source\na: 1\nb: 2\nc: 3\n").unwrap();
+        fs::write(&dest_file, "# dest\nz: 0\n").unwrap();
+
+        let keys = key_pairs(&[("a", "moved.a"), ("c", "moved.c")]);
+        move_values(
+            &source_file,
+            &dest_file,
+            &keys,
+            &TransferOptions::default(),
+            &EditOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&source_file).unwrap(),
+            "# source\nb: 2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&dest_file).unwrap(),
+            "# dest\nz: 0\nmoved:\n  a: 1\n  c: 3\n"
+        );
+
+        let missing = key_pairs(&[("b", "b"), ("nope", "nope")]);
+        let error = move_values(
+            &source_file,
+            &dest_file,
+            &missing,
+            &TransferOptions::default(),
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Key 'nope' not found"));
+        assert_eq!(
+            fs::read_to_string(&source_file).unwrap(),
+            "# source\nb: 2\n"
+        );
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_move_in_document_swaps_keys_in_one_pass() {
+        let keys = key_pairs(&[("a", "b"), ("c", "d")]);
+        let moved =
+            move_in_document("a: 1\nc: 2\nkeep: 3\n", &keys, &EditOptions::default()).unwrap();
+        assert_eq!(moved, "keep: 3\nb: 1\nd: 2\n");
+    }
+
+    #[test]
+    fn test_copy_values_refuses_existing_destination_without_force() {
+        let test_dir = temp_test_dir("test_copy_values_force");
+        let source_file = format!("{test_dir}/staging.yaml");
+        let dest_file = format!("{test_dir}/prod.yaml");
+        fs::write(&source_file, "db:\n  password: new\n").unwrap();
+        fs::write(&dest_file, "db:\n  password: old # rotated\n").unwrap();
+        let keys = key_pairs(&[("db.password", "db.password")]);
+
+        let error = copy_values(
+            &source_file,
+            &dest_file,
+            &keys,
+            &TransferOptions::default(),
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Key 'db.password' already exists in '{dest_file}' (use --force to overwrite it)"
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(&dest_file).unwrap(),
+            "db:\n  password: old # rotated\n"
+        );
+
+        let options = TransferOptions {
+            force: true,
+            ..TransferOptions::default()
+        };
+        copy_values(
+            &source_file,
+            &dest_file,
+            &keys,
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&dest_file).unwrap(),
+            "db:\n  password: new # rotated\n"
+        );
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_copy_value_same_type_only_accepts_matching_types() {
+        let test_dir = temp_test_dir("test_copy_value_same_type");
+        let file = format!("{test_dir}/config.yaml");
+        fs::write(&file, "source:\n  a: 1\ntarget:\n  b: 2\nport: 80\n").unwrap();
+
+        let options = TransferOptions {
+            same_type_only: true,
+            ..TransferOptions::default()
+        };
+        copy_values(
+            &file,
+            &file,
+            &key_pairs(&[("source", "target")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+        copy_values(
+            &file,
+            &file,
+            &key_pairs(&[("port", "new_port")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap();
+
+        let yaml = parse_yaml(&fs::read_to_string(&file).unwrap());
+        assert_eq!(yaml["target"]["a"].as_i64(), Some(1));
+        assert_eq!(yaml["new_port"].as_i64(), Some(80));
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_same_type_only_rejects_mismatching_types() {
+        let test_dir = temp_test_dir("test_same_type_mismatch");
+        let file = format!("{test_dir}/config.yaml");
+        let original = "database:\n  host: db\nport: 80\n";
+        fs::write(&file, original).unwrap();
+
+        let options = TransferOptions {
+            same_type_only: true,
+            ..TransferOptions::default()
+        };
+        let error = copy_values(
+            &file,
+            &file,
+            &key_pairs(&[("port", "database")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Refusing to overwrite mapping at 'database' in '{file}' with int")
+        );
+        let error = move_values(
+            &file,
+            &file,
+            &key_pairs(&[("port", "database")]),
+            &options,
+            &EditOptions::default(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Refusing to overwrite mapping"));
+        assert_eq!(fs::read_to_string(&file).unwrap(), original);
+
+        fs::remove_dir_all(test_dir).unwrap();
+    }
+
     #[test]
     fn test_format_string_value() {
         let value = Value::String("hello".to_string());